@@ -0,0 +1,65 @@
+#[path = "src/shader_compiler.rs"]
+mod shader_compiler;
+
+use std::{env, fs, path::PathBuf};
+
+fn main() {
+    println!("cargo:rerun-if-changed=shaders");
+    println!(
+        "cargo:rerun-if-env-changed={}",
+        shader_compiler::DEFINES_VAR
+    );
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR is set by cargo"));
+
+    let mut shaders: Vec<PathBuf> = fs::read_dir("shaders")
+        .expect("Couldnt read the shaders directory")
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "glsl"))
+        .collect();
+    shaders.sort();
+    //Only the top level is compiled, subdirectories hold includes
+
+    let defines = env::var(shader_compiler::DEFINES_VAR).unwrap_or_default();
+    let defines = shader_compiler::parse_defines(&defines).unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    });
+
+    let mut failed = false;
+    for shader in &shaders {
+        let Some(stage) = shader_compiler::stage_of(shader) else {
+            println!(
+                "cargo:warning=Skipping {}, its name doesnt say which stage it is",
+                shader.display()
+            );
+            continue;
+        };
+        let file_name = shader.file_name().and_then(|name| name.to_str());
+        let defines = shader_compiler::defines_for(&defines, file_name.unwrap_or_default());
+
+        match shader_compiler::compile(shader, stage, &defines) {
+            Ok(compiled) => {
+                for dependency in &compiled.dependencies {
+                    println!("cargo:rerun-if-changed={}", dependency.display());
+                }
+                let bytes: Vec<u8> = compiled
+                    .words
+                    .iter()
+                    .flat_map(|word| word.to_le_bytes())
+                    .collect();
+                let output = out_dir.join(shader.with_extension("spv").file_name().unwrap());
+                fs::write(&output, bytes).expect("Couldnt write SPIR-V to OUT_DIR");
+            }
+            Err(err) => {
+                eprintln!("error: {}", err);
+                println!("cargo:warning={}", err);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+//Every shader is compiled before failing, so all errors show up at once
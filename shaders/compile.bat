@@ -1,2 +0,0 @@
-glslc.exe -fshader-stage=vert vertex.glsl -o vertex.spv
-glslc.exe -fshader-stage=frag fragment.glsl -o fragment.spv
\ No newline at end of file
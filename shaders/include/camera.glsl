@@ -0,0 +1,3 @@
+layout(push_constant) uniform Camera {
+    mat4 view_projection;
+} camera;
@@ -0,0 +1,13 @@
+#version 460
+
+layout(location = 0) in vec3 color;
+layout(location = 0) out vec4 outColor;
+
+layout(set = 0, binding = 0) uniform Material {
+    vec3 tint;
+    float brightness;
+} material;
+
+void main() {
+    outColor = vec4(color * material.tint * material.brightness, 1.0);
+}
@@ -1,11 +1,13 @@
 #version 460
 
-layout(location = 0) in vec2 positions;
+layout(location = 0) in vec3 positions;
 layout(location = 1) in vec3 colors;
 
 layout(location = 0) out vec3 color;
 
+#include "include/camera.glsl"
+
 void main(){
     color = colors;
-    gl_Position = vec4(positions, 0.0, 1.0);
+    gl_Position = camera.view_projection * vec4(positions, 1.0);
 }
\ No newline at end of file
@@ -0,0 +1,178 @@
+use crate::{
+    builder::RendererBuilder,
+    error::{LyeError, LyeResult},
+    frame::DrawContext,
+    setup::Renderer,
+};
+use ash::vk;
+use winit::{
+    application::ApplicationHandler,
+    event::{DeviceEvent, DeviceId, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    window::WindowId,
+};
+
+/// The user side of [`App`], every method but [`Application::init`] has a default.
+///
+/// Closing, resizing and presenting are handled by [`App`], these hooks only add to it.
+pub trait Application {
+    /// Options used to create the renderer when the event loop resumes.
+    fn renderer_builder(&self) -> RendererBuilder {
+        RendererBuilder::default()
+    }
+
+    /// Called once the renderer exists, upload meshes and set the camera here.
+    fn init(&mut self, renderer: &mut Renderer) -> LyeResult<()>;
+
+    /// Called for every window event before [`App`] reacts to it.
+    fn window_event(&mut self, _renderer: &mut Renderer, _event: &WindowEvent) {}
+
+    /// Called for every device event, raw mouse motion arrives here.
+    fn device_event(&mut self, _renderer: &mut Renderer, _event: &DeviceEvent) {}
+
+    /// Called before every frame with the seconds since the last one, clamped to 0.1.
+    fn update(&mut self, _renderer: &mut Renderer, _dt: f32) {}
+
+    /// Called while the frame is recorded, after the renderers own meshes were drawn.
+    fn draw(&mut self, _context: &mut DrawContext) {}
+}
+
+/// Drives an [`Application`] with a winit event loop and a windowed [`Renderer`].
+pub struct App<A: Application> {
+    application: A,
+    renderer: Option<Renderer>,
+    error: Option<LyeError>,
+    last_draw_error: Option<String>,
+    last_frame: Option<std::time::Instant>,
+}
+
+impl<A: Application> App<A> {
+    /// Wraps an application, the window and renderer are created once the event loop resumes.
+    pub fn new(application: A) -> Self {
+        Self {
+            application,
+            renderer: None,
+            error: None,
+            last_draw_error: None,
+            last_frame: None,
+        }
+    }
+
+    /// Set when the event loop was exited because of an unrecoverable error.
+    pub fn error(&self) -> Option<&LyeError> {
+        self.error.as_ref()
+    }
+
+    /// The wrapped application.
+    pub fn application(&self) -> &A {
+        &self.application
+    }
+
+    /// Unwraps the application, after the event loop is done with it.
+    pub fn into_application(self) -> A {
+        self.application
+    }
+}
+
+impl<A: Application> ApplicationHandler for App<A> {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.renderer.is_none() && self.error.is_none() {
+            let renderer = self
+                .application
+                .renderer_builder()
+                .build(event_loop)
+                .and_then(|mut renderer| {
+                    self.application.init(&mut renderer)?;
+                    Ok(renderer)
+                });
+            match renderer {
+                Ok(renderer) => self.renderer = Some(renderer),
+                Err(err) => {
+                    log::error!("Error occured while creating Renderer : {}", err);
+                    self.error = Some(err);
+                    event_loop.exit();
+                }
+            }
+        }
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        _window_id: WindowId,
+        event: WindowEvent,
+    ) {
+        let Some(renderer) = self.renderer.as_mut() else {
+            return;
+        };
+        self.application.window_event(renderer, &event);
+
+        match event {
+            WindowEvent::CloseRequested => {
+                let _ = renderer.wait_idle();
+                event_loop.exit();
+            }
+            WindowEvent::Resized(_) => renderer.mark_swapchain_outdated(),
+            WindowEvent::RedrawRequested => {
+                let now = std::time::Instant::now();
+                let dt = self
+                    .last_frame
+                    .replace(now)
+                    .map_or(0.0, |last| (now - last).as_secs_f32().min(0.1));
+                //Clamped so a stall doesnt teleport the camera
+
+                self.application.update(renderer, dt);
+                let application = &mut self.application;
+                match renderer.draw_with(|context| application.draw(context)) {
+                    Ok(()) => self.last_draw_error = None,
+                    Err(err) => {
+                        let message = err.to_string();
+                        if self.last_draw_error.as_ref() != Some(&message) {
+                            log::error!("Error while drawing : {}", message);
+                            self.last_draw_error = Some(message);
+                        }
+                        if err.result() == Some(vk::Result::ERROR_DEVICE_LOST) {
+                            self.error = Some(err);
+                            event_loop.exit();
+                        }
+                    }
+                }
+                //An error repeating every frame is logged once, until a frame draws again
+            }
+            _ => (),
+        }
+    }
+
+    fn device_event(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+        _device_id: DeviceId,
+        event: DeviceEvent,
+    ) {
+        if let Some(renderer) = self.renderer.as_mut() {
+            self.application.device_event(renderer, &event);
+        }
+    }
+
+    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
+        if let Some(window) = self.renderer.as_ref().and_then(Renderer::window) {
+            window.request_redraw();
+        }
+    }
+}
+
+/// Runs `application` on a new polling event loop until its window is closed.
+///
+/// Returns the application so its state can be inspected afterwards.
+pub fn run<A: Application>(application: A) -> LyeResult<A> {
+    let event_loop = EventLoop::new()?;
+    event_loop.set_control_flow(ControlFlow::Poll);
+
+    let mut app = App::new(application);
+    event_loop.run_app(&mut app)?;
+
+    match app.error.take() {
+        Some(err) => Err(err),
+        None => Ok(app.into_application()),
+    }
+}
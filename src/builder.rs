@@ -0,0 +1,261 @@
+use crate::{
+    depth::DepthTest, device::DeviceSelection, error::LyeResult, frame::DEFAULT_FRAMES_IN_FLIGHT,
+    pipeline::GraphicsPipelineBuilder, present::PresentPreference, setup::Renderer,
+};
+use ash::vk;
+use std::{ffi::CStr, path::PathBuf};
+use winit::{event_loop::ActiveEventLoop, window::WindowAttributes};
+
+/// Configures a [`Renderer`] before it is created.
+///
+/// Every option has a default, so `RendererBuilder::new().build(event_loop)` is a working window
+/// renderer.
+#[derive(Clone, Debug)]
+pub struct RendererBuilder {
+    pub(crate) window_attributes: WindowAttributes,
+    pub(crate) application_name: String,
+    pub(crate) application_version: (u32, u32, u32),
+    pub(crate) engine_name: String,
+    pub(crate) engine_version: (u32, u32, u32),
+    pub(crate) debug_messenger: bool,
+    pub(crate) validation_layer: bool,
+    pub(crate) min_severity: log::Level,
+    pub(crate) strict_validation: bool,
+    pub(crate) instance_extensions: Vec<&'static CStr>,
+    pub(crate) device_selection: Option<DeviceSelection>,
+    pub(crate) device_extensions: Vec<&'static CStr>,
+    pub(crate) optional_device_extensions: Vec<&'static CStr>,
+    pub(crate) features: vk::PhysicalDeviceFeatures,
+    pub(crate) optional_features: vk::PhysicalDeviceFeatures,
+    pub(crate) min_image_count: u32,
+    pub(crate) present_preference: PresentPreference,
+    pub(crate) clear_color: [f32; 4],
+    pub(crate) frames_in_flight: usize,
+    pub(crate) pipeline: GraphicsPipelineBuilder,
+    pub(crate) pipeline_cache: bool,
+    pub(crate) pipeline_cache_dir: Option<PathBuf>,
+    pub(crate) dynamic_rendering: bool,
+    #[cfg(feature = "hot-reload")]
+    pub(crate) shader_dir: Option<std::path::PathBuf>,
+}
+
+impl Default for RendererBuilder {
+    fn default() -> Self {
+        Self {
+            window_attributes: WindowAttributes::default()
+                .with_title("Lye")
+                .with_maximized(true),
+            application_name: "Lye".to_owned(),
+            application_version: (0, 1, 0),
+            engine_name: "Fortnite-Engine".to_owned(),
+            engine_version: (0, 1, 0),
+            debug_messenger: true,
+            validation_layer: cfg!(debug_assertions),
+            min_severity: log::Level::Warn,
+            strict_validation: false,
+            instance_extensions: Vec::new(),
+            device_selection: None,
+            device_extensions: Vec::new(),
+            optional_device_extensions: Vec::new(),
+            features: vk::PhysicalDeviceFeatures::default(),
+            optional_features: vk::PhysicalDeviceFeatures::default(),
+            min_image_count: 3,
+            present_preference: PresentPreference::default(),
+            clear_color: [1.0, 1.0, 1.0, 1.0],
+            frames_in_flight: DEFAULT_FRAMES_IN_FLIGHT,
+            pipeline: GraphicsPipelineBuilder::default(),
+            pipeline_cache: true,
+            pipeline_cache_dir: None,
+            dynamic_rendering: true,
+            #[cfg(feature = "hot-reload")]
+            shader_dir: None,
+        }
+    }
+}
+
+impl RendererBuilder {
+    /// Builder with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attributes of the window created by [`RendererBuilder::build`], ignored for headless renderers.
+    pub fn window_attributes(mut self, window_attributes: WindowAttributes) -> Self {
+        self.window_attributes = window_attributes;
+        self
+    }
+
+    /// Shorthand for setting the title in [`RendererBuilder::window_attributes`].
+    pub fn title(mut self, title: &str) -> Self {
+        self.window_attributes.title = title.to_owned();
+        self
+    }
+
+    /// Shorthand for setting maximized in [`RendererBuilder::window_attributes`].
+    pub fn maximized(mut self, maximized: bool) -> Self {
+        self.window_attributes.maximized = maximized;
+        self
+    }
+
+    /// Name and version reported to the driver in `VkApplicationInfo`.
+    pub fn application(mut self, name: &str, version: (u32, u32, u32)) -> Self {
+        self.application_name = name.to_owned();
+        self.application_version = version;
+        self
+    }
+
+    /// Engine name and version reported to the driver in `VkApplicationInfo`.
+    pub fn engine(mut self, name: &str, version: (u32, u32, u32)) -> Self {
+        self.engine_name = name.to_owned();
+        self.engine_version = version;
+        self
+    }
+
+    /// Whether to install a debug messenger forwarding driver and layer messages to `log`, on by
+    /// default.
+    ///
+    /// Skipped with a warning when `VK_EXT_debug_utils` is unavailable.
+    pub fn debug_messenger(mut self, enabled: bool) -> Self {
+        self.debug_messenger = enabled;
+        self
+    }
+
+    /// Enables `VK_LAYER_KHRONOS_validation` if it is installed, on by default in debug builds.
+    pub fn validation_layer(mut self, enabled: bool) -> Self {
+        self.validation_layer = enabled;
+        self
+    }
+
+    /// Least severe messenger output that gets logged, `Warn` by default.
+    ///
+    /// Vulkan severities map to `Error`, `Warn`, `Info` and `Trace`, every message ID is logged once.
+    pub fn min_severity(mut self, min_severity: log::Level) -> Self {
+        self.min_severity = min_severity;
+        self
+    }
+
+    /// Makes [`Renderer::draw`] fail with [`crate::LyeError::Validation`] once an error was reported.
+    ///
+    /// Meant for tests, together with [`RendererBuilder::validation_layer`].
+    pub fn strict_validation(mut self, strict: bool) -> Self {
+        self.strict_validation = strict;
+        self
+    }
+
+    /// Instance extension to enable on top of the surface ones, creation fails if it is missing.
+    pub fn instance_extension(mut self, extension: &'static CStr) -> Self {
+        self.instance_extensions.push(extension);
+        self
+    }
+
+    /// Forces a physical device, overriding the `LYE_DEVICE` environment variable.
+    pub fn device(mut self, device_selection: DeviceSelection) -> Self {
+        self.device_selection = Some(device_selection);
+        self
+    }
+
+    /// Device extension every candidate device has to support.
+    pub fn device_extension(mut self, extension: &'static CStr) -> Self {
+        self.device_extensions.push(extension);
+        self
+    }
+
+    /// Device extension enabled only if the chosen device supports it, see
+    /// [`Renderer::has_device_extension`].
+    pub fn optional_device_extension(mut self, extension: &'static CStr) -> Self {
+        self.optional_device_extensions.push(extension);
+        self
+    }
+
+    /// Features every candidate device has to support.
+    pub fn features(mut self, features: vk::PhysicalDeviceFeatures) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// Features enabled only if the chosen device supports them, see [`Renderer::features`].
+    pub fn optional_features(mut self, features: vk::PhysicalDeviceFeatures) -> Self {
+        self.optional_features = features;
+        self
+    }
+
+    /// Swapchain images to ask for, clamped to what the surface allows. Defaults to 3.
+    pub fn min_image_count(mut self, min_image_count: u32) -> Self {
+        self.min_image_count = min_image_count;
+        self
+    }
+
+    /// Which present modes to try, the first one the surface supports is used. Defaults to VSync.
+    pub fn present_preference(mut self, present_preference: PresentPreference) -> Self {
+        self.present_preference = present_preference;
+        self
+    }
+
+    /// Color the target is cleared to every frame, white by default.
+    pub fn clear_color(mut self, clear_color: [f32; 4]) -> Self {
+        self.clear_color = clear_color;
+        self
+    }
+
+    /// How many frames the CPU may record ahead of the GPU, at least 1.
+    pub fn frames_in_flight(mut self, frames_in_flight: usize) -> Self {
+        self.frames_in_flight = frames_in_flight;
+        self
+    }
+
+    /// Initial depth test, can be changed later with [`Renderer::set_depth_test`].
+    pub fn depth_test(mut self, depth_test: DepthTest) -> Self {
+        self.pipeline.depth_test = Some(depth_test);
+        self
+    }
+
+    /// Initial pipeline state, can be changed later with [`Renderer::set_pipeline`].
+    pub fn pipeline(mut self, pipeline: GraphicsPipelineBuilder) -> Self {
+        self.pipeline = pipeline;
+        self
+    }
+
+    /// Whether compiled pipelines are saved to disk and reused on the next run, on by default.
+    pub fn pipeline_cache(mut self, enabled: bool) -> Self {
+        self.pipeline_cache = enabled;
+        self
+    }
+
+    /// Directory for the pipeline cache, the users cache directory by default.
+    ///
+    /// Files are named after the device UUID and driver version, so several GPUs and driver
+    /// updates never load each others data.
+    pub fn pipeline_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.pipeline_cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Renders without render pass and framebuffer objects when the device supports it, on by default.
+    ///
+    /// Needs Vulkan 1.3, or `VK_KHR_dynamic_rendering` and `VK_KHR_synchronization2`, other devices
+    /// keep using a render pass either way.
+    pub fn dynamic_rendering(mut self, enabled: bool) -> Self {
+        self.dynamic_rendering = enabled;
+        self
+    }
+
+    /// Watches `shader_dir` and rebuilds the pipeline whenever its GLSL changes.
+    ///
+    /// Compile errors are logged and the last good pipeline is kept.
+    #[cfg(feature = "hot-reload")]
+    pub fn hot_reload(mut self, shader_dir: impl Into<std::path::PathBuf>) -> Self {
+        self.shader_dir = Some(shader_dir.into());
+        self
+    }
+
+    /// Creates a window on `event_loop` and a renderer presenting to it.
+    pub fn build(self, event_loop: &ActiveEventLoop) -> LyeResult<Renderer> {
+        let window = event_loop.create_window(self.window_attributes.clone())?;
+        Renderer::create_windowed(window, &self)
+    }
+
+    /// Creates a renderer drawing into an offscreen image, see [`Renderer::read_frame`].
+    pub fn build_headless(self, extent: vk::Extent2D, format: vk::Format) -> LyeResult<Renderer> {
+        Renderer::create_headless(extent, format, &self)
+    }
+}
@@ -1 +1,317 @@
+use glam::{Mat4, Quat, Vec3};
 
+const FLIP_Y: Mat4 = Mat4::from_cols_array(&[
+    1.0, 0.0, 0.0, 0.0, //
+    0.0, -1.0, 0.0, 0.0, //
+    0.0, 0.0, 1.0, 0.0, //
+    0.0, 0.0, 0.0, 1.0,
+]);
+//World space is Y up, Vulkan clip space is Y down
+
+/// How the camera maps view space to clip space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    /// Perspective projection looking down -Z.
+    Perspective {
+        /// Vertical field of view in radians.
+        fov_y: f32,
+        /// Width divided by height.
+        aspect: f32,
+        /// Distance of the near plane.
+        near: f32,
+        /// Distance of the far plane, may be `f32::INFINITY`.
+        far: f32,
+        /// Maps near to depth 1 and far to 0.
+        reverse_z: bool,
+    },
+    /// Orthographic projection of a box in view space.
+    Orthographic {
+        /// Left edge of the box.
+        left: f32,
+        /// Right edge of the box.
+        right: f32,
+        /// Bottom edge of the box.
+        bottom: f32,
+        /// Top edge of the box.
+        top: f32,
+        /// Distance of the near plane.
+        near: f32,
+        /// Distance of the far plane.
+        far: f32,
+    },
+}
+
+impl Projection {
+    /// Perspective projection with near at depth 0 and far at 1.
+    pub fn perspective(fov_y: f32, aspect: f32, near: f32, far: f32) -> Self {
+        Projection::Perspective {
+            fov_y,
+            aspect,
+            near,
+            far,
+            reverse_z: false,
+        }
+    }
+
+    /// Perspective projection with near at depth 1 and far at 0, see [`crate::DepthTest::REVERSE_Z`].
+    pub fn perspective_reverse_z(fov_y: f32, aspect: f32, near: f32, far: f32) -> Self {
+        Projection::Perspective {
+            fov_y,
+            aspect,
+            near,
+            far,
+            reverse_z: true,
+        }
+    }
+    //Near maps to depth 1 and far to 0, needs a GREATER depth test and a clear depth of 0
+
+    /// Orthographic projection of a box centered on the view axis.
+    pub fn orthographic(width: f32, height: f32, near: f32, far: f32) -> Self {
+        Projection::Orthographic {
+            left: -width / 2.0,
+            right: width / 2.0,
+            bottom: -height / 2.0,
+            top: height / 2.0,
+            near,
+            far,
+        }
+    }
+
+    /// Adapts the projection to a new width / height ratio.
+    pub fn set_aspect(&mut self, new_aspect: f32) {
+        match self {
+            Projection::Perspective { aspect, .. } => *aspect = new_aspect,
+            Projection::Orthographic {
+                left,
+                right,
+                bottom,
+                top,
+                ..
+            } => {
+                let half_width = (*top - *bottom) * new_aspect / 2.0;
+                let center = (*left + *right) / 2.0;
+                (*left, *right) = (center - half_width, center + half_width);
+            }
+        }
+    }
+    //Orthographic projections keep their height and adjust the width
+
+    /// Projection matrix into Vulkan clip space.
+    pub fn matrix(&self) -> Mat4 {
+        let projection = match *self {
+            Projection::Perspective {
+                fov_y,
+                aspect,
+                near,
+                far,
+                reverse_z: false,
+            } => match far {
+                f32::INFINITY => Mat4::perspective_infinite_rh(fov_y, aspect, near),
+                _ => Mat4::perspective_rh(fov_y, aspect, near, far),
+            },
+            Projection::Perspective {
+                fov_y,
+                aspect,
+                near,
+                far,
+                reverse_z: true,
+            } => match far {
+                f32::INFINITY => Mat4::perspective_infinite_reverse_rh(fov_y, aspect, near),
+                _ => Mat4::perspective_rh(fov_y, aspect, far, near),
+            },
+            Projection::Orthographic {
+                left,
+                right,
+                bottom,
+                top,
+                near,
+                far,
+            } => Mat4::orthographic_rh(left, right, bottom, top, near, far),
+        };
+        //glam's _rh projections already map depth to 0..1 like Vulkan expects
+
+        FLIP_Y * projection
+    }
+}
+
+/// A camera placed in the world.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    /// Position in world space.
+    pub position: Vec3,
+    /// Rotation from looking down -Z.
+    pub orientation: Quat,
+    /// How the view is projected.
+    pub projection: Projection,
+}
+//An identity orientation looks down -Z with +Y up
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera::new(
+            Vec3::ZERO,
+            Projection::perspective(60f32.to_radians(), 1.0, 0.1, 100.0),
+        )
+    }
+}
+
+impl Camera {
+    /// Camera at position looking down -Z.
+    pub fn new(position: Vec3, projection: Projection) -> Self {
+        Self {
+            position,
+            orientation: Quat::IDENTITY,
+            projection,
+        }
+    }
+
+    /// Turns the camera to face target.
+    pub fn look_at(&mut self, target: Vec3, up: Vec3) {
+        let view = Mat4::look_at_rh(self.position, target, up);
+        self.orientation = Quat::from_mat4(&view.inverse()).normalize();
+    }
+
+    /// Direction the camera looks in.
+    pub fn forward(&self) -> Vec3 {
+        self.orientation * Vec3::NEG_Z
+    }
+
+    /// Right of the camera.
+    pub fn right(&self) -> Vec3 {
+        self.orientation * Vec3::X
+    }
+
+    /// Up of the camera.
+    pub fn up(&self) -> Vec3 {
+        self.orientation * Vec3::Y
+    }
+
+    /// World to view space matrix.
+    pub fn view(&self) -> Mat4 {
+        Mat4::from_rotation_translation(self.orientation, self.position).inverse()
+    }
+
+    /// View to clip space matrix.
+    pub fn projection(&self) -> Mat4 {
+        self.projection.matrix()
+    }
+
+    /// World to clip space matrix.
+    pub fn view_projection(&self) -> Mat4 {
+        self.projection() * self.view()
+    }
+}
+
+/// What the renderer pushes to the vertex shader for a camera.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraPushConstants {
+    /// World to clip space matrix.
+    pub view_projection: Mat4,
+}
+//Matches the push_constant block of shaders/vertex.glsl
+
+impl CameraPushConstants {
+    /// Size of the push constant block in bytes.
+    pub const SIZE: u32 = std::mem::size_of::<CameraPushConstants>() as u32;
+
+    /// The raw bytes, as passed to vkCmdPushConstants.
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(
+                self as *const CameraPushConstants as *const u8,
+                std::mem::size_of::<CameraPushConstants>(),
+            )
+        }
+    }
+}
+
+impl From<&Camera> for CameraPushConstants {
+    fn from(camera: &Camera) -> Self {
+        Self {
+            view_projection: camera.view_projection(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec4;
+
+    fn depth(projection: Projection, distance: f32) -> f32 {
+        let clip = projection.matrix() * Vec4::new(0.0, 0.0, -distance, 1.0);
+        clip.z / clip.w
+    }
+    //Depth of a point straight ahead after the perspective divide
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "{} isnt close to {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn perspective_maps_near_to_0_and_far_to_1() {
+        let projection = Projection::perspective(1.0, 1.5, 0.1, 100.0);
+        assert_near(depth(projection, 0.1), 0.0);
+        assert_near(depth(projection, 100.0), 1.0);
+
+        let infinite = Projection::perspective(1.0, 1.5, 0.1, f32::INFINITY);
+        assert_near(depth(infinite, 0.1), 0.0);
+        assert!(depth(infinite, 1e6) < 1.0 && depth(infinite, 1e6) > 0.999);
+    }
+
+    #[test]
+    fn reverse_z_maps_near_to_1_and_far_to_0() {
+        let projection = Projection::perspective_reverse_z(1.0, 1.5, 0.1, 100.0);
+        assert_near(depth(projection, 0.1), 1.0);
+        assert_near(depth(projection, 100.0), 0.0);
+
+        let infinite = Projection::perspective_reverse_z(1.0, 1.5, 0.1, f32::INFINITY);
+        assert_near(depth(infinite, 0.1), 1.0);
+        assert!(depth(infinite, 1e6) > 0.0 && depth(infinite, 1e6) < 1e-6);
+        assert!(depth(infinite, 10.0) > depth(infinite, 20.0));
+    }
+
+    #[test]
+    fn projections_flip_y_for_vulkan() {
+        let projection = Projection::orthographic(2.0, 2.0, 0.1, 10.0);
+        let clip = projection.matrix() * Vec4::new(0.5, 1.0, -1.0, 1.0);
+        assert_near(clip.x, 0.5);
+        assert_near(clip.y, -1.0);
+    }
+
+    #[test]
+    fn orthographic_set_aspect_keeps_the_height() {
+        let mut projection = Projection::orthographic(2.0, 2.0, 0.1, 10.0);
+        projection.set_aspect(2.0);
+        assert_eq!(
+            projection,
+            Projection::Orthographic {
+                left: -2.0,
+                right: 2.0,
+                bottom: -1.0,
+                top: 1.0,
+                near: 0.1,
+                far: 10.0,
+            }
+        );
+    }
+
+    #[test]
+    fn look_at_points_forward_at_the_target() {
+        let mut camera = Camera::new(
+            Vec3::new(3.0, 2.0, 1.0),
+            Projection::perspective(1.0, 1.0, 0.1, 100.0),
+        );
+        camera.look_at(Vec3::ZERO, Vec3::Y);
+        assert!(camera
+            .forward()
+            .abs_diff_eq(-camera.position.normalize(), 1e-5));
+        assert!(camera.view().transform_point3(Vec3::ZERO).x.abs() < 1e-5);
+    }
+}
@@ -0,0 +1,429 @@
+use crate::camera::Camera;
+use glam::{Quat, Vec3};
+use std::collections::HashSet;
+use winit::{
+    event::{DeviceEvent, ElementState, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+    window::{CursorGrabMode, Window},
+};
+
+const MAX_PITCH: f32 = 89f32.to_radians();
+const PIXELS_PER_LINE: f32 = 20.0;
+//Touchpads report MouseScrollDelta::PixelDelta, converted to wheel lines
+
+fn yaw_pitch(forward: Vec3) -> (f32, f32) {
+    let forward = forward.normalize_or(Vec3::NEG_Z);
+    (
+        (-forward.x).atan2(-forward.z),
+        forward.y.clamp(-1.0, 1.0).asin(),
+    )
+}
+
+fn orientation(yaw: f32, pitch: f32) -> Quat {
+    Quat::from_rotation_y(yaw) * Quat::from_rotation_x(pitch)
+}
+//Yaw turns around world +Y, pitch around the cameras right axis, without any roll
+
+/// First person controls, the mouse looks around while the cursor is grabbed.
+pub struct FlyController {
+    /// Units per second.
+    pub speed: f32,
+    /// Speed multiplier while ShiftLeft is held.
+    pub boost: f32,
+    /// Radians per pixel of mouse motion.
+    pub sensitivity: f32,
+    yaw: f32,
+    pitch: f32,
+    pressed: HashSet<KeyCode>,
+    mouse_delta: (f64, f64),
+    grabbed: bool,
+}
+//WASD moves, Space/ControlLeft go up/down, ShiftLeft boosts, left click grabs the cursor and Escape releases it
+
+impl Default for FlyController {
+    fn default() -> Self {
+        Self {
+            speed: 2.0,
+            boost: 4.0,
+            sensitivity: 0.002,
+            yaw: 0.0,
+            pitch: 0.0,
+            pressed: HashSet::new(),
+            mouse_delta: (0.0, 0.0),
+            grabbed: false,
+        }
+    }
+}
+
+impl FlyController {
+    /// Starts looking where the camera looks.
+    pub fn from_camera(camera: &Camera) -> Self {
+        let (yaw, pitch) = yaw_pitch(camera.forward());
+        Self {
+            yaw,
+            pitch,
+            ..Default::default()
+        }
+    }
+
+    /// Feeds keyboard and mouse button events.
+    pub fn window_event(&mut self, window: &Window, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                let PhysicalKey::Code(code) = event.physical_key else {
+                    return;
+                };
+                match event.state {
+                    ElementState::Pressed if code == KeyCode::Escape => {
+                        self.set_grabbed(window, false)
+                    }
+                    ElementState::Pressed => {
+                        self.pressed.insert(code);
+                    }
+                    ElementState::Released => {
+                        self.pressed.remove(&code);
+                    }
+                }
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => self.set_grabbed(window, true),
+            WindowEvent::Focused(false) => {
+                self.pressed.clear();
+                self.set_grabbed(window, false);
+            }
+            _ => (),
+        }
+    }
+    //Keys are released on focus loss, the window wont receive their Released events
+
+    /// Feeds raw mouse motion.
+    pub fn device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta } = event {
+            if self.grabbed {
+                self.mouse_delta.0 += delta.0;
+                self.mouse_delta.1 += delta.1;
+            }
+        }
+    }
+    //Raw motion keeps working while the cursor is locked, CursorMoved stops at the window border
+
+    /// Grabs and hides the cursor, or releases it.
+    pub fn set_grabbed(&mut self, window: &Window, grabbed: bool) {
+        if grabbed == self.grabbed {
+            return;
+        }
+        let result = match grabbed {
+            true => window
+                .set_cursor_grab(CursorGrabMode::Locked)
+                .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined)),
+            false => window.set_cursor_grab(CursorGrabMode::None),
+        };
+        if let Err(err) = result {
+            log::warn!("Couldnt change cursor grab : {}", err);
+            return;
+        }
+        window.set_cursor_visible(!grabbed);
+        self.grabbed = grabbed;
+        self.mouse_delta = (0.0, 0.0);
+    }
+    //Not every platform supports Locked, Confined is the fallback
+
+    /// Moves the camera by the input since the last update, returns whether it changed.
+    pub fn update(&mut self, camera: &mut Camera, dt: f32) -> bool {
+        let (dx, dy) = std::mem::take(&mut self.mouse_delta);
+        self.yaw -= dx as f32 * self.sensitivity;
+        self.pitch = (self.pitch - dy as f32 * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        let rotation = orientation(self.yaw, self.pitch);
+
+        let axis = |positive, negative| {
+            self.pressed.contains(&positive) as i32 as f32
+                - self.pressed.contains(&negative) as i32 as f32
+        };
+        let direction = rotation * Vec3::NEG_Z * axis(KeyCode::KeyW, KeyCode::KeyS)
+            + rotation * Vec3::X * axis(KeyCode::KeyD, KeyCode::KeyA)
+            + Vec3::Y * axis(KeyCode::Space, KeyCode::ControlLeft);
+        let speed = match self.pressed.contains(&KeyCode::ShiftLeft) {
+            true => self.speed * self.boost,
+            false => self.speed,
+        };
+        let position = camera.position + direction.normalize_or_zero() * speed * dt;
+
+        let changed = rotation != camera.orientation || position != camera.position;
+        camera.orientation = rotation;
+        camera.position = position;
+        changed
+    }
+    //Mouse deltas are already per frame, only the movement is scaled by dt
+}
+
+/// Orbits the camera around a target point.
+pub struct OrbitController {
+    /// Point the camera orbits and looks at.
+    pub target: Vec3,
+    /// Radians per pixel dragged.
+    pub sensitivity: f32,
+    /// Fraction of the distance one wheel line zooms.
+    pub zoom_speed: f32,
+    /// Closest the camera gets to the target.
+    pub min_distance: f32,
+    /// How fast the distance follows the zoom, higher is snappier.
+    pub smoothing: f32,
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+    target_distance: f32,
+    dragging: bool,
+    cursor: Option<(f64, f64)>,
+    mouse_delta: (f64, f64),
+}
+//Dragging with the left mouse button orbits around target, the wheel zooms
+
+impl Default for OrbitController {
+    fn default() -> Self {
+        Self {
+            target: Vec3::ZERO,
+            sensitivity: 0.005,
+            zoom_speed: 0.1,
+            min_distance: 0.1,
+            smoothing: 15.0,
+            yaw: 0.0,
+            pitch: 0.0,
+            distance: 2.0,
+            target_distance: 2.0,
+            dragging: false,
+            cursor: None,
+            mouse_delta: (0.0, 0.0),
+        }
+    }
+}
+
+impl OrbitController {
+    /// Starts orbiting target from where the camera is.
+    pub fn from_camera(camera: &Camera, target: Vec3) -> Self {
+        let offset = target - camera.position;
+        let (yaw, pitch) = yaw_pitch(offset);
+        let distance = offset.length().max(Self::default().min_distance);
+        Self {
+            target,
+            yaw,
+            pitch: pitch.clamp(-MAX_PITCH, MAX_PITCH),
+            distance,
+            target_distance: distance,
+            ..Default::default()
+        }
+    }
+
+    /// Feeds mouse drags and wheel events.
+    pub fn window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => self.dragging = *state == ElementState::Pressed,
+            WindowEvent::CursorMoved { position, .. } => {
+                if let (true, Some((x, y))) = (self.dragging, self.cursor) {
+                    self.mouse_delta.0 += position.x - x;
+                    self.mouse_delta.1 += position.y - y;
+                }
+                self.cursor = Some((position.x, position.y));
+            }
+            WindowEvent::CursorLeft { .. } => self.cursor = None,
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+                };
+                self.target_distance = (self.target_distance * (1.0 - self.zoom_speed).powf(lines))
+                    .max(self.min_distance);
+            }
+            WindowEvent::Focused(false) => self.dragging = false,
+            _ => (),
+        }
+    }
+    //Each wheel line scales the distance by (1 - zoom_speed), scrolling up zooms in
+
+    /// Moves the camera by the input since the last update, returns whether it changed.
+    pub fn update(&mut self, camera: &mut Camera, dt: f32) -> bool {
+        let (dx, dy) = std::mem::take(&mut self.mouse_delta);
+        self.yaw -= dx as f32 * self.sensitivity;
+        self.pitch = (self.pitch - dy as f32 * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        self.distance +=
+            (self.target_distance - self.distance) * (1.0 - (-self.smoothing * dt).exp());
+        //Exponential smoothing, converges at the same speed whatever the frame rate
+
+        let rotation = orientation(self.yaw, self.pitch);
+        let position = self.target + rotation * Vec3::Z * self.distance;
+
+        let changed = rotation != camera.orientation || position != camera.position;
+        camera.orientation = rotation;
+        camera.position = position;
+        changed
+    }
+}
+
+/// Either controller, switched with Tab.
+pub enum CameraController {
+    /// First person controls.
+    Fly(FlyController),
+    /// Orbit controls.
+    Orbit(OrbitController),
+}
+//Tab switches between both, starting from where the camera currently is
+
+impl Default for CameraController {
+    fn default() -> Self {
+        CameraController::Orbit(OrbitController::default())
+    }
+}
+
+impl CameraController {
+    /// Feeds window events to the active controller, Tab switches controllers.
+    pub fn window_event(&mut self, window: &Window, camera: &Camera, event: &WindowEvent) {
+        if let WindowEvent::KeyboardInput { event: key, .. } = event {
+            if key.state == ElementState::Pressed
+                && !key.repeat
+                && key.physical_key == PhysicalKey::Code(KeyCode::Tab)
+            {
+                self.toggle(window, camera);
+                return;
+            }
+        }
+        match self {
+            CameraController::Fly(fly) => fly.window_event(window, event),
+            CameraController::Orbit(orbit) => orbit.window_event(event),
+        }
+    }
+
+    /// Feeds device events to the active controller.
+    pub fn device_event(&mut self, event: &DeviceEvent) {
+        if let CameraController::Fly(fly) = self {
+            fly.device_event(event);
+        }
+    }
+
+    /// Updates the camera with the active controller, returns whether it changed.
+    pub fn update(&mut self, camera: &mut Camera, dt: f32) -> bool {
+        match self {
+            CameraController::Fly(fly) => fly.update(camera, dt),
+            CameraController::Orbit(orbit) => orbit.update(camera, dt),
+        }
+    }
+
+    fn toggle(&mut self, window: &Window, camera: &Camera) {
+        *self = match self {
+            CameraController::Fly(fly) => {
+                fly.set_grabbed(window, false);
+                CameraController::Orbit(OrbitController::from_camera(
+                    camera,
+                    camera.position + camera.forward() * OrbitController::default().distance,
+                ))
+            }
+            CameraController::Orbit(_) => CameraController::Fly(FlyController::from_camera(camera)),
+        };
+    }
+    //Orbiting resumes around the point in front of the camera
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Projection;
+    use winit::event::{DeviceId, TouchPhase};
+
+    fn camera_at(position: Vec3, target: Vec3) -> Camera {
+        let mut camera = Camera::new(position, Projection::perspective(1.0, 1.0, 0.1, 100.0));
+        camera.look_at(target, Vec3::Y);
+        camera
+    }
+
+    fn wheel(lines: f32) -> WindowEvent {
+        WindowEvent::MouseWheel {
+            device_id: DeviceId::dummy(),
+            delta: MouseScrollDelta::LineDelta(0.0, lines),
+            phase: TouchPhase::Moved,
+        }
+    }
+
+    #[test]
+    fn yaw_pitch_round_trips_through_orientation() {
+        for forward in [
+            Vec3::NEG_Z,
+            Vec3::new(1.0, 0.5, 0.0),
+            Vec3::new(-0.3, -0.8, 0.4),
+        ] {
+            let (yaw, pitch) = yaw_pitch(forward);
+            let rotated = orientation(yaw, pitch) * Vec3::NEG_Z;
+            assert!(
+                rotated.abs_diff_eq(forward.normalize(), 1e-5),
+                "{}",
+                forward
+            );
+        }
+    }
+
+    #[test]
+    fn fly_moves_at_speed_along_the_view() {
+        let mut camera = camera_at(Vec3::ZERO, Vec3::X);
+        let mut fly = FlyController::from_camera(&camera);
+        fly.pressed.insert(KeyCode::KeyW);
+        assert!(fly.update(&mut camera, 0.5));
+        assert!(camera.position.abs_diff_eq(Vec3::X, 1e-5));
+
+        fly.pressed.insert(KeyCode::KeyD);
+        fly.pressed.insert(KeyCode::ShiftLeft);
+        let start = camera.position;
+        fly.update(&mut camera, 0.5);
+        let moved = camera.position - start;
+        assert!((moved.length() - fly.speed * fly.boost * 0.5).abs() < 1e-5);
+        assert!(moved.abs_diff_eq(Vec3::new(1.0, 0.0, 1.0).normalize() * 4.0, 1e-5));
+        //Diagonals arent faster than straight moves
+    }
+
+    #[test]
+    fn fly_pitch_is_clamped() {
+        let mut camera = Camera::default();
+        let mut fly = FlyController::from_camera(&camera);
+        fly.mouse_delta = (0.0, -1e6);
+        fly.update(&mut camera, 0.0);
+        assert!((fly.pitch - MAX_PITCH).abs() < 1e-6);
+        assert!(camera.forward().y > 0.99 && camera.up().y > 0.0);
+        //Looks almost straight up without flipping over
+
+        assert!(!fly.update(&mut camera, 0.1));
+    }
+
+    #[test]
+    fn orbit_starts_where_the_camera_is() {
+        let target = Vec3::new(1.0, 0.0, -2.0);
+        let mut camera = camera_at(Vec3::new(3.0, 2.0, 1.0), target);
+        let position = camera.position;
+        let mut orbit = OrbitController::from_camera(&camera, target);
+        orbit.update(&mut camera, 0.016);
+
+        assert!(camera.position.abs_diff_eq(position, 1e-4));
+        assert!(camera
+            .forward()
+            .abs_diff_eq((target - position).normalize(), 1e-5));
+    }
+
+    #[test]
+    fn orbit_zoom_is_smoothed_and_clamped() {
+        let mut camera = Camera::default();
+        let mut orbit = OrbitController::default();
+        orbit.window_event(&wheel(1.0));
+        assert!((orbit.target_distance - 1.8).abs() < 1e-6);
+
+        orbit.update(&mut camera, 1.0 / 60.0);
+        assert!(orbit.distance < 2.0 && orbit.distance > 1.8);
+        orbit.update(&mut camera, 10.0);
+        assert!((orbit.distance - 1.8).abs() < 1e-5);
+        assert!((camera.position.length() - 1.8).abs() < 1e-5);
+
+        orbit.window_event(&wheel(1000.0));
+        assert_eq!(orbit.target_distance, orbit.min_distance);
+    }
+}
@@ -0,0 +1,68 @@
+use ash::{self, vk};
+use std::{ffi::CString, marker::PhantomData, ptr};
+
+#[derive(Clone, Default)]
+pub(crate) struct DebugUtils {
+    device: Option<ash::ext::debug_utils::Device>,
+}
+//Every call is a no-op without VK_EXT_debug_utils, so callers never have to check
+
+fn c_string(name: &str) -> CString {
+    CString::new(name.replace('\0', "")).unwrap_or_default()
+}
+
+impl DebugUtils {
+    pub(crate) fn new(instance: &ash::Instance, device: &ash::Device, enabled: bool) -> Self {
+        Self {
+            device: enabled.then(|| ash::ext::debug_utils::Device::new(instance, device)),
+        }
+    }
+
+    pub(crate) fn name<H: vk::Handle>(&self, handle: H, name: &str) {
+        let Some(device) = &self.device else {
+            return;
+        };
+        let name = c_string(name);
+        let name_info = vk::DebugUtilsObjectNameInfoEXT {
+            s_type: vk::StructureType::DEBUG_UTILS_OBJECT_NAME_INFO_EXT,
+            p_next: ptr::null(),
+            object_type: H::TYPE,
+            object_handle: handle.as_raw(),
+            p_object_name: name.as_ptr(),
+            _marker: PhantomData,
+        };
+
+        if let Err(err) = unsafe { device.set_debug_utils_object_name(&name_info) } {
+            log::debug!("Couldnt name {:?} : {}", H::TYPE, err);
+        }
+    }
+    //Naming only helps debugging, so a failure is never worth an error
+
+    pub(crate) fn begin_label(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        name: &str,
+        color: [f32; 4],
+    ) {
+        let Some(device) = &self.device else {
+            return;
+        };
+        let name = c_string(name);
+        let label = vk::DebugUtilsLabelEXT {
+            s_type: vk::StructureType::DEBUG_UTILS_LABEL_EXT,
+            p_next: ptr::null(),
+            p_label_name: name.as_ptr(),
+            color,
+            _marker: PhantomData,
+        };
+
+        unsafe { device.cmd_begin_debug_utils_label(command_buffer, &label) };
+    }
+
+    pub(crate) fn end_label(&self, command_buffer: vk::CommandBuffer) {
+        if let Some(device) = &self.device {
+            unsafe { device.cmd_end_debug_utils_label(command_buffer) };
+        }
+    }
+}
+//A color of all zeros lets tools pick their own
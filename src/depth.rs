@@ -0,0 +1,171 @@
+use crate::{
+    camera::Projection,
+    memory::{Allocation, Allocator, MemoryLocation},
+};
+use ash::{self, prelude::VkResult, vk};
+use std::{marker::PhantomData, ptr};
+
+const DEPTH_FORMATS: [vk::Format; 4] = [
+    vk::Format::D32_SFLOAT,
+    vk::Format::D32_SFLOAT_S8_UINT,
+    vk::Format::D24_UNORM_S8_UINT,
+    vk::Format::D16_UNORM,
+];
+//In order of preference, D16_UNORM is always supported
+
+/// How fragments are depth tested, and what the depth buffer is cleared to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthTest {
+    /// Passes when the fragment depth compares true against the stored one.
+    pub compare_op: vk::CompareOp,
+    /// Depth the buffer is cleared to every frame.
+    pub clear_depth: f32,
+    /// Whether passing fragments write their depth.
+    pub write: bool,
+}
+
+impl DepthTest {
+    /// LESS against a buffer cleared to 1, for regular projections.
+    pub const STANDARD: DepthTest = DepthTest {
+        compare_op: vk::CompareOp::LESS,
+        clear_depth: 1.0,
+        write: true,
+    };
+
+    /// GREATER against a buffer cleared to 0, for reverse-Z projections.
+    pub const REVERSE_Z: DepthTest = DepthTest {
+        compare_op: vk::CompareOp::GREATER,
+        clear_depth: 0.0,
+        write: true,
+    };
+    //Spreads float precision evenly over the depth range, needs a reverse-Z projection
+
+    /// The depth test matching a projection.
+    pub fn for_projection(projection: &Projection) -> Self {
+        match projection {
+            Projection::Perspective {
+                reverse_z: true, ..
+            } => DepthTest::REVERSE_Z,
+            _ => DepthTest::STANDARD,
+        }
+    }
+}
+
+impl Default for DepthTest {
+    fn default() -> Self {
+        DepthTest::STANDARD
+    }
+}
+
+pub(crate) struct DepthBuffer {
+    image: vk::Image,
+    allocation: Allocation,
+    view: vk::ImageView,
+}
+
+impl DepthBuffer {
+    pub(crate) fn find_format(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+    ) -> VkResult<vk::Format> {
+        DEPTH_FORMATS
+            .into_iter()
+            .find(|&format| {
+                unsafe { instance.get_physical_device_format_properties(physical_device, format) }
+                    .optimal_tiling_features
+                    .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+            })
+            .ok_or(vk::Result::ERROR_FORMAT_NOT_SUPPORTED)
+    }
+
+    pub(crate) fn aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+        match format {
+            vk::Format::D32_SFLOAT_S8_UINT
+            | vk::Format::D24_UNORM_S8_UINT
+            | vk::Format::D16_UNORM_S8_UINT => {
+                vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+            }
+            _ => vk::ImageAspectFlags::DEPTH,
+        }
+    }
+    //Views used as depth/stencil attachments have to cover every aspect of the format
+
+    pub(crate) fn new(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        format: vk::Format,
+        extent: vk::Extent2D,
+    ) -> VkResult<Self> {
+        let image_create_info = vk::ImageCreateInfo {
+            s_type: vk::StructureType::IMAGE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::ImageCreateFlags::empty(),
+            image_type: vk::ImageType::TYPE_2D,
+            format,
+            extent: vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: 1,
+            samples: vk::SampleCountFlags::TYPE_1,
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            _marker: PhantomData,
+        };
+
+        let (image, allocation) =
+            allocator.create_image(device, &image_create_info, MemoryLocation::GpuOnly)?;
+
+        let view_create_info = vk::ImageViewCreateInfo {
+            s_type: vk::StructureType::IMAGE_VIEW_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::ImageViewCreateFlags::empty(),
+            image,
+            view_type: vk::ImageViewType::TYPE_2D,
+            format,
+            components: vk::ComponentMapping::default(),
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask: DepthBuffer::aspect_mask(format),
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            _marker: PhantomData,
+        };
+
+        let view = match unsafe { device.create_image_view(&view_create_info, None) } {
+            Ok(view) => view,
+            Err(err) => {
+                allocator.destroy_image(device, image, &allocation);
+                return Err(err);
+            }
+        };
+
+        Ok(Self {
+            image,
+            allocation,
+            view,
+        })
+    }
+
+    pub(crate) fn image(&self) -> vk::Image {
+        self.image
+    }
+
+    pub(crate) fn view(&self) -> vk::ImageView {
+        self.view
+    }
+
+    pub(crate) fn destroy(&self, device: &ash::Device, allocator: &mut Allocator) {
+        unsafe { device.destroy_image_view(self.view, None) };
+        allocator.destroy_image(device, self.image, &self.allocation);
+    }
+}
+//One per framebuffer, so frames in flight never share a depth image
@@ -0,0 +1,354 @@
+use crate::{
+    error::{Context, LyeError, LyeResult},
+    memory::{Allocation, Allocator, MemoryLocation},
+    pipeline::GraphicsPipeline,
+    reflect::DescriptorBinding,
+    uniform::UniformSlot,
+};
+use ash::{self, prelude::VkResult, vk};
+use std::{collections::HashMap, marker::PhantomData, ptr};
+
+const SETS_PER_POOL: u32 = 64;
+
+const POOL_SIZES: [vk::DescriptorPoolSize; 6] = [
+    vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::UNIFORM_BUFFER,
+        descriptor_count: 128,
+    },
+    vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::STORAGE_BUFFER,
+        descriptor_count: 64,
+    },
+    vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        descriptor_count: 128,
+    },
+    vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::SAMPLED_IMAGE,
+        descriptor_count: 64,
+    },
+    vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::SAMPLER,
+        descriptor_count: 32,
+    },
+    vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::STORAGE_IMAGE,
+        descriptor_count: 32,
+    },
+];
+//A full pool just starts another one, so these only need to be in the right ballpark
+
+const UNIFORM_ALIGNMENT: usize = 256;
+//The largest minUniformBufferOffsetAlignment the spec allows, so no need to query it
+
+const MIN_UNIFORM_BUFFER_SIZE: usize = 64 * 1024;
+
+type BindingKey = (u32, vk::DescriptorType, u32, vk::ShaderStageFlags);
+
+#[derive(Default)]
+pub(crate) struct DescriptorLayoutCache {
+    layouts: HashMap<Vec<BindingKey>, vk::DescriptorSetLayout>,
+}
+//Pipelines with the same bindings share set layouts, which also makes their descriptor sets compatible
+
+impl DescriptorLayoutCache {
+    pub(crate) fn get(
+        &mut self,
+        device: &ash::Device,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+    ) -> VkResult<vk::DescriptorSetLayout> {
+        let key: Vec<BindingKey> = bindings
+            .iter()
+            .map(|binding| {
+                (
+                    binding.binding,
+                    binding.descriptor_type,
+                    binding.descriptor_count,
+                    binding.stage_flags,
+                )
+            })
+            .collect();
+        if let Some(&layout) = self.layouts.get(&key) {
+            return Ok(layout);
+        }
+
+        let create_info = vk::DescriptorSetLayoutCreateInfo {
+            s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::DescriptorSetLayoutCreateFlags::empty(),
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+            _marker: PhantomData,
+        };
+        let layout = unsafe { device.create_descriptor_set_layout(&create_info, None) }?;
+        self.layouts.insert(key, layout);
+
+        Ok(layout)
+    }
+    //Bindings come sorted from reflection, so equal sets always produce equal keys
+
+    pub(crate) fn destroy(&self, device: &ash::Device) {
+        for &layout in self.layouts.values() {
+            unsafe { device.destroy_descriptor_set_layout(layout, None) };
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct FrameDescriptors {
+    pools: Vec<vk::DescriptorPool>,
+    current_pool: usize,
+    uniform_buffer: vk::Buffer,
+    uniform_allocation: Option<Allocation>,
+    uniform_size: usize,
+}
+//Pools and the uniform buffer are created on first use, a pipeline without descriptors never needs them
+
+impl FrameDescriptors {
+    fn allocate(
+        &mut self,
+        device: &ash::Device,
+        layout: vk::DescriptorSetLayout,
+    ) -> VkResult<vk::DescriptorSet> {
+        loop {
+            let fresh = self.current_pool == self.pools.len();
+            if fresh {
+                let create_info = vk::DescriptorPoolCreateInfo {
+                    s_type: vk::StructureType::DESCRIPTOR_POOL_CREATE_INFO,
+                    p_next: ptr::null(),
+                    flags: vk::DescriptorPoolCreateFlags::empty(),
+                    max_sets: SETS_PER_POOL,
+                    pool_size_count: POOL_SIZES.len() as u32,
+                    p_pool_sizes: POOL_SIZES.as_ptr(),
+                    _marker: PhantomData,
+                };
+                self.pools
+                    .push(unsafe { device.create_descriptor_pool(&create_info, None) }?);
+            }
+
+            let layouts = [layout];
+            let allocate_info = vk::DescriptorSetAllocateInfo {
+                s_type: vk::StructureType::DESCRIPTOR_SET_ALLOCATE_INFO,
+                p_next: ptr::null(),
+                descriptor_pool: self.pools[self.current_pool],
+                descriptor_set_count: layouts.len() as u32,
+                p_set_layouts: layouts.as_ptr(),
+                _marker: PhantomData,
+            };
+            match unsafe { device.allocate_descriptor_sets(&allocate_info) } {
+                Ok(sets) => return Ok(sets[0]),
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL)
+                    if !fresh =>
+                {
+                    self.current_pool += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+    //Moves on to the next pool when one runs out, pools are kept and reused after the reset,
+    //a set that doesnt even fit an empty pool is an error instead of an endless loop
+
+    fn reserve_uniforms(
+        &mut self,
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        size: usize,
+    ) -> VkResult<()> {
+        if size <= self.uniform_size {
+            return Ok(());
+        }
+        self.destroy_uniform_buffer(device, allocator);
+
+        let size = size.next_power_of_two().max(MIN_UNIFORM_BUFFER_SIZE);
+        let buffer_create_info = vk::BufferCreateInfo {
+            s_type: vk::StructureType::BUFFER_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::BufferCreateFlags::empty(),
+            size: size as vk::DeviceSize,
+            usage: vk::BufferUsageFlags::UNIFORM_BUFFER,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+            _marker: PhantomData,
+        };
+        let (buffer, allocation) =
+            allocator.create_buffer(device, &buffer_create_info, MemoryLocation::CpuToGpu)?;
+        self.uniform_buffer = buffer;
+        self.uniform_allocation = Some(allocation);
+        self.uniform_size = size;
+
+        Ok(())
+    }
+    //Only called once the frames fence signaled, so the old buffer is no longer read
+
+    pub(crate) fn prepare(
+        &mut self,
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        pipeline: &GraphicsPipeline,
+        uniforms: &[UniformSlot],
+    ) -> LyeResult<Vec<vk::DescriptorSet>> {
+        for &pool in &self.pools {
+            unsafe { device.reset_descriptor_pool(pool, vk::DescriptorPoolResetFlags::empty()) }
+                .context("Resetting a descriptor pool")?;
+        }
+        self.current_pool = 0;
+        if pipeline.set_layouts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let (offsets, size) = uniform_offsets(&pipeline.uniform_blocks, uniforms)?;
+        self.reserve_uniforms(device, allocator, size)
+            .context("Creating the uniform buffer")?;
+        if let Some(allocation) = &self.uniform_allocation {
+            for &(slot, offset) in &offsets {
+                allocation.write(offset, &slot.data);
+            }
+        }
+        //Every frame gets its own copy, so updating a uniform never races a frame in flight
+
+        let descriptor_sets = pipeline
+            .set_layouts
+            .iter()
+            .map(|&layout| self.allocate(device, layout))
+            .collect::<VkResult<Vec<_>>>()
+            .context("Allocating descriptor sets")?;
+
+        let buffer_infos: Vec<vk::DescriptorBufferInfo> = offsets
+            .iter()
+            .map(|&(slot, offset)| vk::DescriptorBufferInfo {
+                buffer: self.uniform_buffer,
+                offset: offset as vk::DeviceSize,
+                range: slot.data.len() as vk::DeviceSize,
+            })
+            .collect();
+        let writes: Vec<vk::WriteDescriptorSet> = pipeline
+            .uniform_blocks
+            .iter()
+            .zip(&buffer_infos)
+            .map(|(block, buffer_info)| vk::WriteDescriptorSet {
+                s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+                p_next: ptr::null(),
+                dst_set: descriptor_sets[block.set as usize],
+                dst_binding: block.binding,
+                dst_array_element: 0,
+                descriptor_count: 1,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                p_image_info: ptr::null(),
+                p_buffer_info: buffer_info,
+                p_texel_buffer_view: ptr::null(),
+                _marker: PhantomData,
+            })
+            .collect();
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+        Ok(descriptor_sets)
+    }
+    //Called once the frames fence signaled, every set of the pipeline is allocated fresh from the reset pools
+
+    fn destroy_uniform_buffer(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        if let Some(allocation) = self.uniform_allocation.take() {
+            allocator.destroy_buffer(device, self.uniform_buffer, &allocation);
+        }
+        self.uniform_buffer = vk::Buffer::null();
+        self.uniform_size = 0;
+    }
+
+    pub(crate) fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        self.destroy_uniform_buffer(device, allocator);
+        for &pool in &self.pools {
+            unsafe { device.destroy_descriptor_pool(pool, None) };
+        }
+        self.pools.clear();
+    }
+    //Destroying a pool frees its sets
+}
+
+fn uniform_offsets<'a>(
+    blocks: &[DescriptorBinding],
+    uniforms: &'a [UniformSlot],
+) -> LyeResult<(Vec<(&'a UniformSlot, usize)>, usize)> {
+    let mut offsets = Vec::with_capacity(blocks.len());
+    let mut size: usize = 0;
+    for block in blocks {
+        let slot = uniforms
+            .iter()
+            .find(|slot| (slot.set, slot.binding) == (block.set, block.binding))
+            .ok_or_else(|| {
+                LyeError::InvalidConfig(format!(
+                    "Uniform block {} at set {} binding {} has no buffer, create one with Renderer::create_uniform_buffer",
+                    block.name, block.set, block.binding
+                ))
+            })?;
+        let offset = size.next_multiple_of(UNIFORM_ALIGNMENT);
+        size = offset + slot.data.len();
+        offsets.push((slot, offset));
+    }
+
+    Ok((offsets, size))
+}
+//Where every uniform block of the pipeline goes in the frames uniform buffer, and how large the buffer has to be
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Copy)]
+    #[repr(C)]
+    struct Tint {
+        color: glam::Vec4,
+    }
+    crate::uniform_block!(Tint { color });
+
+    fn block(set: u32, binding: u32) -> DescriptorBinding {
+        DescriptorBinding {
+            name: format!("block_{}_{}", set, binding),
+            set,
+            binding,
+            descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+            count: 1,
+            block: None,
+        }
+    }
+
+    #[test]
+    fn uniform_blocks_are_placed_at_aligned_offsets() {
+        let tint = Tint {
+            color: glam::Vec4::ONE,
+        };
+        let uniforms = [
+            UniformSlot::new(1, 0, &tint).unwrap(),
+            UniformSlot::new(0, 2, &tint).unwrap(),
+        ];
+        let (offsets, size) = uniform_offsets(&[block(0, 2), block(1, 0)], &uniforms).unwrap();
+        let placed: Vec<_> = offsets
+            .iter()
+            .map(|&(slot, offset)| (slot.set, slot.binding, offset))
+            .collect();
+        assert_eq!(placed, [(0, 2, 0), (1, 0, UNIFORM_ALIGNMENT)]);
+        assert_eq!(size, UNIFORM_ALIGNMENT + 16);
+
+        let (offsets, size) = uniform_offsets(&[], &uniforms).unwrap();
+        assert!(offsets.is_empty());
+        assert_eq!(size, 0);
+    }
+
+    #[test]
+    fn uniform_blocks_without_a_buffer_are_errors() {
+        let uniforms = [UniformSlot::new(
+            0,
+            0,
+            &Tint {
+                color: glam::Vec4::ONE,
+            },
+        )
+        .unwrap()];
+        let err = uniform_offsets(&[block(0, 0), block(0, 1)], &uniforms)
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .contains("block_0_1 at set 0 binding 1 has no buffer"));
+    }
+}
@@ -0,0 +1,450 @@
+use crate::{
+    camera::CameraPushConstants,
+    depth::DepthBuffer,
+    error::{Context, LyeError, LyeResult},
+};
+use ash::{self, vk};
+use std::ffi::CStr;
+
+/// Which physical device to render with.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum DeviceSelection {
+    /// The best suitable device.
+    #[default]
+    Auto,
+    /// The device at this position in the list logged at startup.
+    Index(usize),
+    /// The best suitable device whose name contains this.
+    Name(String),
+}
+//Index is the position in vkEnumeratePhysicalDevices, Name matches a case-insensitive substring of the device name
+
+impl DeviceSelection {
+    /// Environment variable read when no device is passed to the builder.
+    pub const ENV_VAR: &'static str = "LYE_DEVICE";
+
+    /// Parses [`DeviceSelection::ENV_VAR`], [`DeviceSelection::Auto`] when it isnt set.
+    pub fn from_env() -> LyeResult<Self> {
+        match std::env::var(DeviceSelection::ENV_VAR) {
+            Ok(value) if !value.trim().is_empty() => value.trim().parse(),
+            _ => Ok(DeviceSelection::Auto),
+        }
+    }
+}
+
+impl std::str::FromStr for DeviceSelection {
+    type Err = LyeError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Some(index) = value.strip_prefix("index:") {
+            return index
+                .trim()
+                .parse()
+                .map(DeviceSelection::Index)
+                .map_err(|_| {
+                    LyeError::DeviceSelection(format!("{:?} isnt a device index", index))
+                });
+        }
+        if let Some(name) = value.strip_prefix("name:") {
+            return Ok(DeviceSelection::Name(name.to_owned()));
+        }
+
+        Ok(match value.parse() {
+            Ok(index) => DeviceSelection::Index(index),
+            Err(_) => DeviceSelection::Name(value.to_owned()),
+        })
+    }
+}
+//index:N and name:X are explicit, a bare value is an index if it parses as one, so numeric names need name:
+
+pub(crate) struct DeviceRequirements<'a> {
+    pub extensions: &'a [&'a CStr],
+    pub features: vk::PhysicalDeviceFeatures,
+    pub surface: Option<(&'a ash::khr::surface::Instance, vk::SurfaceKHR)>,
+    pub extent: Option<vk::Extent2D>,
+}
+//extent is only known upfront for offscreen targets, swapchains get clamped to the surface instead
+
+struct Candidate {
+    physical_device: vk::PhysicalDevice,
+    queue_family_index: usize,
+    name: String,
+    score: (u32, vk::DeviceSize),
+}
+
+fn type_rank(device_type: vk::PhysicalDeviceType) -> u32 {
+    match device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => 4,
+        vk::PhysicalDeviceType::INTEGRATED_GPU => 3,
+        vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
+        vk::PhysicalDeviceType::CPU => 1,
+        _ => 0,
+    }
+}
+
+fn feature_bits(features: &vk::PhysicalDeviceFeatures) -> &[vk::Bool32] {
+    unsafe {
+        std::slice::from_raw_parts(
+            features as *const vk::PhysicalDeviceFeatures as *const vk::Bool32,
+            std::mem::size_of::<vk::PhysicalDeviceFeatures>() / std::mem::size_of::<vk::Bool32>(),
+        )
+    }
+}
+//PhysicalDeviceFeatures is nothing but Bool32 fields
+
+fn evaluate(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    requirements: &DeviceRequirements,
+) -> Result<Candidate, String> {
+    let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+    let name = properties
+        .device_name_as_c_str()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let available_extensions = unsafe {
+        instance
+            .enumerate_device_extension_properties(physical_device)
+            .map_err(|err| format!("couldnt enumerate extensions ({})", err))?
+    };
+    if let Some(missing) = requirements.extensions.iter().find(|&&required| {
+        !available_extensions
+            .iter()
+            .any(|extension| extension.extension_name_as_c_str() == Ok(required))
+    }) {
+        return Err(format!("missing extension {:?}", missing));
+    }
+
+    let supported_features = unsafe { instance.get_physical_device_features(physical_device) };
+    let missing_features = feature_bits(&requirements.features)
+        .iter()
+        .zip(feature_bits(&supported_features))
+        .filter(|(&required, &supported)| required == vk::TRUE && supported != vk::TRUE)
+        .count();
+    if missing_features > 0 {
+        return Err(format!("missing {} required feature/s", missing_features));
+    }
+
+    let limits = &properties.limits;
+    if limits.max_push_constants_size < CameraPushConstants::SIZE {
+        return Err(format!(
+            "max_push_constants_size {} is below {}",
+            limits.max_push_constants_size,
+            CameraPushConstants::SIZE
+        ));
+    }
+    if let Some(extent) = requirements.extent {
+        if extent.width.max(extent.height) > limits.max_image_dimension2_d {
+            return Err(format!(
+                "max_image_dimension2_d {} is too small for {}x{}",
+                limits.max_image_dimension2_d, extent.width, extent.height
+            ));
+        }
+    }
+
+    DepthBuffer::find_format(instance, physical_device)
+        .map_err(|_| "no supported depth format".to_owned())?;
+
+    if let Some((surface_loader, surface)) = requirements.surface {
+        let formats =
+            unsafe { surface_loader.get_physical_device_surface_formats(physical_device, surface) }
+                .unwrap_or_default();
+        let present_modes = unsafe {
+            surface_loader.get_physical_device_surface_present_modes(physical_device, surface)
+        }
+        .unwrap_or_default();
+        if formats.is_empty() || present_modes.is_empty() {
+            return Err("no surface formats or present modes".to_owned());
+        }
+    }
+
+    let queue_family_index =
+        unsafe { instance.get_physical_device_queue_family_properties(physical_device) }
+            .iter()
+            .enumerate()
+            .position(|(i, family)| {
+                family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+                    && requirements
+                        .surface
+                        .is_none_or(|(surface_loader, surface)| unsafe {
+                            surface_loader
+                                .get_physical_device_surface_support(
+                                    physical_device,
+                                    i as u32,
+                                    surface,
+                                )
+                                .unwrap_or(false)
+                        })
+            })
+            .ok_or_else(|| "no graphics queue family that can present".to_owned())?;
+
+    let memory_properties =
+        unsafe { instance.get_physical_device_memory_properties(physical_device) };
+    let device_local_memory = memory_properties
+        .memory_heaps_as_slice()
+        .iter()
+        .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+        .map(|heap| heap.size)
+        .sum();
+
+    Ok(Candidate {
+        physical_device,
+        queue_family_index,
+        name,
+        score: (type_rank(properties.device_type), device_local_memory),
+    })
+}
+//Err holds why the device cant be used
+
+pub(crate) fn select_physical_device(
+    instance: &ash::Instance,
+    requirements: &DeviceRequirements,
+    selection: &DeviceSelection,
+) -> LyeResult<(vk::PhysicalDevice, usize)> {
+    let physical_devices =
+        unsafe { instance.enumerate_physical_devices() }.context("Enumerating physical devices")?;
+
+    log::info!("{} Vulkan device/s", physical_devices.len());
+
+    let candidates: Vec<_> = physical_devices
+        .iter()
+        .enumerate()
+        .map(|(index, &physical_device)| {
+            let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+            let candidate = evaluate(instance, physical_device, requirements);
+            match &candidate {
+                Ok(candidate) => log::info!(
+                    "[{}] {:?} {:?}, score {:?}",
+                    index,
+                    candidate.name,
+                    properties.device_type,
+                    candidate.score
+                ),
+                Err(reason) => log::info!(
+                    "[{}] {:?} {:?}, unsuitable : {}",
+                    index,
+                    properties.device_name_as_c_str().unwrap_or_default(),
+                    properties.device_type,
+                    reason
+                ),
+            }
+            (index, candidate)
+        })
+        .collect();
+
+    let chosen = choose(candidates, selection)?;
+
+    log::info!(
+        "Using {:?}, Q family : {}",
+        chosen.name,
+        chosen.queue_family_index
+    );
+
+    Ok((chosen.physical_device, chosen.queue_family_index))
+}
+//Discrete GPUs win over integrated ones, ties are broken by the amount of device local memory
+
+fn choose(
+    candidates: Vec<(usize, Result<Candidate, String>)>,
+    selection: &DeviceSelection,
+) -> LyeResult<Candidate> {
+    Ok(match selection {
+        DeviceSelection::Auto => candidates
+            .into_iter()
+            .filter_map(|(_, candidate)| candidate.ok())
+            .max_by_key(|candidate| candidate.score)
+            .ok_or_else(|| {
+                LyeError::DeviceSelection("Couldnt find a suitable physical device".to_owned())
+            })?,
+        DeviceSelection::Index(wanted) => candidates
+            .into_iter()
+            .find(|(index, _)| index == wanted)
+            .ok_or_else(|| {
+                LyeError::DeviceSelection(format!(
+                    "There is no physical device with index {}",
+                    wanted
+                ))
+            })?
+            .1
+            .map_err(|reason| {
+                LyeError::DeviceSelection(format!(
+                    "Physical device {} is unsuitable : {}",
+                    wanted, reason
+                ))
+            })?,
+        DeviceSelection::Name(wanted) => {
+            let wanted_lowercase = wanted.to_lowercase();
+            candidates
+                .into_iter()
+                .filter_map(|(_, candidate)| candidate.ok())
+                .filter(|candidate| candidate.name.to_lowercase().contains(&wanted_lowercase))
+                .max_by_key(|candidate| candidate.score)
+                .ok_or_else(|| {
+                    LyeError::DeviceSelection(format!(
+                        "No suitable physical device matches {:?}",
+                        wanted
+                    ))
+                })?
+        }
+    })
+}
+//An explicit selection never silently falls back to another device
+
+pub(crate) fn enable_optional_features(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    required: &vk::PhysicalDeviceFeatures,
+    optional: &vk::PhysicalDeviceFeatures,
+) -> vk::PhysicalDeviceFeatures {
+    let supported = unsafe { instance.get_physical_device_features(physical_device) };
+    let mut enabled = *required;
+    let enabled_bits = unsafe {
+        std::slice::from_raw_parts_mut(
+            &mut enabled as *mut vk::PhysicalDeviceFeatures as *mut vk::Bool32,
+            std::mem::size_of::<vk::PhysicalDeviceFeatures>() / std::mem::size_of::<vk::Bool32>(),
+        )
+    };
+
+    let (mut requested, mut granted) = (0, 0);
+    for ((enabled, &optional), &supported) in enabled_bits
+        .iter_mut()
+        .zip(feature_bits(optional))
+        .zip(feature_bits(&supported))
+    {
+        if optional == vk::TRUE {
+            requested += 1;
+            if supported == vk::TRUE {
+                granted += 1;
+                *enabled = vk::TRUE;
+            }
+        }
+    }
+    if requested > 0 {
+        log::info!("Optional features : {}/{} supported", granted, requested);
+    }
+
+    enabled
+}
+//Required features were already checked by select_physical_device, so they are copied as is
+
+pub(crate) fn enable_optional_extensions(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    optional: &[&'static CStr],
+) -> Vec<&'static CStr> {
+    let available_extensions =
+        unsafe { instance.enumerate_device_extension_properties(physical_device) }
+            .unwrap_or_default();
+
+    optional
+        .iter()
+        .copied()
+        .filter(|&extension| {
+            let supported = available_extensions
+                .iter()
+                .any(|available| available.extension_name_as_c_str() == Ok(extension));
+            match supported {
+                true => log::info!("Optional extension {:?} enabled", extension),
+                false => log::warn!("Optional extension {:?} is unsupported", extension),
+            }
+            supported
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(
+        name: &str,
+        device_type: vk::PhysicalDeviceType,
+        memory: vk::DeviceSize,
+    ) -> Result<Candidate, String> {
+        Ok(Candidate {
+            physical_device: vk::PhysicalDevice::null(),
+            queue_family_index: 0,
+            name: name.to_owned(),
+            score: (type_rank(device_type), memory),
+        })
+    }
+
+    fn devices() -> Vec<(usize, Result<Candidate, String>)> {
+        vec![
+            (
+                0,
+                candidate("Intel UHD", vk::PhysicalDeviceType::INTEGRATED_GPU, 8 << 30),
+            ),
+            (
+                1,
+                candidate("Radeon 7900", vk::PhysicalDeviceType::DISCRETE_GPU, 4 << 30),
+            ),
+            (2, candidate("7900", vk::PhysicalDeviceType::CPU, 16 << 30)),
+            (3, Err("missing extension".to_owned())),
+            (
+                4,
+                candidate(
+                    "Radeon 7900",
+                    vk::PhysicalDeviceType::DISCRETE_GPU,
+                    24 << 30,
+                ),
+            ),
+        ]
+    }
+
+    fn chosen_name(selection: DeviceSelection) -> LyeResult<(String, vk::DeviceSize)> {
+        choose(devices(), &selection).map(|candidate| (candidate.name, candidate.score.1))
+    }
+
+    #[test]
+    fn parses_indices_and_names() {
+        let parse = |value: &str| value.parse::<DeviceSelection>().unwrap();
+        assert_eq!(parse("1"), DeviceSelection::Index(1));
+        assert_eq!(parse("index:2"), DeviceSelection::Index(2));
+        assert_eq!(parse("radeon"), DeviceSelection::Name("radeon".to_owned()));
+        assert_eq!(parse("name:7900"), DeviceSelection::Name("7900".to_owned()));
+        assert!("index:radeon".parse::<DeviceSelection>().is_err());
+    }
+
+    #[test]
+    fn auto_prefers_device_type_then_memory() {
+        assert_eq!(
+            chosen_name(DeviceSelection::Auto).unwrap(),
+            ("Radeon 7900".to_owned(), 24 << 30)
+        );
+        assert!(
+            type_rank(vk::PhysicalDeviceType::INTEGRATED_GPU)
+                > type_rank(vk::PhysicalDeviceType::CPU)
+        );
+        assert!(choose(
+            vec![(0, Err("unsuitable".to_owned()))],
+            &DeviceSelection::Auto
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn index_selects_exactly_that_device() {
+        assert_eq!(
+            chosen_name(DeviceSelection::Index(2)).unwrap(),
+            ("7900".to_owned(), 16 << 30)
+        );
+        assert!(chosen_name(DeviceSelection::Index(3)).is_err());
+        assert!(chosen_name(DeviceSelection::Index(5)).is_err());
+    }
+    //An unsuitable or missing index fails instead of picking another device
+
+    #[test]
+    fn name_matches_substrings_case_insensitively() {
+        assert_eq!(
+            chosen_name(DeviceSelection::Name("intel".to_owned())).unwrap(),
+            ("Intel UHD".to_owned(), 8 << 30)
+        );
+        assert_eq!(
+            chosen_name(DeviceSelection::Name("7900".to_owned())).unwrap(),
+            ("Radeon 7900".to_owned(), 24 << 30)
+        );
+        assert!(chosen_name(DeviceSelection::Name("geforce".to_owned())).is_err());
+    }
+}
@@ -0,0 +1,339 @@
+use crate::depth::DepthBuffer;
+use ash::{self, vk};
+use std::{ffi::CStr, marker::PhantomData, os::raw::c_void, ptr};
+
+const EXTENSIONS: [&CStr; 2] = [
+    ash::khr::dynamic_rendering::NAME,
+    ash::khr::synchronization2::NAME,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum DynamicRenderingSupport {
+    Core,
+    Extensions,
+}
+//Core needs a Vulkan 1.3 instance and device, the extensions work from 1.2 on
+
+impl DynamicRenderingSupport {
+    pub(crate) fn query(
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+    ) -> Option<Self> {
+        let instance_version = unsafe { entry.try_enumerate_instance_version() }
+            .ok()
+            .flatten()
+            .unwrap_or(vk::API_VERSION_1_0);
+        let device_version =
+            unsafe { instance.get_physical_device_properties(physical_device) }.api_version;
+        if instance_version < vk::API_VERSION_1_1 || device_version < vk::API_VERSION_1_2 {
+            return None;
+        }
+        //Querying the features needs vkGetPhysicalDeviceFeatures2, VK_KHR_dynamic_rendering needs 1.2 to skip its dependencies
+
+        if instance_version >= vk::API_VERSION_1_3 && device_version >= vk::API_VERSION_1_3 {
+            let mut vulkan13_features = vk::PhysicalDeviceVulkan13Features::default();
+            let mut features =
+                vk::PhysicalDeviceFeatures2::default().push_next(&mut vulkan13_features);
+            unsafe { instance.get_physical_device_features2(physical_device, &mut features) };
+            if vulkan13_features.dynamic_rendering == vk::TRUE
+                && vulkan13_features.synchronization2 == vk::TRUE
+            {
+                return Some(DynamicRenderingSupport::Core);
+            }
+        }
+
+        let available = unsafe { instance.enumerate_device_extension_properties(physical_device) }
+            .unwrap_or_default();
+        let has_extensions = EXTENSIONS.iter().all(|&extension| {
+            available
+                .iter()
+                .any(|properties| properties.extension_name_as_c_str() == Ok(extension))
+        });
+        if !has_extensions {
+            return None;
+        }
+
+        let mut dynamic_rendering_features = vk::PhysicalDeviceDynamicRenderingFeatures::default();
+        let mut synchronization2_features = vk::PhysicalDeviceSynchronization2Features::default();
+        let mut features = vk::PhysicalDeviceFeatures2::default()
+            .push_next(&mut dynamic_rendering_features)
+            .push_next(&mut synchronization2_features);
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features) };
+        (dynamic_rendering_features.dynamic_rendering == vk::TRUE
+            && synchronization2_features.synchronization2 == vk::TRUE)
+            .then_some(DynamicRenderingSupport::Extensions)
+    }
+
+    pub(crate) fn extensions(self) -> &'static [&'static CStr] {
+        match self {
+            DynamicRenderingSupport::Core => &[],
+            DynamicRenderingSupport::Extensions => &EXTENSIONS,
+        }
+    }
+}
+
+pub(crate) struct FeatureChain {
+    vulkan13: vk::PhysicalDeviceVulkan13Features<'static>,
+    dynamic_rendering: vk::PhysicalDeviceDynamicRenderingFeatures<'static>,
+    synchronization2: vk::PhysicalDeviceSynchronization2Features<'static>,
+}
+//Owns the structs enabling the features at device creation, so the p_next pointers stay valid
+
+impl FeatureChain {
+    pub(crate) fn new() -> Self {
+        Self {
+            vulkan13: vk::PhysicalDeviceVulkan13Features {
+                dynamic_rendering: vk::TRUE,
+                synchronization2: vk::TRUE,
+                ..Default::default()
+            },
+            dynamic_rendering: vk::PhysicalDeviceDynamicRenderingFeatures {
+                dynamic_rendering: vk::TRUE,
+                ..Default::default()
+            },
+            synchronization2: vk::PhysicalDeviceSynchronization2Features {
+                synchronization2: vk::TRUE,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub(crate) fn p_next(&mut self, support: Option<DynamicRenderingSupport>) -> *const c_void {
+        match support {
+            Some(DynamicRenderingSupport::Core) => {
+                &self.vulkan13 as *const vk::PhysicalDeviceVulkan13Features as *const c_void
+            }
+            Some(DynamicRenderingSupport::Extensions) => {
+                self.dynamic_rendering.p_next = &mut self.synchronization2
+                    as *mut vk::PhysicalDeviceSynchronization2Features
+                    as *mut c_void;
+                &self.dynamic_rendering as *const vk::PhysicalDeviceDynamicRenderingFeatures
+                    as *const c_void
+            }
+            None => ptr::null(),
+        }
+    }
+}
+
+pub(crate) enum DynamicRendering {
+    Core,
+    Extensions {
+        dynamic_rendering: ash::khr::dynamic_rendering::Device,
+        synchronization2: ash::khr::synchronization2::Device,
+    },
+}
+
+pub(crate) struct Attachments<'a> {
+    pub(crate) color_image: vk::Image,
+    pub(crate) color_view: vk::ImageView,
+    pub(crate) depth_buffer: &'a DepthBuffer,
+    pub(crate) depth_format: vk::Format,
+    pub(crate) clear_color: [f32; 4],
+    pub(crate) clear_depth: f32,
+}
+
+impl DynamicRendering {
+    pub(crate) fn new(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        support: DynamicRenderingSupport,
+    ) -> Self {
+        match support {
+            DynamicRenderingSupport::Core => DynamicRendering::Core,
+            DynamicRenderingSupport::Extensions => DynamicRendering::Extensions {
+                dynamic_rendering: ash::khr::dynamic_rendering::Device::new(instance, device),
+                synchronization2: ash::khr::synchronization2::Device::new(instance, device),
+            },
+        }
+    }
+
+    unsafe fn pipeline_barrier(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        image_barriers: &[vk::ImageMemoryBarrier2],
+    ) {
+        let dependency_info = vk::DependencyInfo {
+            s_type: vk::StructureType::DEPENDENCY_INFO,
+            p_next: ptr::null(),
+            dependency_flags: vk::DependencyFlags::empty(),
+            memory_barrier_count: 0,
+            p_memory_barriers: ptr::null(),
+            buffer_memory_barrier_count: 0,
+            p_buffer_memory_barriers: ptr::null(),
+            image_memory_barrier_count: image_barriers.len() as u32,
+            p_image_memory_barriers: image_barriers.as_ptr(),
+            _marker: PhantomData,
+        };
+        match self {
+            DynamicRendering::Core => {
+                device.cmd_pipeline_barrier2(command_buffer, &dependency_info)
+            }
+            DynamicRendering::Extensions {
+                synchronization2, ..
+            } => synchronization2.cmd_pipeline_barrier2(command_buffer, &dependency_info),
+        }
+    }
+
+    pub(crate) unsafe fn begin(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        extent: vk::Extent2D,
+        attachments: &Attachments,
+    ) {
+        let image_barrier =
+            |image, aspect_mask, stages, access, new_layout| vk::ImageMemoryBarrier2 {
+                s_type: vk::StructureType::IMAGE_MEMORY_BARRIER_2,
+                p_next: ptr::null(),
+                src_stage_mask: stages,
+                src_access_mask: match stages {
+                    vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT => vk::AccessFlags2::NONE,
+                    _ => vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                },
+                dst_stage_mask: stages,
+                dst_access_mask: access,
+                old_layout: vk::ImageLayout::UNDEFINED,
+                new_layout,
+                src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                image,
+                subresource_range: vk::ImageSubresourceRange {
+                    aspect_mask,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                _marker: PhantomData,
+            };
+        let image_barriers = [
+            image_barrier(
+                attachments.color_image,
+                vk::ImageAspectFlags::COLOR,
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags2::COLOR_ATTACHMENT_READ | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            ),
+            image_barrier(
+                attachments.depth_buffer.image(),
+                DepthBuffer::aspect_mask(attachments.depth_format),
+                vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            ),
+        ];
+        //The color barrier chains onto the acquire semaphore wait at COLOR_ATTACHMENT_OUTPUT,
+        //the depth one waits for the last pass writing the same depth image, same as the render pass dependency
+        self.pipeline_barrier(device, command_buffer, &image_barriers);
+
+        let color_attachments = [vk::RenderingAttachmentInfo {
+            s_type: vk::StructureType::RENDERING_ATTACHMENT_INFO,
+            p_next: ptr::null(),
+            image_view: attachments.color_view,
+            image_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            resolve_mode: vk::ResolveModeFlags::NONE,
+            resolve_image_view: vk::ImageView::null(),
+            resolve_image_layout: vk::ImageLayout::UNDEFINED,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            store_op: vk::AttachmentStoreOp::STORE,
+            clear_value: vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: attachments.clear_color,
+                },
+            },
+            _marker: PhantomData,
+        }];
+        let depth_attachment = vk::RenderingAttachmentInfo {
+            s_type: vk::StructureType::RENDERING_ATTACHMENT_INFO,
+            p_next: ptr::null(),
+            image_view: attachments.depth_buffer.view(),
+            image_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            resolve_mode: vk::ResolveModeFlags::NONE,
+            resolve_image_view: vk::ImageView::null(),
+            resolve_image_layout: vk::ImageLayout::UNDEFINED,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            store_op: vk::AttachmentStoreOp::DONT_CARE,
+            clear_value: vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: attachments.clear_depth,
+                    stencil: 0,
+                },
+            },
+            _marker: PhantomData,
+        };
+        let rendering_info = vk::RenderingInfo {
+            s_type: vk::StructureType::RENDERING_INFO,
+            p_next: ptr::null(),
+            flags: vk::RenderingFlags::empty(),
+            render_area: vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            },
+            layer_count: 1,
+            view_mask: 0,
+            color_attachment_count: color_attachments.len() as u32,
+            p_color_attachments: color_attachments.as_ptr(),
+            p_depth_attachment: &depth_attachment,
+            p_stencil_attachment: ptr::null(),
+            _marker: PhantomData,
+        };
+        //Same load and store ops as the render pass, depth is only needed while rendering
+
+        match self {
+            DynamicRendering::Core => device.cmd_begin_rendering(command_buffer, &rendering_info),
+            DynamicRendering::Extensions {
+                dynamic_rendering, ..
+            } => dynamic_rendering.cmd_begin_rendering(command_buffer, &rendering_info),
+        }
+    }
+
+    pub(crate) unsafe fn end(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        color_image: vk::Image,
+        final_layout: vk::ImageLayout,
+    ) {
+        match self {
+            DynamicRendering::Core => device.cmd_end_rendering(command_buffer),
+            DynamicRendering::Extensions {
+                dynamic_rendering, ..
+            } => dynamic_rendering.cmd_end_rendering(command_buffer),
+        }
+
+        let (dst_stage_mask, dst_access_mask) = match final_layout {
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL => (
+                vk::PipelineStageFlags2::COPY,
+                vk::AccessFlags2::TRANSFER_READ,
+            ),
+            _ => (vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE),
+        };
+        //Presenting is ordered by the render finished semaphore, a copy has to wait here
+        let image_barriers = [vk::ImageMemoryBarrier2 {
+            s_type: vk::StructureType::IMAGE_MEMORY_BARRIER_2,
+            p_next: ptr::null(),
+            src_stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            src_access_mask: vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+            dst_stage_mask,
+            dst_access_mask,
+            old_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            new_layout: final_layout,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image: color_image,
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            _marker: PhantomData,
+        }];
+        self.pipeline_barrier(device, command_buffer, &image_barriers);
+    }
+}
@@ -0,0 +1,172 @@
+use ash::vk;
+use std::fmt;
+
+/// Everything that can go wrong in lye.
+#[derive(Debug)]
+pub enum LyeError {
+    /// The Vulkan loader couldnt be found or loaded.
+    Loader(ash::LoadingError),
+    /// The winit event loop failed.
+    EventLoop(winit::error::EventLoopError),
+    /// The window couldnt be created.
+    Window(winit::error::OsError),
+    /// The window or display handle isnt available.
+    WindowHandle(winit::raw_window_handle::HandleError),
+    /// The window system has no Vulkan surface support in lye.
+    UnsupportedDisplay(String),
+    /// Creating the instance or its debug messenger failed.
+    Instance(vk::Result),
+    /// Creating or querying the surface failed.
+    Surface(vk::Result),
+    /// No physical device fits, or the requested one doesnt.
+    DeviceSelection(String),
+    /// Creating the logical device failed.
+    Device(vk::Result),
+    /// Creating the swapchain or its images failed.
+    Swapchain(vk::Result),
+    /// A shader isnt valid SPIR-V.
+    Shader {
+        /// File name of the shader.
+        name: &'static str,
+        /// Why it couldnt be read.
+        source: std::io::Error,
+    },
+    /// Shaders dont match each other, the vertex layout or a uniform block.
+    ShaderInterface(String),
+    /// Creating the pipeline or one of its parts failed.
+    Pipeline(vk::Result),
+    /// A format cant be used for what it was requested for.
+    UnsupportedFormat {
+        /// The requested format.
+        format: vk::Format,
+        /// What the format is missing.
+        reason: &'static str,
+    },
+    /// Mesh data is empty or has out of bounds indices.
+    InvalidMesh(String),
+    /// The requested configuration cant work.
+    InvalidConfig(String),
+    /// A headless only operation was called on a windowed renderer.
+    NotHeadless,
+    /// Validation layer errors reported while strict validation is on.
+    Validation(Vec<String>),
+    /// Any other failed Vulkan call.
+    Vulkan {
+        /// The operation that failed.
+        context: &'static str,
+        /// What Vulkan returned.
+        result: vk::Result,
+    },
+}
+//Vulkan covers everything without a dedicated variant, context names the operation that failed
+
+/// Result with a [`LyeError`].
+pub type LyeResult<T> = Result<T, LyeError>;
+
+impl fmt::Display for LyeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LyeError::Loader(err) => write!(f, "Couldnt load Vulkan : {}", err),
+            LyeError::EventLoop(err) => write!(f, "Event loop error : {}", err),
+            LyeError::Window(err) => write!(f, "Couldnt create window : {}", err),
+            LyeError::WindowHandle(err) => write!(f, "Window handle unavailable : {}", err),
+            LyeError::UnsupportedDisplay(display) => {
+                write!(f, "Unsupported display handle : {}", display)
+            }
+            LyeError::Instance(result) => write!(f, "Couldnt create instance : {}", result),
+            LyeError::Surface(result) => write!(f, "Surface error : {}", result),
+            LyeError::DeviceSelection(reason) => write!(f, "Device selection failed : {}", reason),
+            LyeError::Device(result) => write!(f, "Couldnt create device : {}", result),
+            LyeError::Swapchain(result) => write!(f, "Swapchain error : {}", result),
+            LyeError::Shader { name, source } => {
+                write!(f, "Invalid SPIR-V in shader {} : {}", name, source)
+            }
+            LyeError::ShaderInterface(reason) => {
+                write!(f, "Shader interface mismatch : {}", reason)
+            }
+            LyeError::Pipeline(result) => write!(f, "Couldnt create pipeline : {}", result),
+            LyeError::UnsupportedFormat { format, reason } => {
+                write!(f, "Unsupported format {:?} : {}", format, reason)
+            }
+            LyeError::InvalidMesh(reason) => write!(f, "Invalid mesh : {}", reason),
+            LyeError::InvalidConfig(reason) => write!(f, "Invalid configuration : {}", reason),
+            LyeError::NotHeadless => write!(f, "Only headless renderers can read frames back"),
+            LyeError::Validation(errors) => {
+                write!(f, "{} validation error/s", errors.len())?;
+                errors
+                    .iter()
+                    .try_for_each(|error| write!(f, "\n  {}", error))
+            }
+            LyeError::Vulkan { context, result } => write!(f, "{} failed : {}", context, result),
+        }
+    }
+}
+
+impl std::error::Error for LyeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LyeError::Loader(err) => Some(err),
+            LyeError::EventLoop(err) => Some(err),
+            LyeError::Window(err) => Some(err),
+            LyeError::WindowHandle(err) => Some(err),
+            LyeError::Shader { source, .. } => Some(source),
+            LyeError::Instance(result)
+            | LyeError::Surface(result)
+            | LyeError::Device(result)
+            | LyeError::Swapchain(result)
+            | LyeError::Pipeline(result)
+            | LyeError::Vulkan { result, .. } => Some(result),
+            _ => None,
+        }
+    }
+}
+
+impl From<ash::LoadingError> for LyeError {
+    fn from(err: ash::LoadingError) -> Self {
+        LyeError::Loader(err)
+    }
+}
+
+impl From<winit::error::EventLoopError> for LyeError {
+    fn from(err: winit::error::EventLoopError) -> Self {
+        LyeError::EventLoop(err)
+    }
+}
+
+impl From<winit::error::OsError> for LyeError {
+    fn from(err: winit::error::OsError) -> Self {
+        LyeError::Window(err)
+    }
+}
+
+impl From<winit::raw_window_handle::HandleError> for LyeError {
+    fn from(err: winit::raw_window_handle::HandleError) -> Self {
+        LyeError::WindowHandle(err)
+    }
+}
+
+impl LyeError {
+    /// The Vulkan result behind the error, if there is one.
+    pub fn result(&self) -> Option<vk::Result> {
+        match self {
+            LyeError::Instance(result)
+            | LyeError::Surface(result)
+            | LyeError::Device(result)
+            | LyeError::Swapchain(result)
+            | LyeError::Pipeline(result)
+            | LyeError::Vulkan { result, .. } => Some(*result),
+            _ => None,
+        }
+    }
+    //Lets callers react to things like ERROR_DEVICE_LOST without matching every variant
+}
+
+pub(crate) trait Context<T> {
+    fn context(self, context: &'static str) -> LyeResult<T>;
+}
+
+impl<T> Context<T> for ash::prelude::VkResult<T> {
+    fn context(self, context: &'static str) -> LyeResult<T> {
+        self.map_err(|result| LyeError::Vulkan { context, result })
+    }
+}
@@ -0,0 +1,184 @@
+use crate::{
+    camera::{Camera, CameraPushConstants},
+    debug::DebugUtils,
+    descriptors::FrameDescriptors,
+    mesh::{Mesh, MeshId},
+    pipeline::GraphicsPipeline,
+};
+use ash::{self, prelude::VkResult, vk};
+use std::{marker::PhantomData, ptr};
+
+/// Frames the CPU may record ahead of the GPU unless configured otherwise.
+pub const DEFAULT_FRAMES_IN_FLIGHT: usize = 2;
+
+pub(crate) struct FrameContext {
+    pub image_available: vk::Semaphore,
+    pub in_flight: vk::Fence,
+    pub command_pool: vk::CommandPool,
+    pub command_buffer: vk::CommandBuffer,
+    pub descriptors: FrameDescriptors,
+}
+//Everything the CPU may only reuse once the GPU finished the frame that used it,
+//descriptors hold allocator memory and are destroyed by the renderer before the frame
+
+impl FrameContext {
+    pub(crate) fn new(device: &ash::Device, queue_family_index: usize) -> VkResult<Self> {
+        let semaphore_create_info = vk::SemaphoreCreateInfo {
+            s_type: vk::StructureType::SEMAPHORE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::SemaphoreCreateFlags::empty(),
+            _marker: PhantomData,
+        };
+        let fence_create_info = vk::FenceCreateInfo {
+            s_type: vk::StructureType::FENCE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::FenceCreateFlags::SIGNALED,
+            _marker: PhantomData,
+        };
+        //Signaled so the first wait on a fresh frame returns immediately
+        let command_pool_create_info = vk::CommandPoolCreateInfo {
+            s_type: vk::StructureType::COMMAND_POOL_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::CommandPoolCreateFlags::TRANSIENT,
+            queue_family_index: queue_family_index as u32,
+            _marker: PhantomData,
+        };
+        //Reset as a whole every frame, so no RESET_COMMAND_BUFFER
+
+        unsafe {
+            let mut frame = Self {
+                image_available: device.create_semaphore(&semaphore_create_info, None)?,
+                in_flight: vk::Fence::null(),
+                command_pool: vk::CommandPool::null(),
+                command_buffer: vk::CommandBuffer::null(),
+                descriptors: FrameDescriptors::default(),
+            };
+            let result = (|| -> VkResult<()> {
+                frame.in_flight = device.create_fence(&fence_create_info, None)?;
+                frame.command_pool = device.create_command_pool(&command_pool_create_info, None)?;
+                let allocate_info = vk::CommandBufferAllocateInfo {
+                    s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
+                    p_next: ptr::null(),
+                    command_pool: frame.command_pool,
+                    level: vk::CommandBufferLevel::PRIMARY,
+                    command_buffer_count: 1,
+                    _marker: PhantomData,
+                };
+                frame.command_buffer = device.allocate_command_buffers(&allocate_info)?[0];
+                Ok(())
+            })();
+
+            match result {
+                Ok(()) => Ok(frame),
+                Err(err) => {
+                    frame.destroy(device);
+                    Err(err)
+                }
+            }
+        }
+    }
+    //Null handles are ignored by the destroy functions, so a partially created frame can be destroyed
+
+    pub(crate) fn create_ring(
+        device: &ash::Device,
+        queue_family_index: usize,
+        count: usize,
+    ) -> VkResult<Vec<Self>> {
+        let mut frames = Vec::with_capacity(count);
+        for _ in 0..count {
+            match FrameContext::new(device, queue_family_index) {
+                Ok(frame) => frames.push(frame),
+                Err(err) => {
+                    frames.iter().for_each(|frame| frame.destroy(device));
+                    return Err(err);
+                }
+            }
+        }
+        Ok(frames)
+    }
+    //The frames created before a failure are destroyed, the failed one cleans up after itself
+
+    pub(crate) fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_command_pool(self.command_pool, None);
+            device.destroy_semaphore(self.image_available, None);
+            device.destroy_fence(self.in_flight, None);
+        };
+    }
+    //Destroying the pool frees its command buffer
+}
+
+/// Records draws into the current frame, see [`crate::Renderer::draw_with`].
+pub struct DrawContext<'a> {
+    pub(crate) device: &'a ash::Device,
+    pub(crate) command_buffer: vk::CommandBuffer,
+    pub(crate) pipeline: &'a GraphicsPipeline,
+    pub(crate) meshes: &'a [Mesh],
+    pub(crate) extent: vk::Extent2D,
+    pub(crate) debug_utils: &'a DebugUtils,
+}
+//Handed to Renderer::draw_with while the render pass is open, the renderers pipeline is bound
+
+impl DrawContext<'_> {
+    /// Draws a mesh with the current camera.
+    ///
+    /// # Panics
+    ///
+    /// If `id` came from a different renderer and is out of range for the one recording this frame.
+    pub fn draw_mesh(&mut self, id: MeshId) {
+        self.meshes[id.0].draw(self.device, self.command_buffer);
+    }
+
+    /// Camera for the following draws.
+    pub fn set_camera(&mut self, camera: &Camera) {
+        if self.pipeline.push_constant_stages.is_empty() {
+            return;
+        }
+        unsafe {
+            self.device.cmd_push_constants(
+                self.command_buffer,
+                self.pipeline.layout,
+                self.pipeline.push_constant_stages,
+                0,
+                CameraPushConstants::from(camera).as_bytes(),
+            )
+        };
+    }
+    //Only affects the draws recorded after it, the renderers camera is restored next frame
+
+    /// Opens a named debug region.
+    pub fn begin_label(&mut self, name: &str, color: [f32; 4]) {
+        self.debug_utils
+            .begin_label(self.command_buffer, name, color);
+    }
+
+    /// Closes the last debug region.
+    pub fn end_label(&mut self) {
+        self.debug_utils.end_label(self.command_buffer);
+    }
+
+    /// Wraps the draws recorded by f in a debug region.
+    pub fn label<R>(&mut self, name: &str, f: impl FnOnce(&mut Self) -> R) -> R {
+        self.begin_label(name, [0.0; 4]);
+        let result = f(self);
+        self.end_label();
+        result
+    }
+    //Regions show up in captures and validation messages, they do nothing without VK_EXT_debug_utils
+
+    /// Size of the frame being drawn.
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    /// The logical device.
+    pub fn device(&self) -> &ash::Device {
+        self.device
+    }
+
+    /// The command buffer of the frame.
+    pub fn command_buffer(&self) -> vk::CommandBuffer {
+        self.command_buffer
+    }
+    //Raw access for anything not wrapped, the command buffer has to be left inside the same render pass
+}
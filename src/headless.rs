@@ -0,0 +1,197 @@
+use crate::{
+    error::{Context, LyeError, LyeResult},
+    memory::{Allocation, Allocator, MemoryLocation},
+};
+use ash::{self, vk};
+use std::{marker::PhantomData, ptr};
+
+pub struct OffscreenTarget {
+    extent: vk::Extent2D,
+    image_allocation: Allocation,
+    readback_buffer: vk::Buffer,
+    readback_allocation: Allocation,
+    readback_size: vk::DeviceSize,
+}
+
+impl OffscreenTarget {
+    pub fn new(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        format: vk::Format,
+        extent: vk::Extent2D,
+    ) -> LyeResult<(Self, vk::Image)> {
+        let texel_size =
+            OffscreenTarget::texel_size(format).ok_or(LyeError::UnsupportedFormat {
+                format,
+                reason: "no known texel size for readback",
+            })?;
+
+        let format_properties =
+            unsafe { instance.get_physical_device_format_properties(physical_device, format) };
+        if !format_properties
+            .optimal_tiling_features
+            .contains(vk::FormatFeatureFlags::COLOR_ATTACHMENT)
+        {
+            return Err(LyeError::UnsupportedFormat {
+                format,
+                reason: "can't be used as a color attachment",
+            });
+        }
+
+        let image_create_info = vk::ImageCreateInfo {
+            s_type: vk::StructureType::IMAGE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::ImageCreateFlags::empty(),
+            image_type: vk::ImageType::TYPE_2D,
+            format,
+            extent: vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: 1,
+            samples: vk::SampleCountFlags::TYPE_1,
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            _marker: PhantomData,
+        };
+
+        let (image, image_allocation) = allocator
+            .create_image(device, &image_create_info, MemoryLocation::GpuOnly)
+            .context("Creating the offscreen image")?;
+
+        let readback_size =
+            extent.width as vk::DeviceSize * extent.height as vk::DeviceSize * texel_size;
+        let buffer_create_info = vk::BufferCreateInfo {
+            s_type: vk::StructureType::BUFFER_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::BufferCreateFlags::empty(),
+            size: readback_size,
+            usage: vk::BufferUsageFlags::TRANSFER_DST,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+            _marker: PhantomData,
+        };
+
+        let (readback_buffer, readback_allocation) =
+            match allocator.create_buffer(device, &buffer_create_info, MemoryLocation::GpuToCpu) {
+                Ok(readback) => readback,
+                Err(err) => {
+                    allocator.destroy_image(device, image, &image_allocation);
+                    return Err(LyeError::Vulkan {
+                        context: "Creating the readback buffer",
+                        result: err,
+                    });
+                }
+            };
+
+        Ok((
+            Self {
+                extent,
+                image_allocation,
+                readback_buffer,
+                readback_allocation,
+                readback_size,
+            },
+            image,
+        ))
+    }
+
+    fn texel_size(format: vk::Format) -> Option<vk::DeviceSize> {
+        match format {
+            vk::Format::R8G8B8A8_UNORM
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::B8G8R8A8_UNORM
+            | vk::Format::B8G8R8A8_SRGB => Some(4),
+            vk::Format::R16G16B16A16_SFLOAT => Some(8),
+            vk::Format::R32G32B32A32_SFLOAT => Some(16),
+            _ => None,
+        }
+    }
+
+    pub fn record_readback(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+    ) {
+        let region = vk::BufferImageCopy {
+            buffer_offset: 0,
+            buffer_row_length: 0,
+            buffer_image_height: 0,
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+            image_extent: vk::Extent3D {
+                width: self.extent.width,
+                height: self.extent.height,
+                depth: 1,
+            },
+        };
+
+        let host_barrier = vk::BufferMemoryBarrier {
+            s_type: vk::StructureType::BUFFER_MEMORY_BARRIER,
+            p_next: ptr::null(),
+            src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+            dst_access_mask: vk::AccessFlags::HOST_READ,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            buffer: self.readback_buffer,
+            offset: 0,
+            size: vk::WHOLE_SIZE,
+            _marker: PhantomData,
+        };
+        //Makes the copied texels visible to the host once the fence is signaled
+
+        unsafe {
+            device.cmd_copy_image_to_buffer(
+                command_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                self.readback_buffer,
+                &[region],
+            );
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[host_barrier],
+                &[],
+            );
+        };
+    }
+
+    pub fn readback_buffer(&self) -> vk::Buffer {
+        self.readback_buffer
+    }
+
+    pub fn read(&self) -> Vec<u8> {
+        self.readback_allocation
+            .read(0, self.readback_size as usize)
+            .to_vec()
+    }
+
+    pub unsafe fn destroy(
+        &self,
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        image: vk::Image,
+    ) {
+        allocator.destroy_buffer(device, self.readback_buffer, &self.readback_allocation);
+        allocator.destroy_image(device, image, &self.image_allocation);
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{
+    shader_compiler::{self, CompileError, Define},
+    shaders::PipelineShaders,
+};
+use notify::Watcher;
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+
+pub(crate) struct ShaderWatcher {
+    directory: PathBuf,
+    _watcher: notify::RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+    dependencies: Vec<PathBuf>,
+    defines: Vec<Define>,
+}
+//The watcher stops when dropped, so it is kept even though only the channel is read
+
+impl ShaderWatcher {
+    pub(crate) fn new(directory: &Path) -> notify::Result<Self> {
+        let directory = directory
+            .canonicalize()
+            .map_err(|err| notify::Error::io(err).add_path(directory.to_owned()))?;
+        let defines =
+            shader_compiler::parse_defines(option_env!("LYE_SHADER_DEFINES").unwrap_or(""))
+                .map_err(|err| notify::Error::generic(&err))?;
+        //The defines the shaders were built with, so a reload only changes what was edited
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(&directory, notify::RecursiveMode::Recursive)?;
+        log::info!("Watching {} for shader changes", directory.display());
+
+        Ok(Self {
+            directory,
+            _watcher: watcher,
+            events,
+            dependencies: Vec::new(),
+            defines,
+        })
+    }
+
+    pub(crate) fn changed(&self) -> bool {
+        let mut changed = false;
+        for event in self.events.try_iter() {
+            let event = match event {
+                Ok(event) => event,
+                Err(err) => {
+                    log::warn!("Shader watcher error : {}", err);
+                    continue;
+                }
+            };
+            if !(event.kind.is_modify() || event.kind.is_create() || event.kind.is_remove()) {
+                continue;
+            }
+            changed |= event
+                .paths
+                .iter()
+                .any(|path| match self.dependencies.is_empty() {
+                    true => path.extension().is_some_and(|ext| ext == "glsl"),
+                    false => self.dependencies.contains(path),
+                });
+        }
+        changed
+    }
+    //Drains every pending event, so a burst of writes from an editor causes a single reload
+
+    pub(crate) fn compile(&mut self) -> Result<PipelineShaders, CompileError> {
+        let compile = |file_name: &str| {
+            let path = self.directory.join(file_name);
+            let stage = shader_compiler::stage_of(&path).ok_or_else(|| CompileError {
+                file: path.clone(),
+                line: 0,
+                column: 0,
+                message: "The file name doesnt say which stage it is".to_owned(),
+            })?;
+            let defines = shader_compiler::defines_for(&self.defines, file_name);
+            shader_compiler::compile(&path, stage, &defines)
+        };
+        let (vertex, fragment) = match compile("vertex.glsl")
+            .and_then(|vertex| compile("fragment.glsl").map(|fragment| (vertex, fragment)))
+        {
+            Ok(shaders) => shaders,
+            Err(err) => {
+                self.dependencies.clear();
+                return Err(err);
+            }
+        };
+        //A failed compile may have stopped before reading the include that fixes it, so until the
+        //next success any GLSL change triggers a retry
+
+        self.dependencies = vertex.dependencies;
+        self.dependencies.extend(fragment.dependencies);
+        let bytes = |words: Vec<u32>| -> Cow<'static, [u8]> {
+            Cow::Owned(words.iter().flat_map(|word| word.to_le_bytes()).collect())
+        };
+
+        Ok(PipelineShaders {
+            vertex: bytes(vertex.words),
+            fragment: bytes(fragment.words),
+        })
+    }
+    //Only the files the last successful compile read trigger the next one
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        fs, thread,
+        time::{Duration, Instant},
+    };
+
+    fn wait_for_change(watcher: &ShaderWatcher) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            if watcher.changed() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+    //Events arrive from the watcher thread, so give them time before giving up
+
+    fn settle(watcher: &ShaderWatcher) -> bool {
+        thread::sleep(Duration::from_millis(500));
+        watcher.changed()
+    }
+    //There is no event telling that nothing happened, waiting is the best a test can do
+
+    #[test]
+    fn failed_compiles_fall_back_to_every_glsl_change() {
+        let directory = std::env::temp_dir().join(format!("lye-watcher-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let shaders = Path::new(env!("CARGO_MANIFEST_DIR")).join("shaders");
+        fs::create_dir_all(directory.join("include")).unwrap();
+        for file_name in ["vertex.glsl", "fragment.glsl", "include/camera.glsl"] {
+            fs::copy(shaders.join(file_name), directory.join(file_name)).unwrap();
+        }
+        let camera = fs::read(directory.join("include/camera.glsl")).unwrap();
+
+        let mut watcher = ShaderWatcher::new(&directory).unwrap();
+        watcher.compile().unwrap();
+        assert!(!watcher.dependencies.is_empty());
+        settle(&watcher);
+
+        fs::write(directory.join("include/other.glsl"), "float unused;\n").unwrap();
+        assert!(!settle(&watcher));
+        fs::write(directory.join("include/camera.glsl"), &camera).unwrap();
+        assert!(wait_for_change(&watcher));
+        settle(&watcher);
+
+        fs::write(
+            directory.join("fragment.glsl"),
+            "#version 450\nvoid main() {\n",
+        )
+        .unwrap();
+        assert!(watcher.compile().is_err());
+        assert!(watcher.dependencies.is_empty());
+        settle(&watcher);
+
+        fs::write(
+            directory.join("include/other.glsl"),
+            "float still_unused;\n",
+        )
+        .unwrap();
+        assert!(wait_for_change(&watcher));
+
+        drop(watcher);
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
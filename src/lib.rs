@@ -0,0 +1,51 @@
+//! Lye is a small Vulkan renderer built on ash and winit.
+//!
+//! Implement [`Application`] and hand it to [`run`] for a windowed app, or configure a
+//! [`Renderer`] directly with [`RendererBuilder`] to drive it from your own event loop or headless.
+
+#![warn(missing_docs)]
+
+mod app;
+mod builder;
+mod camera;
+mod controller;
+mod debug;
+mod depth;
+mod descriptors;
+mod device;
+mod dynamic_rendering;
+mod error;
+mod frame;
+mod headless;
+#[cfg(feature = "hot-reload")]
+mod hot_reload;
+mod memory;
+mod mesh;
+mod pipeline;
+mod pipeline_cache;
+mod present;
+mod reflect;
+mod setup;
+#[cfg(feature = "hot-reload")]
+mod shader_compiler;
+mod shaders;
+mod uniform;
+mod upload;
+mod validation;
+
+pub use app::{run, App, Application};
+pub use ash::vk;
+pub use builder::RendererBuilder;
+pub use camera::{Camera, CameraPushConstants, Projection};
+pub use controller::{CameraController, FlyController, OrbitController};
+pub use depth::DepthTest;
+pub use device::DeviceSelection;
+pub use error::{LyeError, LyeResult};
+pub use frame::{DrawContext, DEFAULT_FRAMES_IN_FLIGHT};
+pub use memory::{HeapUsage, MemoryLocation};
+pub use mesh::{ColorVertex, Mesh, MeshId, Vertex};
+pub use pipeline::{BlendMode, GraphicsPipelineBuilder, SpecializationValue};
+pub use present::PresentPreference;
+pub use setup::Renderer;
+pub use uniform::{Std140, UniformBlock, UniformBuffer, UniformField};
+pub use winit;
@@ -1,12 +1,91 @@
-use winit::event_loop::EventLoop;
+use lye::{
+    winit::{
+        event::{DeviceEvent, ElementState, WindowEvent},
+        keyboard::{KeyCode, PhysicalKey},
+    },
+    Application, Camera, CameraController, ColorVertex, LyeResult, OrbitController, Renderer,
+};
 
-mod camera;
-mod setup;
+const TRIANGLE: [ColorVertex; 3] = [
+    ColorVertex {
+        position: [0.0, 0.5, 0.0],
+        color: [1.0, 0.0, 0.0],
+    },
+    ColorVertex {
+        position: [0.5, -0.5, 0.0],
+        color: [0.0, 1.0, 0.0],
+    },
+    ColorVertex {
+        position: [-0.5, -0.5, 0.0],
+        color: [0.0, 0.0, 1.0],
+    },
+];
+//World space, Y up
 
-fn main() {
-    let event_loop = EventLoop::new().expect("Why would this fail");
-    event_loop.set_control_flow(winit::event_loop::ControlFlow::Poll);
-    let mut app = setup::App::default();
+#[derive(Default)]
+struct Demo {
+    camera: Camera,
+    controller: CameraController,
+}
+
+impl Application for Demo {
+    #[cfg(feature = "hot-reload")]
+    fn renderer_builder(&self) -> lye::RendererBuilder {
+        lye::RendererBuilder::new().hot_reload(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders"))
+    }
+
+    fn init(&mut self, renderer: &mut Renderer) -> LyeResult<()> {
+        renderer.add_mesh(&TRIANGLE, &[0, 1, 2])?;
+
+        let extent = renderer.extent();
+        self.camera.position = glam::Vec3::new(0.0, 0.0, 2.0);
+        self.camera.look_at(glam::Vec3::ZERO, glam::Vec3::Y);
+        self.camera
+            .projection
+            .set_aspect(extent.width as f32 / extent.height.max(1) as f32);
+        renderer.set_camera(&self.camera);
+        self.controller =
+            CameraController::Orbit(OrbitController::from_camera(&self.camera, glam::Vec3::ZERO));
+
+        Ok(())
+    }
+
+    fn window_event(&mut self, renderer: &mut Renderer, event: &WindowEvent) {
+        if let Some(window) = renderer.window() {
+            self.controller.window_event(window, &self.camera, event);
+        }
+        match event {
+            WindowEvent::Resized(size) if size.width > 0 && size.height > 0 => {
+                self.camera
+                    .projection
+                    .set_aspect(size.width as f32 / size.height as f32);
+            }
+            WindowEvent::KeyboardInput { event: key, .. }
+                if key.state == ElementState::Pressed
+                    && !key.repeat
+                    && key.physical_key == PhysicalKey::Code(KeyCode::KeyV) =>
+            {
+                renderer.set_vsync(!renderer.vsync());
+            }
+            _ => (),
+        }
+        //V toggles VSync
+    }
+
+    fn device_event(&mut self, _renderer: &mut Renderer, event: &DeviceEvent) {
+        self.controller.device_event(event);
+    }
+
+    fn update(&mut self, renderer: &mut Renderer, dt: f32) {
+        self.controller.update(&mut self.camera, dt);
+        renderer.set_camera(&self.camera);
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    lye::run(Demo::default())?;
 
-    event_loop.run_app(&mut app).expect("do be not working");
+    Ok(())
 }
@@ -0,0 +1,520 @@
+use ash::{self, prelude::VkResult, vk};
+use std::{marker::PhantomData, ptr};
+
+const BLOCK_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+
+/// Where an allocation should live.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryLocation {
+    /// Device local, only the GPU accesses it.
+    GpuOnly,
+    /// Mapped, written by the CPU and read by the GPU.
+    CpuToGpu,
+    /// Mapped and preferably cached, written by the GPU and read back by the CPU.
+    GpuToCpu,
+}
+
+/// How much of a memory heap the renderer uses, see [`crate::Renderer::memory_usage`].
+#[derive(Clone, Copy, Debug)]
+pub struct HeapUsage {
+    /// Index into the devices memory heaps.
+    pub heap_index: u32,
+    /// Flags of the heap, DEVICE_LOCAL for VRAM.
+    pub flags: vk::MemoryHeapFlags,
+    /// Total size of the heap.
+    pub heap_size: vk::DeviceSize,
+    /// Bytes allocated from the heap.
+    pub allocated: vk::DeviceSize,
+    /// Bytes of the allocated ones in use.
+    pub used: vk::DeviceSize,
+}
+//allocated : bytes taken from the heap with vkAllocateMemory, used : bytes handed out to buffers and images
+
+#[derive(Debug)]
+pub struct Allocation {
+    block: usize,
+    memory: vk::DeviceMemory,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+    mapped: *mut u8,
+}
+
+impl Allocation {
+    pub fn write<T: Copy>(&self, offset: usize, data: &[T]) {
+        let bytes = std::mem::size_of_val(data);
+        assert!(
+            !self.mapped.is_null(),
+            "Only host visible allocations can be written to"
+        );
+        assert!(
+            (offset + bytes) as vk::DeviceSize <= self.size,
+            "Write of {} bytes at {} overflows allocation of {} bytes",
+            bytes,
+            offset,
+            self.size
+        );
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr() as *const u8, self.mapped.add(offset), bytes)
+        };
+    }
+
+    pub fn read(&self, offset: usize, len: usize) -> &[u8] {
+        assert!(
+            !self.mapped.is_null(),
+            "Only host visible allocations can be read from"
+        );
+        assert!((offset + len) as vk::DeviceSize <= self.size);
+        unsafe { std::slice::from_raw_parts(self.mapped.add(offset), len) }
+    }
+}
+
+struct Block {
+    memory: vk::DeviceMemory,
+    memory_type_index: u32,
+    linear: bool,
+    dedicated: bool,
+    size: vk::DeviceSize,
+    used: vk::DeviceSize,
+    mapped: *mut u8,
+    free_ranges: Vec<(vk::DeviceSize, vk::DeviceSize)>,
+}
+//free_ranges are (offset, size), sorted by offset and never adjacent
+
+impl Block {
+    fn try_allocate(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Option<vk::DeviceSize> {
+        let (i, aligned_offset) =
+            self.free_ranges
+                .iter()
+                .enumerate()
+                .find_map(|(i, &(offset, range_size))| {
+                    let aligned_offset = offset.next_multiple_of(alignment);
+                    (aligned_offset + size <= offset + range_size).then_some((i, aligned_offset))
+                })?;
+
+        let (offset, range_size) = self.free_ranges[i];
+        let mut remaining = Vec::with_capacity(2);
+        if aligned_offset > offset {
+            remaining.push((offset, aligned_offset - offset));
+        }
+        if aligned_offset + size < offset + range_size {
+            remaining.push((
+                aligned_offset + size,
+                offset + range_size - aligned_offset - size,
+            ));
+        }
+        self.free_ranges.splice(i..=i, remaining);
+        self.used += size;
+
+        Some(aligned_offset)
+    }
+    //First fit, alignment padding stays in the free list
+
+    fn release(&mut self, offset: vk::DeviceSize, size: vk::DeviceSize) {
+        let i = self
+            .free_ranges
+            .partition_point(|&(range_offset, _)| range_offset < offset);
+        self.free_ranges.insert(i, (offset, size));
+        self.used -= size;
+
+        if i + 1 < self.free_ranges.len() {
+            let (next_offset, next_size) = self.free_ranges[i + 1];
+            if offset + size == next_offset {
+                self.free_ranges[i].1 += next_size;
+                self.free_ranges.remove(i + 1);
+            }
+        }
+        if i > 0 {
+            let (previous_offset, previous_size) = self.free_ranges[i - 1];
+            if previous_offset + previous_size == offset {
+                self.free_ranges[i - 1].1 += self.free_ranges[i].1;
+                self.free_ranges.remove(i);
+            }
+        }
+    }
+    //Merges with its neighbours so free ranges dont fragment
+}
+
+pub struct Allocator {
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    blocks: Vec<Option<Block>>,
+}
+
+impl Allocator {
+    pub fn new(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> Self {
+        let memory_properties =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+
+        Self {
+            memory_properties,
+            blocks: Vec::new(),
+        }
+    }
+
+    fn memory_type_candidates(&self, memory_type_bits: u32, location: MemoryLocation) -> Vec<u32> {
+        let (required, preferred) = match location {
+            MemoryLocation::GpuOnly => (
+                vk::MemoryPropertyFlags::empty(),
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            ),
+            MemoryLocation::CpuToGpu => (
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                vk::MemoryPropertyFlags::empty(),
+            ),
+            MemoryLocation::GpuToCpu => (
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                vk::MemoryPropertyFlags::HOST_CACHED,
+            ),
+        };
+
+        let mut candidates: Vec<u32> = (0..self.memory_properties.memory_type_count)
+            .filter(|&i| {
+                memory_type_bits & (1 << i) != 0
+                    && self.memory_properties.memory_types[i as usize]
+                        .property_flags
+                        .contains(required)
+            })
+            .collect();
+        candidates.sort_by_key(|&i| {
+            !self.memory_properties.memory_types[i as usize]
+                .property_flags
+                .contains(preferred)
+        });
+
+        candidates
+    }
+    //Types with the preferred flags come first, the rest are fallbacks if those heaps run out
+
+    pub fn allocate(
+        &mut self,
+        device: &ash::Device,
+        requirements: vk::MemoryRequirements,
+        location: MemoryLocation,
+        linear: bool,
+    ) -> VkResult<Allocation> {
+        let candidates = self.memory_type_candidates(requirements.memory_type_bits, location);
+        let mut result = Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY);
+
+        for memory_type_index in candidates {
+            result = self.allocate_from_type(device, requirements, memory_type_index, linear);
+            if result.is_ok() {
+                break;
+            }
+        }
+
+        result
+    }
+    //linear separates buffers from optimal tiling images so bufferImageGranularity never matters
+
+    fn allocate_from_type(
+        &mut self,
+        device: &ash::Device,
+        requirements: vk::MemoryRequirements,
+        memory_type_index: u32,
+        linear: bool,
+    ) -> VkResult<Allocation> {
+        let heap_size = self.memory_properties.memory_heaps
+            [self.memory_properties.memory_types[memory_type_index as usize].heap_index as usize]
+            .size;
+        let block_size = BLOCK_SIZE.min(heap_size / 8).max(requirements.size);
+        let dedicated = requirements.size > block_size / 2;
+        //Big resources get their own vkAllocateMemory instead of eating half a block
+
+        if !dedicated {
+            for (i, block) in self.blocks.iter_mut().enumerate() {
+                let Some(block) = block.as_mut().filter(|block| {
+                    !block.dedicated
+                        && block.memory_type_index == memory_type_index
+                        && block.linear == linear
+                }) else {
+                    continue;
+                };
+
+                if let Some(offset) = block.try_allocate(requirements.size, requirements.alignment)
+                {
+                    return Ok(Allocation {
+                        block: i,
+                        memory: block.memory,
+                        offset,
+                        size: requirements.size,
+                        mapped: Allocator::offset_ptr(block.mapped, offset),
+                    });
+                }
+            }
+        }
+
+        let size = if dedicated {
+            requirements.size
+        } else {
+            block_size
+        };
+        let mut block = self.allocate_block(device, memory_type_index, size, linear, dedicated)?;
+        let offset = block
+            .try_allocate(requirements.size, requirements.alignment)
+            .expect("Fresh blocks fit the allocation they were made for");
+        let allocation_memory = block.memory;
+        let mapped = Allocator::offset_ptr(block.mapped, offset);
+
+        let index = match self.blocks.iter().position(Option::is_none) {
+            Some(index) => {
+                self.blocks[index] = Some(block);
+                index
+            }
+            None => {
+                self.blocks.push(Some(block));
+                self.blocks.len() - 1
+            }
+        };
+
+        Ok(Allocation {
+            block: index,
+            memory: allocation_memory,
+            offset,
+            size: requirements.size,
+            mapped,
+        })
+    }
+
+    fn offset_ptr(mapped: *mut u8, offset: vk::DeviceSize) -> *mut u8 {
+        if mapped.is_null() {
+            mapped
+        } else {
+            unsafe { mapped.add(offset as usize) }
+        }
+    }
+
+    fn allocate_block(
+        &self,
+        device: &ash::Device,
+        memory_type_index: u32,
+        size: vk::DeviceSize,
+        linear: bool,
+        dedicated: bool,
+    ) -> VkResult<Block> {
+        let allocate_info = vk::MemoryAllocateInfo {
+            s_type: vk::StructureType::MEMORY_ALLOCATE_INFO,
+            p_next: ptr::null(),
+            allocation_size: size,
+            memory_type_index,
+            _marker: PhantomData,
+        };
+
+        let memory = unsafe { device.allocate_memory(&allocate_info, None)? };
+
+        let host_visible = self.memory_properties.memory_types[memory_type_index as usize]
+            .property_flags
+            .contains(vk::MemoryPropertyFlags::HOST_VISIBLE);
+        let mapped = if host_visible {
+            match unsafe {
+                device.map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+            } {
+                Ok(mapped) => mapped as *mut u8,
+                Err(err) => {
+                    unsafe { device.free_memory(memory, None) };
+                    return Err(err);
+                }
+            }
+        } else {
+            ptr::null_mut()
+        };
+        //Host visible blocks stay mapped for their whole lifetime
+
+        Ok(Block {
+            memory,
+            memory_type_index,
+            linear,
+            dedicated,
+            size,
+            used: 0,
+            mapped,
+            free_ranges: vec![(0, size)],
+        })
+    }
+
+    pub fn free(&mut self, device: &ash::Device, allocation: &Allocation) {
+        let slot = &mut self.blocks[allocation.block];
+        let block = slot
+            .as_mut()
+            .expect("Allocation belongs to a block that was already freed");
+        block.release(allocation.offset, allocation.size);
+
+        if block.dedicated && block.used == 0 {
+            unsafe { device.free_memory(block.memory, None) };
+            *slot = None;
+        }
+    }
+    //Pooled blocks are kept around when empty, the next allocation will likely need them again
+
+    pub fn create_buffer(
+        &mut self,
+        device: &ash::Device,
+        create_info: &vk::BufferCreateInfo,
+        location: MemoryLocation,
+    ) -> VkResult<(vk::Buffer, Allocation)> {
+        let buffer = unsafe { device.create_buffer(create_info, None)? };
+        let requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
+
+        let allocation = match self.allocate(device, requirements, location, true) {
+            Ok(allocation) => allocation,
+            Err(err) => {
+                unsafe { device.destroy_buffer(buffer, None) };
+                return Err(err);
+            }
+        };
+        if let Err(err) =
+            unsafe { device.bind_buffer_memory(buffer, allocation.memory, allocation.offset) }
+        {
+            self.destroy_buffer(device, buffer, &allocation);
+            return Err(err);
+        }
+
+        Ok((buffer, allocation))
+    }
+
+    pub fn create_image(
+        &mut self,
+        device: &ash::Device,
+        create_info: &vk::ImageCreateInfo,
+        location: MemoryLocation,
+    ) -> VkResult<(vk::Image, Allocation)> {
+        let image = unsafe { device.create_image(create_info, None)? };
+        let requirements = unsafe { device.get_image_memory_requirements(image) };
+
+        let linear = create_info.tiling == vk::ImageTiling::LINEAR;
+        let allocation = match self.allocate(device, requirements, location, linear) {
+            Ok(allocation) => allocation,
+            Err(err) => {
+                unsafe { device.destroy_image(image, None) };
+                return Err(err);
+            }
+        };
+        if let Err(err) =
+            unsafe { device.bind_image_memory(image, allocation.memory, allocation.offset) }
+        {
+            self.destroy_image(device, image, &allocation);
+            return Err(err);
+        }
+
+        Ok((image, allocation))
+    }
+
+    pub fn destroy_buffer(
+        &mut self,
+        device: &ash::Device,
+        buffer: vk::Buffer,
+        allocation: &Allocation,
+    ) {
+        unsafe { device.destroy_buffer(buffer, None) };
+        self.free(device, allocation);
+    }
+
+    pub fn destroy_image(
+        &mut self,
+        device: &ash::Device,
+        image: vk::Image,
+        allocation: &Allocation,
+    ) {
+        unsafe { device.destroy_image(image, None) };
+        self.free(device, allocation);
+    }
+
+    pub fn heap_usage(&self) -> Vec<HeapUsage> {
+        let mut usage: Vec<HeapUsage> = self.memory_properties.memory_heaps
+            [..self.memory_properties.memory_heap_count as usize]
+            .iter()
+            .enumerate()
+            .map(|(i, heap)| HeapUsage {
+                heap_index: i as u32,
+                flags: heap.flags,
+                heap_size: heap.size,
+                allocated: 0,
+                used: 0,
+            })
+            .collect();
+
+        for block in self.blocks.iter().flatten() {
+            let heap_index = self.memory_properties.memory_types[block.memory_type_index as usize]
+                .heap_index as usize;
+            usage[heap_index].allocated += block.size;
+            usage[heap_index].used += block.used;
+        }
+
+        usage
+    }
+
+    pub unsafe fn destroy(&mut self, device: &ash::Device) {
+        for block in self.blocks.drain(..).flatten() {
+            device.free_memory(block.memory, None);
+        }
+    }
+    //Frees every block, allocations still pointing into them become dangling
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(size: vk::DeviceSize) -> Block {
+        Block {
+            memory: vk::DeviceMemory::null(),
+            memory_type_index: 0,
+            linear: true,
+            dedicated: false,
+            size,
+            used: 0,
+            mapped: std::ptr::null_mut(),
+            free_ranges: vec![(0, size)],
+        }
+    }
+
+    #[test]
+    fn allocations_are_aligned_and_padding_stays_free() {
+        let mut block = block(1024);
+        assert_eq!(block.try_allocate(10, 4), Some(0));
+        assert_eq!(block.try_allocate(16, 256), Some(256));
+        assert_eq!(block.free_ranges, vec![(10, 246), (272, 752)]);
+        assert_eq!(block.used, 26);
+
+        assert_eq!(block.try_allocate(8, 8), Some(16));
+        //First fit reuses the alignment padding
+        assert_eq!(block.free_ranges, vec![(10, 6), (24, 232), (272, 752)]);
+    }
+
+    #[test]
+    fn allocations_split_the_free_range() {
+        let mut block = block(100);
+        assert_eq!(block.try_allocate(30, 1), Some(0));
+        assert_eq!(block.try_allocate(30, 1), Some(30));
+        assert_eq!(block.free_ranges, vec![(60, 40)]);
+        assert_eq!(block.used, 60);
+    }
+
+    #[test]
+    fn released_ranges_coalesce_with_both_neighbours() {
+        let mut block = block(90);
+        let offsets: Vec<_> = (0..3).map(|_| block.try_allocate(30, 1).unwrap()).collect();
+        assert!(block.free_ranges.is_empty());
+
+        block.release(offsets[0], 30);
+        block.release(offsets[2], 30);
+        assert_eq!(block.free_ranges, vec![(0, 30), (60, 30)]);
+        block.release(offsets[1], 30);
+        assert_eq!(block.free_ranges, vec![(0, 90)]);
+        assert_eq!(block.used, 0);
+    }
+
+    #[test]
+    fn exhausted_blocks_refuse_allocations() {
+        let mut block = block(64);
+        assert_eq!(block.try_allocate(65, 1), None);
+        assert_eq!(block.try_allocate(48, 1), Some(0));
+        assert_eq!(block.try_allocate(16, 32), None);
+        //16 bytes are free, but not at a multiple of 32
+        assert_eq!(block.try_allocate(16, 16), Some(48));
+        assert_eq!(block.try_allocate(1, 1), None);
+        assert_eq!(block.used, 64);
+    }
+}
@@ -0,0 +1,209 @@
+use crate::{
+    error::{Context, LyeError, LyeResult},
+    memory::{Allocation, Allocator, MemoryLocation},
+    upload::Uploader,
+};
+use ash::{self, prelude::VkResult, vk};
+use std::{marker::PhantomData, mem, ptr};
+
+/// A vertex layout, fed to `PipelineVertexInputStateCreateInfo`.
+///
+/// # Safety
+///
+/// Meshes are uploaded by reading the vertex slice as raw bytes, so the type has to be
+/// `#[repr(C)]` without any padding, between fields or at the end, and every field has to be
+/// plain data (floats, integers, arrays of them).
+pub unsafe trait Vertex: Copy {
+    /// The vertex buffer bindings, with their stride and input rate.
+    fn bindings() -> Vec<vk::VertexInputBindingDescription>;
+    /// Format and offset of every shader input location.
+    fn attributes() -> Vec<vk::VertexInputAttributeDescription>;
+}
+//Describes how a vertex buffer is laid out, feeds PipelineVertexInputStateCreateInfo
+
+/// Vertex with a position and a color, the layout of the default shaders.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorVertex {
+    /// Position in world space.
+    pub position: [f32; 3],
+    /// Linear RGB color.
+    pub color: [f32; 3],
+}
+//Matches the positions/colors inputs of shaders/vertex.glsl
+
+unsafe impl Vertex for ColorVertex {
+    fn bindings() -> Vec<vk::VertexInputBindingDescription> {
+        vec![vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: mem::size_of::<ColorVertex>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }]
+    }
+
+    fn attributes() -> Vec<vk::VertexInputAttributeDescription> {
+        vec![
+            vk::VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: mem::offset_of!(ColorVertex, position) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: mem::offset_of!(ColorVertex, color) as u32,
+            },
+        ]
+    }
+}
+
+/// Handle to a mesh added with [`crate::Renderer::add_mesh`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MeshId(pub(crate) usize);
+
+/// Vertex and index buffers uploaded to the GPU.
+pub struct Mesh {
+    vertex_buffer: vk::Buffer,
+    vertex_allocation: Allocation,
+    index_buffer: vk::Buffer,
+    index_allocation: Allocation,
+    index_count: u32,
+}
+
+impl Mesh {
+    pub(crate) fn new<V: Vertex>(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        uploader: &mut Uploader,
+        vertices: &[V],
+        indices: &[u32],
+    ) -> LyeResult<Self> {
+        if vertices.is_empty() || indices.is_empty() {
+            return Err(LyeError::InvalidMesh(
+                "Meshes need at least one vertex and one index".to_owned(),
+            ));
+        }
+        if let Some(index) = indices.iter().find(|&&i| i as usize >= vertices.len()) {
+            return Err(LyeError::InvalidMesh(format!(
+                "Index {} is out of bounds for {} vertices",
+                index,
+                vertices.len()
+            )));
+        }
+
+        let (vertex_buffer, vertex_allocation) = Mesh::create_buffer(
+            device,
+            allocator,
+            uploader,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            vertices,
+        )
+        .context("Creating the vertex buffer")?;
+        let (index_buffer, index_allocation) = match Mesh::create_buffer(
+            device,
+            allocator,
+            uploader,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            indices,
+        ) {
+            Ok(index) => index,
+            Err(err) => {
+                Mesh::discard_buffer(
+                    device,
+                    allocator,
+                    uploader,
+                    vertex_buffer,
+                    vertex_allocation,
+                );
+                return Err(LyeError::Vulkan {
+                    context: "Creating the index buffer",
+                    result: err,
+                });
+            }
+        };
+
+        Ok(Self {
+            vertex_buffer,
+            vertex_allocation,
+            index_buffer,
+            index_allocation,
+            index_count: indices.len() as u32,
+        })
+    }
+
+    fn create_buffer<T: Copy>(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        uploader: &mut Uploader,
+        usage: vk::BufferUsageFlags,
+        data: &[T],
+    ) -> VkResult<(vk::Buffer, Allocation)> {
+        let buffer_create_info = vk::BufferCreateInfo {
+            s_type: vk::StructureType::BUFFER_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::BufferCreateFlags::empty(),
+            size: mem::size_of_val(data) as vk::DeviceSize,
+            usage: usage | vk::BufferUsageFlags::TRANSFER_DST,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+            _marker: PhantomData,
+        };
+
+        let (buffer, allocation) =
+            allocator.create_buffer(device, &buffer_create_info, MemoryLocation::GpuOnly)?;
+        if let Err(err) = uploader.upload_buffer(device, buffer, data) {
+            Mesh::discard_buffer(device, allocator, uploader, buffer, allocation);
+            return Err(err);
+        }
+
+        Ok((buffer, allocation))
+    }
+
+    fn discard_buffer(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        uploader: &mut Uploader,
+        buffer: vk::Buffer,
+        allocation: Allocation,
+    ) {
+        match uploader.abort(device) {
+            Ok(()) => allocator.destroy_buffer(device, buffer, &allocation),
+            Err(err) => log::error!(
+                "Leaking a mesh buffer, its upload couldnt be aborted : {}",
+                err
+            ),
+        }
+    }
+    //The uploader still holds copies into the buffer until they are aborted, destroying it first
+    //would let the next flush write to freed memory
+
+    /// Number of indices drawn.
+    pub fn index_count(&self) -> u32 {
+        self.index_count
+    }
+
+    pub(crate) fn buffers(&self) -> (vk::Buffer, vk::Buffer) {
+        (self.vertex_buffer, self.index_buffer)
+    }
+
+    pub(crate) fn draw(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertex_buffer], &[0]);
+            device.cmd_bind_index_buffer(
+                command_buffer,
+                self.index_buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+            device.cmd_draw_indexed(command_buffer, self.index_count, 1, 0, 0, 0);
+        };
+    }
+
+    pub(crate) fn destroy(&self, device: &ash::Device, allocator: &mut Allocator) {
+        allocator.destroy_buffer(device, self.vertex_buffer, &self.vertex_allocation);
+        allocator.destroy_buffer(device, self.index_buffer, &self.index_allocation);
+    }
+}
@@ -0,0 +1,817 @@
+use crate::{
+    camera::CameraPushConstants,
+    depth::DepthTest,
+    descriptors::DescriptorLayoutCache,
+    error::{LyeError, LyeResult},
+    mesh::Vertex,
+    reflect::{self, DescriptorBinding, PipelineInterface, ShaderReflection},
+    shaders::PipelineShaders,
+};
+use ash::{self, prelude::VkResult, vk};
+use std::{borrow::Cow, marker::PhantomData, ptr};
+
+const SUPPORTED_DYNAMIC_STATES: [vk::DynamicState; 3] = [
+    vk::DynamicState::VIEWPORT,
+    vk::DynamicState::SCISSOR,
+    vk::DynamicState::LINE_WIDTH,
+];
+//The renderer records these itself before drawing, anything else would be unset for its own draws
+
+const ALWAYS_DYNAMIC_STATES: [vk::DynamicState; 2] =
+    [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+//Resizing only changes these, so the pipeline survives a swapchain recreation
+
+/// What a pipeline renders into, a render pass or the attachment formats for dynamic rendering.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PipelineTarget {
+    RenderPass(vk::RenderPass),
+    Dynamic {
+        color_format: vk::Format,
+        depth_format: vk::Format,
+    },
+}
+
+/// How the fragment output is combined with the color already in the target.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Overwrites the target.
+    #[default]
+    Opaque,
+    /// Mixes by the fragments alpha.
+    Alpha,
+    /// Mixes by alpha, the fragment color is already multiplied by it.
+    PremultipliedAlpha,
+    /// Adds the fragment color weighted by its alpha.
+    Additive,
+    /// Multiplies the target with the fragment color.
+    Multiply,
+}
+
+impl BlendMode {
+    fn attachment_state(self) -> vk::PipelineColorBlendAttachmentState {
+        let (blend_enable, src_color_blend_factor, dst_color_blend_factor) = match self {
+            BlendMode::Opaque => (vk::FALSE, vk::BlendFactor::ONE, vk::BlendFactor::ZERO),
+            BlendMode::Alpha => (
+                vk::TRUE,
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+            BlendMode::PremultipliedAlpha => (
+                vk::TRUE,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+            BlendMode::Additive => (vk::TRUE, vk::BlendFactor::SRC_ALPHA, vk::BlendFactor::ONE),
+            BlendMode::Multiply => (vk::TRUE, vk::BlendFactor::DST_COLOR, vk::BlendFactor::ZERO),
+        };
+        vk::PipelineColorBlendAttachmentState {
+            blend_enable,
+            src_color_blend_factor,
+            dst_color_blend_factor,
+            color_blend_op: vk::BlendOp::ADD,
+            src_alpha_blend_factor: vk::BlendFactor::ONE,
+            dst_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            alpha_blend_op: vk::BlendOp::ADD,
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+        }
+    }
+    //Alpha always accumulates like premultiplied alpha, so the target ends up with the coverage
+}
+
+/// Value of a `layout(constant_id = N)` constant, every variant takes 4 bytes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpecializationValue {
+    /// A `bool` constant.
+    Bool(bool),
+    /// A `uint` constant.
+    U32(u32),
+    /// An `int` constant.
+    I32(i32),
+    /// A `float` constant.
+    F32(f32),
+}
+
+impl SpecializationValue {
+    fn to_bytes(self) -> [u8; 4] {
+        match self {
+            SpecializationValue::Bool(value) => (value as vk::Bool32).to_ne_bytes(),
+            SpecializationValue::U32(value) => value.to_ne_bytes(),
+            SpecializationValue::I32(value) => value.to_ne_bytes(),
+            SpecializationValue::F32(value) => value.to_ne_bytes(),
+        }
+    }
+}
+
+impl From<bool> for SpecializationValue {
+    fn from(value: bool) -> Self {
+        SpecializationValue::Bool(value)
+    }
+}
+
+impl From<u32> for SpecializationValue {
+    fn from(value: u32) -> Self {
+        SpecializationValue::U32(value)
+    }
+}
+
+impl From<i32> for SpecializationValue {
+    fn from(value: i32) -> Self {
+        SpecializationValue::I32(value)
+    }
+}
+
+impl From<f32> for SpecializationValue {
+    fn from(value: f32) -> Self {
+        SpecializationValue::F32(value)
+    }
+}
+
+/// Fixed function state of the renderers pipeline, shaders and layout come from the SPIR-V.
+///
+/// The defaults draw filled, back face culled, clockwise triangles without blending and with
+/// [`DepthTest::STANDARD`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct GraphicsPipelineBuilder {
+    pub(crate) topology: vk::PrimitiveTopology,
+    pub(crate) primitive_restart: bool,
+    pub(crate) polygon_mode: vk::PolygonMode,
+    pub(crate) cull_mode: vk::CullModeFlags,
+    pub(crate) front_face: vk::FrontFace,
+    pub(crate) line_width: f32,
+    pub(crate) blend_mode: BlendMode,
+    pub(crate) depth_test: Option<DepthTest>,
+    pub(crate) dynamic_states: Vec<vk::DynamicState>,
+    pub(crate) specialization_constants: Vec<(vk::ShaderStageFlags, u32, SpecializationValue)>,
+    pub(crate) shaders: Option<PipelineShaders>,
+}
+
+impl Default for GraphicsPipelineBuilder {
+    fn default() -> Self {
+        Self {
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            primitive_restart: false,
+            polygon_mode: vk::PolygonMode::FILL,
+            cull_mode: vk::CullModeFlags::BACK,
+            front_face: vk::FrontFace::CLOCKWISE,
+            line_width: 1.0,
+            blend_mode: BlendMode::default(),
+            depth_test: Some(DepthTest::default()),
+            dynamic_states: Vec::new(),
+            specialization_constants: Vec::new(),
+            shaders: None,
+        }
+    }
+}
+
+impl GraphicsPipelineBuilder {
+    /// Builder with the default state, see the setters for each default.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How vertices form primitives, `TRIANGLE_LIST` by default.
+    pub fn topology(mut self, topology: vk::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// Lets the maximum index value restart strip and fan topologies.
+    pub fn primitive_restart(mut self, enabled: bool) -> Self {
+        self.primitive_restart = enabled;
+        self
+    }
+
+    /// `FILL` by default, `LINE` and `POINT` need the `fill_mode_non_solid` feature.
+    pub fn polygon_mode(mut self, polygon_mode: vk::PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    /// Which faces are discarded, `BACK` by default.
+    pub fn cull_mode(mut self, cull_mode: vk::CullModeFlags) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    /// Winding order of front faces, `CLOCKWISE` by default to match the Y flipping projection.
+    pub fn front_face(mut self, front_face: vk::FrontFace) -> Self {
+        self.front_face = front_face;
+        self
+    }
+
+    /// Width of rasterized lines, anything but 1.0 needs the `wide_lines` feature.
+    pub fn line_width(mut self, line_width: f32) -> Self {
+        self.line_width = line_width;
+        self
+    }
+
+    /// How the color is blended into the target, [`BlendMode::Opaque`] by default.
+    pub fn blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+        self
+    }
+
+    /// Depth test and write, `None` draws everything regardless of depth.
+    pub fn depth_test(mut self, depth_test: Option<DepthTest>) -> Self {
+        self.depth_test = depth_test;
+        self
+    }
+
+    /// Makes `state` settable while recording instead of being baked into the pipeline.
+    ///
+    /// Only `VIEWPORT`, `SCISSOR` and `LINE_WIDTH` are supported, the renderer sets them to the
+    /// target size and [`GraphicsPipelineBuilder::line_width`] before every frame. Viewport and
+    /// scissor are always dynamic, adding them is a no-op.
+    pub fn dynamic_state(mut self, state: vk::DynamicState) -> Self {
+        if !self.dynamic_states.contains(&state) {
+            self.dynamic_states.push(state);
+        }
+        self
+    }
+
+    /// Sets `layout(constant_id = constant_id)` in the shader stages in `stages`.
+    pub fn specialization_constant(
+        mut self,
+        stages: vk::ShaderStageFlags,
+        constant_id: u32,
+        value: impl Into<SpecializationValue>,
+    ) -> Self {
+        let value = value.into();
+        self.specialization_constants
+            .retain(|&(existing_stages, id, _)| !(existing_stages == stages && id == constant_id));
+        self.specialization_constants
+            .push((stages, constant_id, value));
+        self
+    }
+
+    /// SPIR-V of the vertex and fragment shader, replacing the crates `shaders/vertex.glsl` and
+    /// `fragment.glsl`.
+    ///
+    /// The vertex shader has to read [`ColorVertex`](crate::ColorVertex) and may read the camera
+    /// push constants, uniform blocks are filled with [`crate::Renderer::create_uniform_buffer`].
+    /// Left unset, [`crate::Renderer::set_pipeline`] keeps the shaders the renderer already uses.
+    pub fn shaders(
+        mut self,
+        vertex: impl Into<Cow<'static, [u8]>>,
+        fragment: impl Into<Cow<'static, [u8]>>,
+    ) -> Self {
+        self.shaders = Some(PipelineShaders {
+            vertex: vertex.into(),
+            fragment: fragment.into(),
+        });
+        self
+    }
+
+    fn specialization_data(
+        &self,
+        stage: vk::ShaderStageFlags,
+    ) -> (Vec<vk::SpecializationMapEntry>, Vec<u8>) {
+        let mut map_entries = Vec::new();
+        let mut data = Vec::new();
+        for &(stages, constant_id, value) in self.specialization_constants.iter().rev() {
+            if !stages.contains(stage)
+                || map_entries
+                    .iter()
+                    .any(|entry: &vk::SpecializationMapEntry| entry.constant_id == constant_id)
+            {
+                continue;
+            }
+            map_entries.push(vk::SpecializationMapEntry {
+                constant_id,
+                offset: data.len() as u32,
+                size: 4,
+            });
+            data.extend(value.to_bytes());
+        }
+        (map_entries, data)
+    }
+
+    pub(crate) fn build<V: Vertex>(
+        &self,
+        device: &ash::Device,
+        pipeline_cache: vk::PipelineCache,
+        layout_cache: &mut DescriptorLayoutCache,
+        target: PipelineTarget,
+        shaders: &PipelineShaders,
+    ) -> LyeResult<GraphicsPipeline> {
+        if let Some(state) = self
+            .dynamic_states
+            .iter()
+            .find(|state| !SUPPORTED_DYNAMIC_STATES.contains(state))
+        {
+            return Err(LyeError::InvalidConfig(format!(
+                "Dynamic state {:?} isnt supported, only {:?} are",
+                state, SUPPORTED_DYNAMIC_STATES
+            )));
+        }
+
+        let vertex_code = read_spirv("vertex.spv", &shaders.vertex)?;
+        let fragment_code = read_spirv("fragment.spv", &shaders.fragment)?;
+        let vertex_reflection = ShaderReflection::new("vertex.spv", &vertex_code)?;
+        let fragment_reflection = ShaderReflection::new("fragment.spv", &fragment_code)?;
+        reflect::check_stage_interface(&vertex_reflection, &fragment_reflection)?;
+        let vertex_bindings = V::bindings();
+        let vertex_attributes =
+            reflect::check_vertex_input(&vertex_reflection, &vertex_bindings, &V::attributes())?;
+        let interface = PipelineInterface::new(&[&vertex_reflection, &fragment_reflection])?;
+        //Mismatches fail here with the offending location instead of as garbage on screen
+
+        let push_constant_range =
+            interface
+                .push_constant_range
+                .map(|range| vk::PushConstantRange {
+                    size: range.size.max(CameraPushConstants::SIZE),
+                    ..range
+                });
+        //The renderer always pushes the whole camera, even if the shader reads less of it
+        let mut pipeline = GraphicsPipeline {
+            pipeline: vk::Pipeline::null(),
+            layout: vk::PipelineLayout::null(),
+            set_layouts: interface
+                .sets
+                .iter()
+                .map(|bindings| layout_cache.get(device, bindings))
+                .collect::<VkResult<_>>()
+                .map_err(LyeError::Pipeline)?,
+            uniform_blocks: interface.uniform_blocks,
+            push_constant_stages: push_constant_range
+                .map_or(vk::ShaderStageFlags::empty(), |range| range.stage_flags),
+            vertex_type: std::any::type_name::<V>(),
+            vertex_bindings,
+            vertex_attributes,
+            dynamic_states: ALWAYS_DYNAMIC_STATES
+                .into_iter()
+                .chain(self.dynamic_states.iter().copied())
+                .fold(Vec::new(), |mut states, state| {
+                    if !states.contains(&state) {
+                        states.push(state);
+                    }
+                    states
+                }),
+            line_width: self.line_width,
+        };
+        //Destroying null handles is a no-op, so every failure below can just destroy the whole thing
+
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo {
+            s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::PipelineLayoutCreateFlags::empty(),
+            set_layout_count: pipeline.set_layouts.len() as u32,
+            p_set_layouts: pipeline.set_layouts.as_ptr(),
+            push_constant_range_count: push_constant_range.is_some() as u32,
+            p_push_constant_ranges: push_constant_range
+                .as_ref()
+                .map_or(ptr::null(), |range| range as *const _),
+            _marker: PhantomData,
+        };
+        pipeline.layout =
+            match unsafe { device.create_pipeline_layout(&pipeline_layout_info, None) } {
+                Ok(pipeline_layout) => pipeline_layout,
+                Err(result) => {
+                    pipeline.destroy(device);
+                    return Err(LyeError::Pipeline(result));
+                }
+            };
+
+        let vertex_shader_module = match create_shader_module(&vertex_code, device) {
+            Ok(module) => module,
+            Err(err) => {
+                pipeline.destroy(device);
+                return Err(err);
+            }
+        };
+        let fragment_shader_module = match create_shader_module(&fragment_code, device) {
+            Ok(module) => module,
+            Err(err) => {
+                unsafe { device.destroy_shader_module(vertex_shader_module, None) };
+                pipeline.destroy(device);
+                return Err(err);
+            }
+        };
+
+        let entry = c"main";
+
+        let (vertex_map_entries, vertex_data) =
+            self.specialization_data(vk::ShaderStageFlags::VERTEX);
+        let (fragment_map_entries, fragment_data) =
+            self.specialization_data(vk::ShaderStageFlags::FRAGMENT);
+        let specialization_infos = [
+            vk::SpecializationInfo {
+                map_entry_count: vertex_map_entries.len() as u32,
+                p_map_entries: vertex_map_entries.as_ptr(),
+                data_size: vertex_data.len(),
+                p_data: vertex_data.as_ptr().cast(),
+                _marker: PhantomData,
+            },
+            vk::SpecializationInfo {
+                map_entry_count: fragment_map_entries.len() as u32,
+                p_map_entries: fragment_map_entries.as_ptr(),
+                data_size: fragment_data.len(),
+                p_data: fragment_data.as_ptr().cast(),
+                _marker: PhantomData,
+            },
+        ];
+        //Constants the shader doesnt declare are ignored by the driver
+
+        let shader_states_create_infos = [
+            vk::PipelineShaderStageCreateInfo {
+                s_type: vk::StructureType::PIPELINE_SHADER_STAGE_CREATE_INFO,
+                p_next: ptr::null(),
+                flags: vk::PipelineShaderStageCreateFlags::empty(),
+                stage: vk::ShaderStageFlags::VERTEX,
+                module: vertex_shader_module,
+                p_name: entry.as_ptr(),
+                p_specialization_info: &specialization_infos[0],
+                _marker: PhantomData,
+            },
+            vk::PipelineShaderStageCreateInfo {
+                s_type: vk::StructureType::PIPELINE_SHADER_STAGE_CREATE_INFO,
+                p_next: ptr::null(),
+                flags: vk::PipelineShaderStageCreateFlags::empty(),
+                stage: vk::ShaderStageFlags::FRAGMENT,
+                module: fragment_shader_module,
+                p_name: entry.as_ptr(),
+                p_specialization_info: &specialization_infos[1],
+                _marker: PhantomData,
+            },
+        ];
+
+        let vertex_input_state = vk::PipelineVertexInputStateCreateInfo {
+            s_type: vk::StructureType::PIPELINE_VERTEX_INPUT_STATE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::PipelineVertexInputStateCreateFlags::empty(),
+            vertex_attribute_description_count: pipeline.vertex_attributes.len() as u32,
+            p_vertex_attribute_descriptions: pipeline.vertex_attributes.as_ptr(),
+            vertex_binding_description_count: pipeline.vertex_bindings.len() as u32,
+            p_vertex_binding_descriptions: pipeline.vertex_bindings.as_ptr(),
+            _marker: PhantomData,
+        };
+
+        let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo {
+            s_type: vk::StructureType::PIPELINE_INPUT_ASSEMBLY_STATE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::PipelineInputAssemblyStateCreateFlags::empty(),
+            topology: self.topology,
+            primitive_restart_enable: self.primitive_restart as vk::Bool32,
+            _marker: PhantomData,
+        };
+        //How vertices form a shape
+
+        let viewport_state = vk::PipelineViewportStateCreateInfo {
+            s_type: vk::StructureType::PIPELINE_VIEWPORT_STATE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::PipelineViewportStateCreateFlags::empty(),
+            viewport_count: 1,
+            p_viewports: ptr::null(),
+            scissor_count: 1,
+            p_scissors: ptr::null(),
+            _marker: PhantomData,
+        };
+        //Viewport and scissor are dynamic, only their count is baked in
+
+        let rasterization_state = vk::PipelineRasterizationStateCreateInfo {
+            s_type: vk::StructureType::PIPELINE_RASTERIZATION_STATE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::PipelineRasterizationStateCreateFlags::empty(),
+            depth_clamp_enable: vk::FALSE,
+            rasterizer_discard_enable: vk::FALSE,
+            polygon_mode: self.polygon_mode,
+            cull_mode: self.cull_mode,
+            front_face: self.front_face,
+            depth_bias_enable: vk::FALSE,
+            depth_bias_constant_factor: 0.0,
+            depth_bias_clamp: 0.0,
+            depth_bias_slope_factor: 0.0,
+            line_width: self.line_width,
+            _marker: PhantomData,
+        };
+        //Coordinates to Pixels, face-culling (cut out none visible parts)
+
+        let multisample_state = vk::PipelineMultisampleStateCreateInfo {
+            s_type: vk::StructureType::PIPELINE_MULTISAMPLE_STATE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::PipelineMultisampleStateCreateFlags::empty(),
+            rasterization_samples: vk::SampleCountFlags::TYPE_1,
+            sample_shading_enable: vk::FALSE,
+            min_sample_shading: 1.0,
+            p_sample_mask: ptr::null(),
+            alpha_to_coverage_enable: 0,
+            alpha_to_one_enable: 0,
+            _marker: PhantomData,
+        };
+        //Multisampling
+
+        let depth_test = self.depth_test.unwrap_or_default();
+        let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo {
+            s_type: vk::StructureType::PIPELINE_DEPTH_STENCIL_STATE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::PipelineDepthStencilStateCreateFlags::empty(),
+            depth_test_enable: self.depth_test.is_some() as vk::Bool32,
+            depth_write_enable: (self.depth_test.is_some() && depth_test.write) as vk::Bool32,
+            depth_compare_op: depth_test.compare_op,
+            depth_bounds_test_enable: vk::FALSE,
+            stencil_test_enable: vk::FALSE,
+            front: vk::StencilOpState::default(),
+            back: vk::StencilOpState::default(),
+            min_depth_bounds: 0.0,
+            max_depth_bounds: 1.0,
+            _marker: PhantomData,
+        };
+        //Fragments failing compare_op against the stored depth are discarded
+
+        let color_blend_attachments = [self.blend_mode.attachment_state()];
+
+        let color_blend_state = vk::PipelineColorBlendStateCreateInfo {
+            s_type: vk::StructureType::PIPELINE_COLOR_BLEND_STATE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::PipelineColorBlendStateCreateFlags::empty(),
+            logic_op: vk::LogicOp::COPY,
+            logic_op_enable: vk::FALSE,
+            attachment_count: 1,
+            p_attachments: color_blend_attachments.as_ptr(),
+            blend_constants: [0.0, 0.0, 0.0, 0.0],
+            _marker: PhantomData,
+        };
+        //Combines color from framebuffer and newly rendered color
+
+        let dynamic_state = vk::PipelineDynamicStateCreateInfo {
+            s_type: vk::StructureType::PIPELINE_DYNAMIC_STATE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::PipelineDynamicStateCreateFlags::empty(),
+            dynamic_state_count: pipeline.dynamic_states.len() as u32,
+            p_dynamic_states: pipeline.dynamic_states.as_ptr(),
+            _marker: PhantomData,
+        };
+
+        let (render_pass, color_formats, depth_format) = match target {
+            PipelineTarget::RenderPass(render_pass) => {
+                (render_pass, [vk::Format::UNDEFINED], vk::Format::UNDEFINED)
+            }
+            PipelineTarget::Dynamic {
+                color_format,
+                depth_format,
+            } => (vk::RenderPass::null(), [color_format], depth_format),
+        };
+        let rendering_info = vk::PipelineRenderingCreateInfo {
+            s_type: vk::StructureType::PIPELINE_RENDERING_CREATE_INFO,
+            p_next: ptr::null(),
+            view_mask: 0,
+            color_attachment_count: color_formats.len() as u32,
+            p_color_attachment_formats: color_formats.as_ptr(),
+            depth_attachment_format: depth_format,
+            stencil_attachment_format: vk::Format::UNDEFINED,
+            _marker: PhantomData,
+        };
+        //Without a render pass the pipeline only knows the attachment formats
+
+        let pipeline_create_info = [vk::GraphicsPipelineCreateInfo {
+            s_type: vk::StructureType::GRAPHICS_PIPELINE_CREATE_INFO,
+            p_next: match target {
+                PipelineTarget::RenderPass(_) => ptr::null(),
+                PipelineTarget::Dynamic { .. } => {
+                    &rendering_info as *const vk::PipelineRenderingCreateInfo as *const _
+                }
+            },
+            flags: vk::PipelineCreateFlags::empty(),
+            stage_count: shader_states_create_infos.len() as u32,
+            p_stages: shader_states_create_infos.as_ptr(),
+            p_vertex_input_state: &vertex_input_state,
+            p_input_assembly_state: &input_assembly_state,
+            p_tessellation_state: ptr::null(),
+            p_viewport_state: &viewport_state,
+            p_rasterization_state: &rasterization_state,
+            p_multisample_state: &multisample_state,
+            p_depth_stencil_state: &depth_stencil_state,
+            p_color_blend_state: &color_blend_state,
+            p_dynamic_state: &dynamic_state,
+            layout: pipeline.layout,
+            render_pass,
+            subpass: 0,
+            base_pipeline_handle: Default::default(),
+            base_pipeline_index: 0,
+            _marker: PhantomData,
+        }];
+
+        let pipelines = unsafe {
+            device.create_graphics_pipelines(pipeline_cache, &pipeline_create_info, None)
+        };
+
+        unsafe {
+            device.destroy_shader_module(vertex_shader_module, None);
+            device.destroy_shader_module(fragment_shader_module, None);
+        };
+        //Modules are only needed while creating the pipeline, failed or not
+
+        match pipelines {
+            Ok(pipelines) => {
+                pipeline.pipeline = pipelines[0];
+                Ok(pipeline)
+            }
+            Err((_, result)) => {
+                pipeline.destroy(device);
+                Err(LyeError::Pipeline(result))
+            }
+        }
+    }
+}
+
+pub(crate) struct GraphicsPipeline {
+    pub(crate) pipeline: vk::Pipeline,
+    pub(crate) layout: vk::PipelineLayout,
+    pub(crate) set_layouts: Vec<vk::DescriptorSetLayout>,
+    pub(crate) uniform_blocks: Vec<DescriptorBinding>,
+    pub(crate) push_constant_stages: vk::ShaderStageFlags,
+    vertex_type: &'static str,
+    vertex_bindings: Vec<vk::VertexInputBindingDescription>,
+    vertex_attributes: Vec<vk::VertexInputAttributeDescription>,
+    dynamic_states: Vec<vk::DynamicState>,
+    line_width: f32,
+}
+//The layout is derived from the shaders and lives and dies with the pipeline, set layouts belong to the layout cache
+
+impl GraphicsPipeline {
+    pub(crate) fn set_dynamic_state(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        extent: vk::Extent2D,
+    ) {
+        for &state in &self.dynamic_states {
+            match state {
+                vk::DynamicState::VIEWPORT => unsafe {
+                    device.cmd_set_viewport(
+                        command_buffer,
+                        0,
+                        &[vk::Viewport {
+                            x: 0.0,
+                            y: 0.0,
+                            width: extent.width as f32,
+                            height: extent.height as f32,
+                            min_depth: 0.0,
+                            max_depth: 1.0,
+                        }],
+                    )
+                },
+                vk::DynamicState::SCISSOR => unsafe {
+                    device.cmd_set_scissor(
+                        command_buffer,
+                        0,
+                        &[vk::Rect2D {
+                            offset: vk::Offset2D { x: 0, y: 0 },
+                            extent,
+                        }],
+                    )
+                },
+                vk::DynamicState::LINE_WIDTH => unsafe {
+                    device.cmd_set_line_width(command_buffer, self.line_width)
+                },
+                _ => (),
+            }
+        }
+    }
+    //Gives every dynamic state the value it would have had if it were baked in
+
+    pub(crate) fn check_vertex<V: Vertex>(&self) -> LyeResult<()> {
+        let mismatch = |reason: String| {
+            Err(LyeError::ShaderInterface(format!(
+                "{} doesnt match the pipelines vertex layout ({}) : {}",
+                std::any::type_name::<V>(),
+                self.vertex_type,
+                reason
+            )))
+        };
+
+        let bindings = V::bindings();
+        for expected in &self.vertex_bindings {
+            match bindings.iter().find(|b| b.binding == expected.binding) {
+                Some(binding)
+                    if (binding.stride, binding.input_rate)
+                        == (expected.stride, expected.input_rate) => {}
+                Some(binding) => {
+                    return mismatch(format!(
+                        "binding {} has a stride of {} ({:?}), the pipeline expects {} ({:?})",
+                        binding.binding,
+                        binding.stride,
+                        binding.input_rate,
+                        expected.stride,
+                        expected.input_rate
+                    ))
+                }
+                None => return mismatch(format!("binding {} is missing", expected.binding)),
+            }
+        }
+
+        let attributes = V::attributes();
+        for expected in &self.vertex_attributes {
+            match attributes.iter().find(|a| a.location == expected.location) {
+                Some(attribute)
+                    if (attribute.binding, attribute.format, attribute.offset)
+                        == (expected.binding, expected.format, expected.offset) => {}
+                Some(attribute) => {
+                    return mismatch(format!(
+                        "location {} is {:?} at offset {} of binding {}, the pipeline reads {:?} at offset {} of binding {}",
+                        attribute.location,
+                        attribute.format,
+                        attribute.offset,
+                        attribute.binding,
+                        expected.format,
+                        expected.offset,
+                        expected.binding
+                    ))
+                }
+                None => return mismatch(format!("location {} is missing", expected.location)),
+            }
+        }
+
+        Ok(())
+    }
+    //Only the attributes the vertex shader reads are compared, a vertex type may carry extra data
+
+    pub(crate) fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.layout, None);
+        };
+    }
+}
+
+fn read_spirv(name: &'static str, bytes: &[u8]) -> LyeResult<Vec<u32>> {
+    let mut cursor = std::io::Cursor::new(bytes);
+    ash::util::read_spv(&mut cursor).map_err(|source| LyeError::Shader { name, source })
+}
+
+fn create_shader_module(code: &[u32], device: &ash::Device) -> LyeResult<vk::ShaderModule> {
+    let shader_create_info = vk::ShaderModuleCreateInfo::default().code(code);
+    unsafe { device.create_shader_module(&shader_create_info, None) }.map_err(LyeError::Pipeline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::ColorVertex;
+    use std::mem;
+
+    fn color_pipeline() -> GraphicsPipeline {
+        GraphicsPipeline {
+            pipeline: vk::Pipeline::null(),
+            layout: vk::PipelineLayout::null(),
+            set_layouts: Vec::new(),
+            uniform_blocks: Vec::new(),
+            push_constant_stages: vk::ShaderStageFlags::empty(),
+            vertex_type: std::any::type_name::<ColorVertex>(),
+            vertex_bindings: ColorVertex::bindings(),
+            vertex_attributes: ColorVertex::attributes(),
+            dynamic_states: Vec::new(),
+            line_width: 1.0,
+        }
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct UvVertex {
+        position: [f32; 3],
+        uv: [f32; 2],
+    }
+
+    unsafe impl Vertex for UvVertex {
+        fn bindings() -> Vec<vk::VertexInputBindingDescription> {
+            vec![vk::VertexInputBindingDescription {
+                binding: 0,
+                stride: mem::size_of::<UvVertex>() as u32,
+                input_rate: vk::VertexInputRate::VERTEX,
+            }]
+        }
+
+        fn attributes() -> Vec<vk::VertexInputAttributeDescription> {
+            vec![
+                vk::VertexInputAttributeDescription {
+                    location: 0,
+                    binding: 0,
+                    format: vk::Format::R32G32B32_SFLOAT,
+                    offset: 0,
+                },
+                vk::VertexInputAttributeDescription {
+                    location: 1,
+                    binding: 0,
+                    format: vk::Format::R32G32_SFLOAT,
+                    offset: 12,
+                },
+            ]
+        }
+    }
+
+    #[test]
+    fn vertex_type_matches_its_own_pipeline() {
+        assert!(color_pipeline().check_vertex::<ColorVertex>().is_ok());
+    }
+
+    #[test]
+    fn other_vertex_layouts_are_rejected() {
+        let err = color_pipeline().check_vertex::<UvVertex>().unwrap_err();
+        assert!(
+            matches!(&err, LyeError::ShaderInterface(message) if message.contains("stride of 20")),
+            "{}",
+            err
+        );
+    }
+}
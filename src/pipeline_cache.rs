@@ -0,0 +1,246 @@
+use ash::{self, prelude::VkResult, vk};
+use std::{
+    env, fs,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    ptr,
+};
+
+const HEADER_SIZE: usize = 32;
+//length, version, vendor ID, device ID and the 16 byte pipelineCacheUUID, see VkPipelineCacheHeaderVersionOne
+
+pub(crate) struct PipelineCache {
+    cache: vk::PipelineCache,
+    path: Option<PathBuf>,
+}
+
+pub(crate) fn default_dir(application_name: &str) -> Option<PathBuf> {
+    let base = env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("LOCALAPPDATA")
+                .filter(|_| cfg!(windows))
+                .map(PathBuf::from)
+        })
+        .or_else(|| {
+            env::var_os("HOME").map(|home| match cfg!(target_os = "macos") {
+                true => Path::new(&home).join("Library").join("Caches"),
+                false => Path::new(&home).join(".cache"),
+            })
+        })?;
+    Some(
+        base.join(directory_name(application_name))
+            .join("pipelines"),
+    )
+}
+//The platforms user cache directory, without pulling in a crate for it
+
+fn directory_name(application_name: &str) -> String {
+    let name: String = application_name
+        .chars()
+        .map(
+            |c| match c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                true => c,
+                false => '_',
+            },
+        )
+        .collect();
+    match name.is_empty() {
+        true => "lye".to_owned(),
+        false => name,
+    }
+}
+//Anything that could leave the cache directory or isnt valid on every platform becomes _
+
+fn device_uuid(
+    entry: &ash::Entry,
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    properties: &vk::PhysicalDeviceProperties,
+) -> [u8; vk::UUID_SIZE] {
+    let instance_version = unsafe { entry.try_enumerate_instance_version() }
+        .ok()
+        .flatten()
+        .unwrap_or(vk::API_VERSION_1_0);
+    if instance_version < vk::API_VERSION_1_1 || properties.api_version < vk::API_VERSION_1_1 {
+        return properties.pipeline_cache_uuid;
+    }
+
+    let mut id_properties = vk::PhysicalDeviceIDProperties::default();
+    let mut properties2 = vk::PhysicalDeviceProperties2::default().push_next(&mut id_properties);
+    unsafe { instance.get_physical_device_properties2(physical_device, &mut properties2) };
+    id_properties.device_uuid
+}
+//deviceUUID needs Vulkan 1.1, older devices fall back to the pipeline cache UUID
+
+fn header_matches(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> bool {
+    let word = |index: usize| {
+        data.get(index * 4..index * 4 + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap_or_default()))
+    };
+    data.len() >= HEADER_SIZE
+        && word(0).is_some_and(|length| length as usize >= HEADER_SIZE)
+        && word(1) == Some(vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32)
+        && word(2) == Some(properties.vendor_id)
+        && word(3) == Some(properties.device_id)
+        && data[16..HEADER_SIZE] == properties.pipeline_cache_uuid
+}
+//Drivers have to reject foreign data themselves, checking first avoids relying on that. The header is
+//little endian regardless of the host
+
+impl PipelineCache {
+    pub(crate) fn new(
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        device: &ash::Device,
+        dir: Option<&Path>,
+    ) -> VkResult<Self> {
+        let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        let path = dir.map(|dir| {
+            let uuid: String = device_uuid(entry, instance, physical_device, &properties)
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            dir.join(format!("{}-{:08x}.bin", uuid, properties.driver_version))
+        });
+        //A new driver gets a new file, the old one is simply left behind
+
+        let data = match path.as_deref().map(fs::read) {
+            Some(Ok(data)) if header_matches(&data, &properties) => {
+                log::debug!("Loaded {} bytes of pipeline cache", data.len());
+                data
+            }
+            Some(Ok(_)) => {
+                log::info!("Ignoring a pipeline cache written by a different driver");
+                Vec::new()
+            }
+            _ => Vec::new(),
+        };
+
+        let create = |data: &[u8]| {
+            let create_info = vk::PipelineCacheCreateInfo {
+                s_type: vk::StructureType::PIPELINE_CACHE_CREATE_INFO,
+                p_next: ptr::null(),
+                flags: vk::PipelineCacheCreateFlags::empty(),
+                initial_data_size: data.len(),
+                p_initial_data: data.as_ptr().cast(),
+                _marker: PhantomData,
+            };
+            unsafe { device.create_pipeline_cache(&create_info, None) }
+        };
+        let cache = match create(&data) {
+            Err(err) if !data.is_empty() => {
+                log::warn!("Couldnt load the pipeline cache, starting empty : {}", err);
+                create(&[])?
+            }
+            cache => cache?,
+        };
+
+        Ok(Self { cache, path })
+    }
+
+    pub(crate) fn handle(&self) -> vk::PipelineCache {
+        self.cache
+    }
+
+    pub(crate) fn save(&self, device: &ash::Device) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let data = match unsafe { device.get_pipeline_cache_data(self.cache) } {
+            Ok(data) => data,
+            Err(err) => {
+                log::warn!("Couldnt read the pipeline cache : {}", err);
+                return;
+            }
+        };
+
+        let temporary = path.with_extension("tmp");
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&temporary, &data))
+            .and_then(|_| fs::rename(&temporary, path));
+        match result {
+            Ok(()) => log::debug!(
+                "Saved {} bytes of pipeline cache to {}",
+                data.len(),
+                path.display()
+            ),
+            Err(err) => log::warn!(
+                "Couldnt save the pipeline cache to {} : {}",
+                path.display(),
+                err
+            ),
+        }
+    }
+    //Written next to the target and renamed, so a crash never leaves half a cache behind
+
+    pub(crate) fn destroy(&self, device: &ash::Device) {
+        unsafe { device.destroy_pipeline_cache(self.cache, None) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties() -> vk::PhysicalDeviceProperties {
+        vk::PhysicalDeviceProperties {
+            vendor_id: 0x10de,
+            device_id: 0x2684,
+            pipeline_cache_uuid: [7; vk::UUID_SIZE],
+            ..Default::default()
+        }
+    }
+
+    fn header(properties: &vk::PhysicalDeviceProperties) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend((HEADER_SIZE as u32).to_le_bytes());
+        data.extend((vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32).to_le_bytes());
+        data.extend(properties.vendor_id.to_le_bytes());
+        data.extend(properties.device_id.to_le_bytes());
+        data.extend(properties.pipeline_cache_uuid);
+        data.extend([0xab; 64]);
+        data
+    }
+    //A version one header followed by some driver data
+
+    #[test]
+    fn header_has_to_match_the_device() {
+        let properties = properties();
+        let data = header(&properties);
+        assert!(header_matches(&data, &properties));
+
+        assert!(!header_matches(&[], &properties));
+        assert!(!header_matches(&data[..HEADER_SIZE - 1], &properties));
+
+        let mut wrong_vendor = data.clone();
+        wrong_vendor[8..12].copy_from_slice(&0x1002u32.to_le_bytes());
+        assert!(!header_matches(&wrong_vendor, &properties));
+
+        let mut wrong_uuid = data.clone();
+        wrong_uuid[20] ^= 1;
+        assert!(!header_matches(&wrong_uuid, &properties));
+
+        let mut big_endian = data.clone();
+        big_endian[8..12].copy_from_slice(&properties.vendor_id.to_be_bytes());
+        assert!(!header_matches(&big_endian, &properties));
+    }
+
+    #[test]
+    fn application_names_are_sanitized() {
+        assert_eq!(directory_name("my-app_2"), "my-app_2");
+        assert_eq!(directory_name("My App: 2.0"), "My_App__2_0");
+        assert_eq!(directory_name("../../etc"), "______etc");
+        assert_eq!(directory_name("C:\\temp"), "C__temp");
+        assert_eq!(directory_name(""), "lye");
+
+        if let Some(dir) = default_dir("../my app") {
+            assert!(dir.ends_with(Path::new("___my_app").join("pipelines")));
+        }
+    }
+    //default_dir is None without any cache or home directory in the environment
+}
@@ -0,0 +1,192 @@
+use ash::vk;
+
+const SURFACE_FORMATS: [vk::Format; 4] = [
+    vk::Format::B8G8R8A8_SRGB,
+    vk::Format::R8G8B8A8_SRGB,
+    vk::Format::B8G8R8A8_UNORM,
+    vk::Format::R8G8B8A8_UNORM,
+];
+//In order of preference, sRGB formats let the hardware encode the linear shader output
+
+/// Which present mode the swapchain should use, falling back to FIFO when unsupported.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PresentPreference {
+    /// FIFO, waits for vblank.
+    #[default]
+    VSync,
+    /// FIFO_RELAXED, tears instead of waiting for a late frame.
+    AdaptiveVSync,
+    /// MAILBOX, replaces the queued frame without tearing or limiting the frame rate.
+    Mailbox,
+    /// IMMEDIATE, presents right away and may tear.
+    Immediate,
+}
+
+impl PresentPreference {
+    /// Present modes to try, in order.
+    pub fn candidates(self) -> &'static [vk::PresentModeKHR] {
+        match self {
+            PresentPreference::VSync => &[vk::PresentModeKHR::FIFO],
+            PresentPreference::AdaptiveVSync => {
+                &[vk::PresentModeKHR::FIFO_RELAXED, vk::PresentModeKHR::FIFO]
+            }
+            PresentPreference::Mailbox => &[vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::FIFO],
+            PresentPreference::Immediate => &[
+                vk::PresentModeKHR::IMMEDIATE,
+                vk::PresentModeKHR::MAILBOX,
+                vk::PresentModeKHR::FIFO_RELAXED,
+                vk::PresentModeKHR::FIFO,
+            ],
+        }
+    }
+    //FIFO ends every list since it is the only mode every surface has to support
+
+    /// Whether presenting waits for vblank.
+    pub fn is_vsync(self) -> bool {
+        matches!(
+            self,
+            PresentPreference::VSync | PresentPreference::AdaptiveVSync
+        )
+    }
+}
+
+pub(crate) fn choose_present_mode(
+    preference: PresentPreference,
+    supported: &[vk::PresentModeKHR],
+) -> vk::PresentModeKHR {
+    preference
+        .candidates()
+        .iter()
+        .copied()
+        .find(|mode| supported.contains(mode))
+        .unwrap_or(vk::PresentModeKHR::FIFO)
+}
+
+pub(crate) fn choose_surface_format(supported: &[vk::SurfaceFormatKHR]) -> vk::SurfaceFormatKHR {
+    if let [only] = supported {
+        if only.format == vk::Format::UNDEFINED {
+            return vk::SurfaceFormatKHR {
+                format: SURFACE_FORMATS[0],
+                color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            };
+        }
+    }
+    //A single UNDEFINED entry means the surface takes any format
+
+    SURFACE_FORMATS
+        .iter()
+        .find_map(|&format| {
+            supported.iter().copied().find(|supported| {
+                supported.format == format
+                    && supported.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+            })
+        })
+        .or_else(|| supported.first().copied())
+        .unwrap_or_default()
+}
+//Falls back to whatever the surface lists first, device selection already rejected surfaces without formats
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn srgb(format: vk::Format) -> vk::SurfaceFormatKHR {
+        vk::SurfaceFormatKHR {
+            format,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        }
+    }
+
+    #[test]
+    fn present_mode_follows_the_preference() {
+        let all = [
+            vk::PresentModeKHR::FIFO,
+            vk::PresentModeKHR::FIFO_RELAXED,
+            vk::PresentModeKHR::MAILBOX,
+            vk::PresentModeKHR::IMMEDIATE,
+        ];
+        assert_eq!(
+            choose_present_mode(PresentPreference::VSync, &all),
+            vk::PresentModeKHR::FIFO
+        );
+        assert_eq!(
+            choose_present_mode(PresentPreference::AdaptiveVSync, &all),
+            vk::PresentModeKHR::FIFO_RELAXED
+        );
+        assert_eq!(
+            choose_present_mode(PresentPreference::Mailbox, &all),
+            vk::PresentModeKHR::MAILBOX
+        );
+        assert_eq!(
+            choose_present_mode(PresentPreference::Immediate, &all),
+            vk::PresentModeKHR::IMMEDIATE
+        );
+    }
+
+    #[test]
+    fn present_mode_falls_back_to_fifo() {
+        let fifo_only = [vk::PresentModeKHR::FIFO];
+        for preference in [
+            PresentPreference::AdaptiveVSync,
+            PresentPreference::Mailbox,
+            PresentPreference::Immediate,
+        ] {
+            assert_eq!(
+                choose_present_mode(preference, &fifo_only),
+                vk::PresentModeKHR::FIFO
+            );
+        }
+        assert_eq!(
+            choose_present_mode(
+                PresentPreference::Immediate,
+                &[vk::PresentModeKHR::FIFO, vk::PresentModeKHR::MAILBOX]
+            ),
+            vk::PresentModeKHR::MAILBOX
+        );
+        assert_eq!(
+            choose_present_mode(PresentPreference::Mailbox, &[]),
+            vk::PresentModeKHR::FIFO
+        );
+    }
+
+    #[test]
+    fn surface_format_prefers_srgb() {
+        let supported = [
+            srgb(vk::Format::R8G8B8A8_UNORM),
+            srgb(vk::Format::R8G8B8A8_SRGB),
+            srgb(vk::Format::B8G8R8A8_UNORM),
+        ];
+        assert_eq!(
+            choose_surface_format(&supported),
+            srgb(vk::Format::R8G8B8A8_SRGB)
+        );
+    }
+
+    #[test]
+    fn surface_format_ignores_other_color_spaces() {
+        let supported = [
+            vk::SurfaceFormatKHR {
+                format: vk::Format::B8G8R8A8_SRGB,
+                color_space: vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+            },
+            srgb(vk::Format::B8G8R8A8_UNORM),
+        ];
+        assert_eq!(
+            choose_surface_format(&supported),
+            srgb(vk::Format::B8G8R8A8_UNORM)
+        );
+    }
+
+    #[test]
+    fn surface_format_fallbacks() {
+        assert_eq!(
+            choose_surface_format(&[srgb(vk::Format::UNDEFINED)]),
+            srgb(vk::Format::B8G8R8A8_SRGB)
+        );
+        let unknown = vk::SurfaceFormatKHR {
+            format: vk::Format::A2B10G10R10_UNORM_PACK32,
+            color_space: vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+        };
+        assert_eq!(choose_surface_format(&[unknown]), unknown);
+    }
+}
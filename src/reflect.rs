@@ -0,0 +1,1254 @@
+use crate::error::{LyeError, LyeResult};
+use ash::vk;
+use std::{collections::HashMap, fmt, io, ptr};
+
+const MAGIC: u32 = 0x0723_0203;
+
+const OP_NAME: u32 = 5;
+const OP_MEMBER_NAME: u32 = 6;
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+const OP_TYPE_ACCELERATION_STRUCTURE: u32 = 5341;
+
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_INPUT: u32 = 1;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_OUTPUT: u32 = 3;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+//Only the parts of the SPIR-V spec needed to describe a pipeline interface
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum NumericType {
+    Float,
+    Sint,
+    Uint,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct InterfaceFormat {
+    pub(crate) numeric: NumericType,
+    pub(crate) components: u32,
+    pub(crate) width: u32,
+}
+
+impl InterfaceFormat {
+    pub(crate) fn vertex_format(self) -> vk::Format {
+        use vk::Format as F;
+        let formats = match (self.numeric, self.width) {
+            (NumericType::Float, 32) => [
+                F::R32_SFLOAT,
+                F::R32G32_SFLOAT,
+                F::R32G32B32_SFLOAT,
+                F::R32G32B32A32_SFLOAT,
+            ],
+            (NumericType::Sint, 32) => [
+                F::R32_SINT,
+                F::R32G32_SINT,
+                F::R32G32B32_SINT,
+                F::R32G32B32A32_SINT,
+            ],
+            (NumericType::Uint, 32) => [
+                F::R32_UINT,
+                F::R32G32_UINT,
+                F::R32G32B32_UINT,
+                F::R32G32B32A32_UINT,
+            ],
+            (NumericType::Float, 64) => [
+                F::R64_SFLOAT,
+                F::R64G64_SFLOAT,
+                F::R64G64B64_SFLOAT,
+                F::R64G64B64A64_SFLOAT,
+            ],
+            (NumericType::Sint, 64) => [
+                F::R64_SINT,
+                F::R64G64_SINT,
+                F::R64G64B64_SINT,
+                F::R64G64B64A64_SINT,
+            ],
+            (NumericType::Uint, 64) => [
+                F::R64_UINT,
+                F::R64G64_UINT,
+                F::R64G64B64_UINT,
+                F::R64G64B64A64_UINT,
+            ],
+            _ => return F::UNDEFINED,
+        };
+        formats
+            .get(self.components as usize - 1)
+            .copied()
+            .unwrap_or(F::UNDEFINED)
+    }
+    //The format a vertex buffer would naturally use for this input, normalized formats work too
+}
+
+impl fmt::Display for InterfaceFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (scalar, prefix) = match (self.numeric, self.width) {
+            (NumericType::Float, 64) => ("double", "d"),
+            (NumericType::Float, _) => ("float", ""),
+            (NumericType::Sint, _) => ("int", "i"),
+            (NumericType::Uint, _) => ("uint", "u"),
+        };
+        match self.components {
+            1 => write!(f, "{}", scalar)?,
+            components => write!(f, "{}vec{}", prefix, components)?,
+        }
+        match (self.numeric, self.width) {
+            (_, 32) | (NumericType::Float, 64) => Ok(()),
+            (_, width) => write!(f, " ({} bit)", width),
+        }
+    }
+}
+//Printed like the GLSL type, so errors read like the shader source
+
+fn vertex_format_numeric(format: vk::Format) -> NumericType {
+    use vk::Format as F;
+    match format {
+        F::R8_UINT
+        | F::R8G8_UINT
+        | F::R8G8B8_UINT
+        | F::B8G8R8_UINT
+        | F::R8G8B8A8_UINT
+        | F::B8G8R8A8_UINT
+        | F::A8B8G8R8_UINT_PACK32
+        | F::A2R10G10B10_UINT_PACK32
+        | F::A2B10G10R10_UINT_PACK32
+        | F::R16_UINT
+        | F::R16G16_UINT
+        | F::R16G16B16_UINT
+        | F::R16G16B16A16_UINT
+        | F::R32_UINT
+        | F::R32G32_UINT
+        | F::R32G32B32_UINT
+        | F::R32G32B32A32_UINT
+        | F::R64_UINT
+        | F::R64G64_UINT
+        | F::R64G64B64_UINT
+        | F::R64G64B64A64_UINT => NumericType::Uint,
+        F::R8_SINT
+        | F::R8G8_SINT
+        | F::R8G8B8_SINT
+        | F::B8G8R8_SINT
+        | F::R8G8B8A8_SINT
+        | F::B8G8R8A8_SINT
+        | F::A8B8G8R8_SINT_PACK32
+        | F::A2R10G10B10_SINT_PACK32
+        | F::A2B10G10R10_SINT_PACK32
+        | F::R16_SINT
+        | F::R16G16_SINT
+        | F::R16G16B16_SINT
+        | F::R16G16B16A16_SINT
+        | F::R32_SINT
+        | F::R32G32_SINT
+        | F::R32G32B32_SINT
+        | F::R32G32B32A32_SINT
+        | F::R64_SINT
+        | F::R64G64_SINT
+        | F::R64G64B64_SINT
+        | F::R64G64B64A64_SINT => NumericType::Sint,
+        _ => NumericType::Float,
+    }
+}
+//Normalized, scaled and sRGB formats all arrive in the shader as floats
+
+fn vertex_format_size(format: vk::Format) -> Option<(u32, u32)> {
+    use vk::Format as F;
+    let size = match format {
+        F::R8_UNORM
+        | F::R8_SNORM
+        | F::R8_USCALED
+        | F::R8_SSCALED
+        | F::R8_UINT
+        | F::R8_SINT
+        | F::R8_SRGB => (1, 8),
+        F::R8G8_UNORM
+        | F::R8G8_SNORM
+        | F::R8G8_USCALED
+        | F::R8G8_SSCALED
+        | F::R8G8_UINT
+        | F::R8G8_SINT
+        | F::R8G8_SRGB => (2, 8),
+        F::R8G8B8_UNORM
+        | F::R8G8B8_SNORM
+        | F::R8G8B8_USCALED
+        | F::R8G8B8_SSCALED
+        | F::R8G8B8_UINT
+        | F::R8G8B8_SINT
+        | F::R8G8B8_SRGB
+        | F::B8G8R8_UNORM
+        | F::B8G8R8_SNORM
+        | F::B8G8R8_USCALED
+        | F::B8G8R8_SSCALED
+        | F::B8G8R8_UINT
+        | F::B8G8R8_SINT
+        | F::B8G8R8_SRGB => (3, 8),
+        F::R8G8B8A8_UNORM
+        | F::R8G8B8A8_SNORM
+        | F::R8G8B8A8_USCALED
+        | F::R8G8B8A8_SSCALED
+        | F::R8G8B8A8_UINT
+        | F::R8G8B8A8_SINT
+        | F::R8G8B8A8_SRGB
+        | F::B8G8R8A8_UNORM
+        | F::B8G8R8A8_SNORM
+        | F::B8G8R8A8_USCALED
+        | F::B8G8R8A8_SSCALED
+        | F::B8G8R8A8_UINT
+        | F::B8G8R8A8_SINT
+        | F::B8G8R8A8_SRGB
+        | F::A8B8G8R8_UNORM_PACK32
+        | F::A8B8G8R8_SNORM_PACK32
+        | F::A8B8G8R8_USCALED_PACK32
+        | F::A8B8G8R8_SSCALED_PACK32
+        | F::A8B8G8R8_UINT_PACK32
+        | F::A8B8G8R8_SINT_PACK32
+        | F::A8B8G8R8_SRGB_PACK32 => (4, 8),
+        F::A2R10G10B10_UNORM_PACK32
+        | F::A2R10G10B10_SNORM_PACK32
+        | F::A2R10G10B10_USCALED_PACK32
+        | F::A2R10G10B10_SSCALED_PACK32
+        | F::A2R10G10B10_UINT_PACK32
+        | F::A2R10G10B10_SINT_PACK32
+        | F::A2B10G10R10_UNORM_PACK32
+        | F::A2B10G10R10_SNORM_PACK32
+        | F::A2B10G10R10_USCALED_PACK32
+        | F::A2B10G10R10_SSCALED_PACK32
+        | F::A2B10G10R10_UINT_PACK32
+        | F::A2B10G10R10_SINT_PACK32 => (4, 10),
+        F::B10G11R11_UFLOAT_PACK32 => (3, 11),
+        F::R16_UNORM
+        | F::R16_SNORM
+        | F::R16_USCALED
+        | F::R16_SSCALED
+        | F::R16_UINT
+        | F::R16_SINT
+        | F::R16_SFLOAT => (1, 16),
+        F::R16G16_UNORM
+        | F::R16G16_SNORM
+        | F::R16G16_USCALED
+        | F::R16G16_SSCALED
+        | F::R16G16_UINT
+        | F::R16G16_SINT
+        | F::R16G16_SFLOAT => (2, 16),
+        F::R16G16B16_UNORM
+        | F::R16G16B16_SNORM
+        | F::R16G16B16_USCALED
+        | F::R16G16B16_SSCALED
+        | F::R16G16B16_UINT
+        | F::R16G16B16_SINT
+        | F::R16G16B16_SFLOAT => (3, 16),
+        F::R16G16B16A16_UNORM
+        | F::R16G16B16A16_SNORM
+        | F::R16G16B16A16_USCALED
+        | F::R16G16B16A16_SSCALED
+        | F::R16G16B16A16_UINT
+        | F::R16G16B16A16_SINT
+        | F::R16G16B16A16_SFLOAT => (4, 16),
+        F::R32_UINT | F::R32_SINT | F::R32_SFLOAT => (1, 32),
+        F::R32G32_UINT | F::R32G32_SINT | F::R32G32_SFLOAT => (2, 32),
+        F::R32G32B32_UINT | F::R32G32B32_SINT | F::R32G32B32_SFLOAT => (3, 32),
+        F::R32G32B32A32_UINT | F::R32G32B32A32_SINT | F::R32G32B32A32_SFLOAT => (4, 32),
+        F::R64_UINT | F::R64_SINT | F::R64_SFLOAT => (1, 64),
+        F::R64G64_UINT | F::R64G64_SINT | F::R64G64_SFLOAT => (2, 64),
+        F::R64G64B64_UINT | F::R64G64B64_SINT | F::R64G64B64_SFLOAT => (3, 64),
+        F::R64G64B64A64_UINT | F::R64G64B64A64_SINT | F::R64G64B64A64_SFLOAT => (4, 64),
+        _ => return None,
+    };
+    Some(size)
+}
+//Component count and bits per component of the vertex formats lye knows, None for anything else
+
+#[derive(Clone, Debug)]
+pub(crate) struct InterfaceVariable {
+    pub(crate) name: String,
+    pub(crate) location: u32,
+    pub(crate) format: InterfaceFormat,
+}
+//Matrices and arrays are split into one entry per location they occupy
+
+#[derive(Clone, Debug)]
+pub(crate) struct DescriptorBinding {
+    pub(crate) name: String,
+    pub(crate) set: u32,
+    pub(crate) binding: u32,
+    pub(crate) descriptor_type: vk::DescriptorType,
+    pub(crate) count: u32,
+    pub(crate) block: Option<BlockLayout>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct BlockLayout {
+    pub(crate) size: u32,
+    pub(crate) members: Vec<BlockMember>,
+}
+//Only for uniform and storage buffers, as laid out by the shader
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct BlockMember {
+    pub(crate) name: String,
+    pub(crate) offset: u32,
+    pub(crate) size: u32,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct ShaderReflection {
+    pub(crate) name: &'static str,
+    pub(crate) stage: vk::ShaderStageFlags,
+    pub(crate) inputs: Vec<InterfaceVariable>,
+    pub(crate) outputs: Vec<InterfaceVariable>,
+    pub(crate) push_constant_size: Option<u32>,
+    pub(crate) bindings: Vec<DescriptorBinding>,
+}
+
+#[derive(Clone, Debug)]
+enum Type {
+    Scalar { numeric: NumericType, width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Array { element: u32, length: Option<u32> },
+    Struct { members: Vec<u32> },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    AccelerationStructure,
+    Pointer { pointee: u32 },
+    Other,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Decorations {
+    location: Option<u32>,
+    binding: Option<u32>,
+    set: Option<u32>,
+    array_stride: Option<u32>,
+    block: bool,
+    buffer_block: bool,
+    built_in: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct MemberDecorations {
+    offset: u32,
+    matrix_stride: Option<u32>,
+    built_in: bool,
+}
+
+#[derive(Default)]
+struct Module {
+    names: HashMap<u32, String>,
+    member_names: HashMap<(u32, u32), String>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    decorations: HashMap<u32, Decorations>,
+    member_decorations: HashMap<(u32, u32), MemberDecorations>,
+    variables: Vec<(u32, u32, u32)>,
+    entry_point: Option<(u32, Vec<u32>)>,
+}
+//variables holds (id, pointer type, storage class), entry_point (execution model, interface ids)
+
+fn string(words: &[u32]) -> (String, usize) {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .take_while(|&byte| byte != 0)
+        .collect();
+    (
+        String::from_utf8_lossy(&bytes).into_owned(),
+        bytes.len() / 4 + 1,
+    )
+}
+//Literal strings are nul terminated and padded to whole words, returns the words used
+
+fn stage_name(stage: vk::ShaderStageFlags) -> &'static str {
+    match stage {
+        vk::ShaderStageFlags::VERTEX => "vertex shader",
+        vk::ShaderStageFlags::FRAGMENT => "fragment shader",
+        vk::ShaderStageFlags::COMPUTE => "compute shader",
+        _ => "shader",
+    }
+}
+
+impl Module {
+    fn parse(name: &'static str, words: &[u32]) -> LyeResult<Self> {
+        let invalid = |reason: &str| LyeError::Shader {
+            name,
+            source: io::Error::new(io::ErrorKind::InvalidData, reason.to_owned()),
+        };
+        if words.len() < 5 || words[0] != MAGIC {
+            return Err(invalid("Missing SPIR-V header"));
+        }
+
+        let mut module = Module::default();
+        let mut rest = &words[5..];
+        while let Some(&first) = rest.first() {
+            let (count, opcode) = ((first >> 16) as usize, first & 0xffff);
+            if count == 0 || count > rest.len() {
+                return Err(invalid("Truncated SPIR-V instruction"));
+            }
+            let operands = &rest[1..count];
+            rest = &rest[count..];
+            let operand = |index: usize| {
+                operands
+                    .get(index)
+                    .copied()
+                    .ok_or_else(|| invalid("Truncated SPIR-V instruction"))
+            };
+
+            match opcode {
+                OP_NAME => {
+                    let target = operand(0)?;
+                    module.names.insert(target, string(&operands[1..]).0);
+                }
+                OP_MEMBER_NAME => {
+                    let (target, member) = (operand(0)?, operand(1)?);
+                    module
+                        .member_names
+                        .insert((target, member), string(&operands[2..]).0);
+                }
+                OP_ENTRY_POINT if module.entry_point.is_none() => {
+                    let execution_model = operand(0)?;
+                    let (_, used) = string(operands.get(2..).unwrap_or_default());
+                    let interface = operands.get(2 + used..).unwrap_or_default().to_vec();
+                    module.entry_point = Some((execution_model, interface));
+                }
+                OP_TYPE_BOOL => {
+                    module.types.insert(operand(0)?, Type::Other);
+                }
+                OP_TYPE_INT => {
+                    let numeric = match operand(2)? {
+                        0 => NumericType::Uint,
+                        _ => NumericType::Sint,
+                    };
+                    let width = operand(1)?;
+                    module
+                        .types
+                        .insert(operand(0)?, Type::Scalar { numeric, width });
+                }
+                OP_TYPE_FLOAT => {
+                    let width = operand(1)?;
+                    module.types.insert(
+                        operand(0)?,
+                        Type::Scalar {
+                            numeric: NumericType::Float,
+                            width,
+                        },
+                    );
+                }
+                OP_TYPE_VECTOR => {
+                    let (component, count) = (operand(1)?, operand(2)?);
+                    module
+                        .types
+                        .insert(operand(0)?, Type::Vector { component, count });
+                }
+                OP_TYPE_MATRIX => {
+                    let (column, count) = (operand(1)?, operand(2)?);
+                    module
+                        .types
+                        .insert(operand(0)?, Type::Matrix { column, count });
+                }
+                OP_TYPE_IMAGE => {
+                    let (dim, sampled) = (operand(2)?, operand(6)?);
+                    module
+                        .types
+                        .insert(operand(0)?, Type::Image { dim, sampled });
+                }
+                OP_TYPE_SAMPLER => {
+                    module.types.insert(operand(0)?, Type::Sampler);
+                }
+                OP_TYPE_SAMPLED_IMAGE => {
+                    module.types.insert(operand(0)?, Type::SampledImage);
+                }
+                OP_TYPE_ACCELERATION_STRUCTURE => {
+                    module
+                        .types
+                        .insert(operand(0)?, Type::AccelerationStructure);
+                }
+                OP_TYPE_ARRAY => {
+                    let element = operand(1)?;
+                    let length = module.constants.get(&operand(2)?).copied();
+                    module
+                        .types
+                        .insert(operand(0)?, Type::Array { element, length });
+                }
+                OP_TYPE_RUNTIME_ARRAY => {
+                    let element = operand(1)?;
+                    module.types.insert(
+                        operand(0)?,
+                        Type::Array {
+                            element,
+                            length: None,
+                        },
+                    );
+                }
+                OP_TYPE_STRUCT => {
+                    let id = operand(0)?;
+                    let members = operands[1..].to_vec();
+                    module.types.insert(id, Type::Struct { members });
+                }
+                OP_TYPE_POINTER => {
+                    let pointee = operand(2)?;
+                    module.types.insert(operand(0)?, Type::Pointer { pointee });
+                }
+                OP_CONSTANT => {
+                    module.constants.insert(operand(1)?, operand(2)?);
+                }
+                OP_VARIABLE => {
+                    module
+                        .variables
+                        .push((operand(1)?, operand(0)?, operand(2)?));
+                }
+                OP_DECORATE => {
+                    let decorations = module.decorations.entry(operand(0)?).or_default();
+                    match operand(1)? {
+                        DECORATION_BLOCK => decorations.block = true,
+                        DECORATION_BUFFER_BLOCK => decorations.buffer_block = true,
+                        DECORATION_BUILT_IN => decorations.built_in = true,
+                        DECORATION_ARRAY_STRIDE => decorations.array_stride = Some(operand(2)?),
+                        DECORATION_LOCATION => decorations.location = Some(operand(2)?),
+                        DECORATION_BINDING => decorations.binding = Some(operand(2)?),
+                        DECORATION_DESCRIPTOR_SET => decorations.set = Some(operand(2)?),
+                        _ => (),
+                    }
+                }
+                OP_MEMBER_DECORATE => {
+                    let decorations = module
+                        .member_decorations
+                        .entry((operand(0)?, operand(1)?))
+                        .or_default();
+                    match operand(2)? {
+                        DECORATION_BUILT_IN => decorations.built_in = true,
+                        DECORATION_OFFSET => decorations.offset = operand(3)?,
+                        DECORATION_MATRIX_STRIDE => decorations.matrix_stride = Some(operand(3)?),
+                        _ => (),
+                    }
+                }
+                _ => (),
+            }
+        }
+        //Types are declared before use, so array lengths can be looked up right away
+
+        Ok(module)
+    }
+
+    fn ty(&self, id: u32) -> &Type {
+        self.types.get(&id).unwrap_or(&Type::Other)
+    }
+
+    fn name(&self, id: u32) -> String {
+        self.names
+            .get(&id)
+            .filter(|name| !name.is_empty())
+            .cloned()
+            .unwrap_or_else(|| format!("%{}", id))
+    }
+
+    fn decorations(&self, id: u32) -> Decorations {
+        self.decorations.get(&id).copied().unwrap_or_default()
+    }
+
+    fn is_built_in(&self, variable: u32, ty: u32) -> bool {
+        self.decorations(variable).built_in
+            || match self.ty(ty) {
+                Type::Struct { members } => (0..members.len() as u32).any(|member| {
+                    self.member_decorations
+                        .get(&(ty, member))
+                        .is_some_and(|decorations| decorations.built_in)
+                }),
+                _ => false,
+            }
+    }
+    //gl_Position and friends dont take part in location matching
+
+    fn interface_formats(&self, ty: u32) -> Option<Vec<InterfaceFormat>> {
+        match *self.ty(ty) {
+            Type::Scalar { numeric, width } => Some(vec![InterfaceFormat {
+                numeric,
+                components: 1,
+                width,
+            }]),
+            Type::Vector { component, count } => match *self.ty(component) {
+                Type::Scalar { numeric, width } => Some(vec![InterfaceFormat {
+                    numeric,
+                    components: count,
+                    width,
+                }]),
+                _ => None,
+            },
+            Type::Matrix { column, count } => {
+                let column = self.interface_formats(column)?;
+                Some(column.repeat(count as usize))
+            }
+            Type::Array {
+                element,
+                length: Some(length),
+            } => {
+                let element = self.interface_formats(element)?;
+                Some(element.repeat(length as usize))
+            }
+            _ => None,
+        }
+    }
+    //One format per location, a mat4 takes four vec4 locations
+
+    fn size(&self, ty: u32, matrix_stride: Option<u32>) -> u32 {
+        match self.ty(ty) {
+            &Type::Scalar { width, .. } => width / 8,
+            &Type::Vector { component, count } => count * self.size(component, None),
+            &Type::Matrix { column, count } => {
+                count * matrix_stride.unwrap_or_else(|| self.size(column, None))
+            }
+            &Type::Array {
+                element,
+                length: Some(length),
+            } => {
+                let stride = self
+                    .decorations(ty)
+                    .array_stride
+                    .unwrap_or_else(|| self.size(element, matrix_stride));
+                length * stride
+            }
+            Type::Struct { members } => members
+                .iter()
+                .enumerate()
+                .map(|(index, &member)| {
+                    let decorations = self
+                        .member_decorations
+                        .get(&(ty, index as u32))
+                        .copied()
+                        .unwrap_or_default();
+                    decorations.offset + self.size(member, decorations.matrix_stride)
+                })
+                .max()
+                .unwrap_or(0),
+            _ => 0,
+        }
+    }
+    //Struct sizes come from the explicit member offsets, so std140 and std430 padding is respected
+
+    fn block_layout(&self, mut ty: u32) -> Option<BlockLayout> {
+        while let &Type::Array { element, .. } = self.ty(ty) {
+            ty = element;
+        }
+        let mut base = 0;
+        while let Type::Struct { members } = self.ty(ty) {
+            match members[..] {
+                [member] if matches!(self.ty(member), Type::Struct { .. }) => {
+                    base += self
+                        .member_decorations
+                        .get(&(ty, 0))
+                        .map_or(0, |decorations| decorations.offset);
+                    ty = member;
+                }
+                _ => break,
+            }
+        }
+        //naga wraps every block in a struct holding the declared one
+        let Type::Struct { members } = self.ty(ty) else {
+            return None;
+        };
+        let members = members
+            .iter()
+            .enumerate()
+            .map(|(index, &member)| {
+                let index = index as u32;
+                let decorations = self
+                    .member_decorations
+                    .get(&(ty, index))
+                    .copied()
+                    .unwrap_or_default();
+                BlockMember {
+                    name: self
+                        .member_names
+                        .get(&(ty, index))
+                        .filter(|name| !name.is_empty())
+                        .cloned()
+                        .unwrap_or_else(|| format!("member {}", index)),
+                    offset: base + decorations.offset,
+                    size: self.size(member, decorations.matrix_stride),
+                }
+            })
+            .collect();
+        Some(BlockLayout {
+            size: self.size(ty, None),
+            members,
+        })
+    }
+    //Top level members only, enough to compare against the CPU side struct
+
+    fn descriptor(
+        &self,
+        variable: u32,
+        mut ty: u32,
+        storage_class: u32,
+    ) -> Result<(vk::DescriptorType, u32), String> {
+        let mut count = 1;
+        while let &Type::Array { element, length } = self.ty(ty) {
+            count *= length.ok_or_else(|| {
+                format!(
+                    "{} is a runtime sized descriptor array, which isnt supported",
+                    self.name(variable)
+                )
+            })?;
+            ty = element;
+        }
+
+        let descriptor_type = match (self.ty(ty), storage_class) {
+            (Type::Struct { .. }, STORAGE_STORAGE_BUFFER) => vk::DescriptorType::STORAGE_BUFFER,
+            (Type::Struct { .. }, _) if self.decorations(ty).buffer_block => {
+                vk::DescriptorType::STORAGE_BUFFER
+            }
+            (Type::Struct { .. }, _) if self.decorations(ty).block => {
+                vk::DescriptorType::UNIFORM_BUFFER
+            }
+            (&Type::Image { dim, sampled }, _) => match (dim, sampled) {
+                (DIM_BUFFER, 2) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                (DIM_BUFFER, _) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                (DIM_SUBPASS_DATA, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+                (_, 2) => vk::DescriptorType::STORAGE_IMAGE,
+                _ => vk::DescriptorType::SAMPLED_IMAGE,
+            },
+            (Type::Sampler, _) => vk::DescriptorType::SAMPLER,
+            (Type::SampledImage, _) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            (Type::AccelerationStructure, _) => vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+            _ => {
+                return Err(format!(
+                    "{} has a type that cant be bound as a descriptor",
+                    self.name(variable)
+                ))
+            }
+        };
+        Ok((descriptor_type, count))
+    }
+}
+
+impl ShaderReflection {
+    pub(crate) fn new(name: &'static str, words: &[u32]) -> LyeResult<Self> {
+        let module = Module::parse(name, words)?;
+        let Some((execution_model, interface)) = &module.entry_point else {
+            return Err(LyeError::ShaderInterface(format!(
+                "{} has no entry point",
+                name
+            )));
+        };
+        let stage = match execution_model {
+            0 => vk::ShaderStageFlags::VERTEX,
+            1 => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+            2 => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+            3 => vk::ShaderStageFlags::GEOMETRY,
+            4 => vk::ShaderStageFlags::FRAGMENT,
+            5 => vk::ShaderStageFlags::COMPUTE,
+            _ => vk::ShaderStageFlags::ALL,
+        };
+        let error = |reason: String| {
+            LyeError::ShaderInterface(format!("{} ({}) : {}", stage_name(stage), name, reason))
+        };
+
+        let mut reflection = ShaderReflection {
+            name,
+            stage,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            push_constant_size: None,
+            bindings: Vec::new(),
+        };
+        for &(variable, pointer, storage_class) in &module.variables {
+            let &Type::Pointer { pointee: ty } = module.ty(pointer) else {
+                continue;
+            };
+
+            match storage_class {
+                STORAGE_INPUT | STORAGE_OUTPUT => {
+                    if !interface.contains(&variable) || module.is_built_in(variable, ty) {
+                        continue;
+                    }
+                    let name = module.name(variable);
+                    let location = module
+                        .decorations(variable)
+                        .location
+                        .ok_or_else(|| error(format!("{} has no location decoration", name)))?;
+                    let formats = module.interface_formats(ty).ok_or_else(|| {
+                        error(format!(
+                            "{} at location {} has an unsupported type",
+                            name, location
+                        ))
+                    })?;
+
+                    let list = match storage_class {
+                        STORAGE_INPUT => &mut reflection.inputs,
+                        _ => &mut reflection.outputs,
+                    };
+                    list.extend(
+                        formats
+                            .into_iter()
+                            .zip(location..)
+                            .map(|(format, location)| InterfaceVariable {
+                                name: name.clone(),
+                                location,
+                                format,
+                            }),
+                    );
+                }
+                STORAGE_PUSH_CONSTANT => {
+                    let size = module.size(ty, None);
+                    reflection.push_constant_size =
+                        Some(reflection.push_constant_size.unwrap_or(0).max(size));
+                }
+                STORAGE_UNIFORM_CONSTANT | STORAGE_UNIFORM | STORAGE_STORAGE_BUFFER => {
+                    let (descriptor_type, count) = module
+                        .descriptor(variable, ty, storage_class)
+                        .map_err(error)?;
+                    let decorations = module.decorations(variable);
+                    reflection.bindings.push(DescriptorBinding {
+                        name: module.name(variable),
+                        set: decorations.set.unwrap_or(0),
+                        binding: decorations.binding.unwrap_or(0),
+                        descriptor_type,
+                        count,
+                        block: matches!(
+                            descriptor_type,
+                            vk::DescriptorType::UNIFORM_BUFFER | vk::DescriptorType::STORAGE_BUFFER
+                        )
+                        .then(|| module.block_layout(ty))
+                        .flatten(),
+                    });
+                }
+                _ => (),
+            }
+        }
+        reflection.inputs.sort_by_key(|input| input.location);
+        reflection.outputs.sort_by_key(|output| output.location);
+
+        Ok(reflection)
+    }
+}
+
+pub(crate) fn check_vertex_input(
+    vertex: &ShaderReflection,
+    bindings: &[vk::VertexInputBindingDescription],
+    attributes: &[vk::VertexInputAttributeDescription],
+) -> LyeResult<Vec<vk::VertexInputAttributeDescription>> {
+    for attribute in attributes {
+        if !bindings
+            .iter()
+            .any(|binding| binding.binding == attribute.binding)
+        {
+            return Err(LyeError::ShaderInterface(format!(
+                "Vertex attribute at location {} reads binding {}, which isnt described",
+                attribute.location, attribute.binding
+            )));
+        }
+    }
+
+    for input in &vertex.inputs {
+        let Some(attribute) = attributes
+            .iter()
+            .find(|attribute| attribute.location == input.location)
+        else {
+            return Err(LyeError::ShaderInterface(format!(
+                "Vertex shader input {} at location {} ({}) has no vertex attribute, expected one like {:?}",
+                input.name,
+                input.location,
+                input.format,
+                input.format.vertex_format()
+            )));
+        };
+        let Some((components, width)) = vertex_format_size(attribute.format) else {
+            return Err(LyeError::ShaderInterface(format!(
+                "Vertex attribute at location {} is {:?}, which isnt a supported vertex format",
+                attribute.location, attribute.format
+            )));
+        };
+        if vertex_format_numeric(attribute.format) != input.format.numeric
+            || (width == 64) != (input.format.width == 64)
+            || (width == 64 && components < input.format.components)
+        {
+            return Err(LyeError::ShaderInterface(format!(
+                "Vertex attribute at location {} is {:?}, but vertex shader input {} reads {}, expected a format like {:?}",
+                attribute.location,
+                attribute.format,
+                input.name,
+                input.format,
+                input.format.vertex_format()
+            )));
+        }
+    }
+
+    Ok(attributes
+        .iter()
+        .filter(|attribute| {
+            let used = vertex
+                .inputs
+                .iter()
+                .any(|input| input.location == attribute.location);
+            if !used {
+                log::debug!(
+                    "Vertex attribute at location {} isnt read by {}, skipping it",
+                    attribute.location,
+                    vertex.name
+                );
+            }
+            used
+        })
+        .copied()
+        .collect())
+}
+//The numeric type has to match and 64 bit inputs need 64 bit formats and the other way around. Missing
+//components are filled with (0, 0, 1) except for 64 bit formats, which need every component the shader reads
+
+pub(crate) fn check_stage_interface(
+    producer: &ShaderReflection,
+    consumer: &ShaderReflection,
+) -> LyeResult<()> {
+    for input in &consumer.inputs {
+        let Some(output) = producer
+            .outputs
+            .iter()
+            .find(|output| output.location == input.location)
+        else {
+            return Err(LyeError::ShaderInterface(format!(
+                "{} input {} at location {} isnt written by the {}",
+                stage_name(consumer.stage),
+                input.name,
+                input.location,
+                stage_name(producer.stage)
+            )));
+        };
+        if output.format.numeric != input.format.numeric
+            || output.format.width != input.format.width
+            || output.format.components < input.format.components
+        {
+            return Err(LyeError::ShaderInterface(format!(
+                "{} input {} at location {} is {}, but the {} writes {} ({})",
+                stage_name(consumer.stage),
+                input.name,
+                input.location,
+                input.format,
+                stage_name(producer.stage),
+                output.format,
+                output.name
+            )));
+        }
+    }
+
+    Ok(())
+}
+//Unread outputs are fine, an output may also have more components than the input reads
+
+pub(crate) struct PipelineInterface {
+    pub(crate) sets: Vec<Vec<vk::DescriptorSetLayoutBinding<'static>>>,
+    pub(crate) push_constant_range: Option<vk::PushConstantRange>,
+    pub(crate) uniform_blocks: Vec<DescriptorBinding>,
+}
+
+impl PipelineInterface {
+    pub(crate) fn new(stages: &[&ShaderReflection]) -> LyeResult<Self> {
+        let mut sets: Vec<Vec<vk::DescriptorSetLayoutBinding>> = Vec::new();
+        let mut names: HashMap<(u32, u32), &str> = HashMap::new();
+        let mut push_constant_range: Option<vk::PushConstantRange> = None;
+        let mut uniform_blocks: Vec<DescriptorBinding> = Vec::new();
+
+        for stage in stages {
+            for binding in &stage.bindings {
+                if binding.descriptor_type != vk::DescriptorType::UNIFORM_BUFFER {
+                    return Err(LyeError::ShaderInterface(format!(
+                        "{} at set {} binding {} in the {} is {:?}, the renderer can only fill uniform buffers",
+                        binding.name,
+                        binding.set,
+                        binding.binding,
+                        stage_name(stage.stage),
+                        binding.descriptor_type
+                    )));
+                }
+                if binding.count != 1 {
+                    return Err(LyeError::ShaderInterface(format!(
+                        "{} at set {} binding {} in the {} is an array of {} uniform blocks, only single blocks are supported",
+                        binding.name,
+                        binding.set,
+                        binding.binding,
+                        stage_name(stage.stage),
+                        binding.count
+                    )));
+                }
+                //Anything else would be bound without ever being written
+                if sets.len() <= binding.set as usize {
+                    sets.resize_with(binding.set as usize + 1, Vec::new);
+                }
+                let set = &mut sets[binding.set as usize];
+                match set
+                    .iter_mut()
+                    .find(|existing| existing.binding == binding.binding)
+                {
+                    Some(existing)
+                        if existing.descriptor_type == binding.descriptor_type
+                            && existing.descriptor_count == binding.count =>
+                    {
+                        existing.stage_flags |= stage.stage;
+                        if let Some(block) = uniform_blocks.iter().find(|block| {
+                            (block.set, block.binding) == (binding.set, binding.binding)
+                        }) {
+                            if block.block != binding.block {
+                                return Err(LyeError::ShaderInterface(format!(
+                                    "Uniform block {} at set {} binding {} is laid out differently in the {}",
+                                    binding.name,
+                                    binding.set,
+                                    binding.binding,
+                                    stage_name(stage.stage)
+                                )));
+                            }
+                        }
+                    }
+                    Some(existing) => {
+                        return Err(LyeError::ShaderInterface(format!(
+                        "Set {} binding {} is {} {:?}[{}] in one stage, but {} {:?}[{}] in the {}",
+                        binding.set,
+                        binding.binding,
+                        names[&(binding.set, binding.binding)],
+                        existing.descriptor_type,
+                        existing.descriptor_count,
+                        binding.name,
+                        binding.descriptor_type,
+                        binding.count,
+                        stage_name(stage.stage)
+                    )))
+                    }
+                    None => {
+                        names.insert((binding.set, binding.binding), &binding.name);
+                        if binding.descriptor_type == vk::DescriptorType::UNIFORM_BUFFER {
+                            uniform_blocks.push(binding.clone());
+                        }
+                        set.push(vk::DescriptorSetLayoutBinding {
+                            binding: binding.binding,
+                            descriptor_type: binding.descriptor_type,
+                            descriptor_count: binding.count,
+                            stage_flags: stage.stage,
+                            p_immutable_samplers: ptr::null(),
+                            _marker: std::marker::PhantomData,
+                        });
+                    }
+                }
+            }
+
+            if let Some(size) = stage.push_constant_size {
+                let range = push_constant_range.get_or_insert(vk::PushConstantRange {
+                    stage_flags: vk::ShaderStageFlags::empty(),
+                    offset: 0,
+                    size: 0,
+                });
+                range.stage_flags |= stage.stage;
+                range.size = range.size.max(size);
+            }
+        }
+        for set in &mut sets {
+            set.sort_by_key(|binding| binding.binding);
+        }
+
+        Ok(PipelineInterface {
+            sets,
+            push_constant_range,
+            uniform_blocks,
+        })
+    }
+}
+//One push constant range shared by every stage that declares a block, unused set numbers get empty layouts,
+//a uniform block shared between stages has to have the same layout in all of them
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::{ColorVertex, Vertex};
+
+    fn reflect(name: &'static str, bytes: &[u8]) -> ShaderReflection {
+        let words = ash::util::read_spv(&mut std::io::Cursor::new(bytes)).unwrap();
+        ShaderReflection::new(name, &words).unwrap()
+    }
+
+    fn vertex() -> ShaderReflection {
+        reflect(
+            "vertex.spv",
+            include_bytes!(concat!(env!("OUT_DIR"), "/vertex.spv")),
+        )
+    }
+
+    fn fragment() -> ShaderReflection {
+        reflect(
+            "fragment.spv",
+            include_bytes!(concat!(env!("OUT_DIR"), "/fragment.spv")),
+        )
+    }
+
+    fn float(components: u32) -> InterfaceFormat {
+        InterfaceFormat {
+            numeric: NumericType::Float,
+            components,
+            width: 32,
+        }
+    }
+
+    fn locations(variables: &[InterfaceVariable]) -> Vec<(&str, u32, InterfaceFormat)> {
+        variables
+            .iter()
+            .map(|variable| (variable.name.as_str(), variable.location, variable.format))
+            .collect()
+    }
+
+    #[test]
+    fn reflects_the_default_shaders() {
+        let vertex = vertex();
+        assert_eq!(vertex.stage, vk::ShaderStageFlags::VERTEX);
+        assert_eq!(
+            locations(&vertex.inputs),
+            [("positions", 0, float(3)), ("colors", 1, float(3))]
+        );
+        assert_eq!(locations(&vertex.outputs), [("color", 0, float(3))]);
+        assert_eq!(vertex.push_constant_size, Some(64));
+        assert!(vertex.bindings.is_empty());
+
+        let fragment = fragment();
+        assert_eq!(fragment.stage, vk::ShaderStageFlags::FRAGMENT);
+        assert_eq!(locations(&fragment.inputs), [("color", 0, float(3))]);
+        assert_eq!(locations(&fragment.outputs), [("outColor", 0, float(4))]);
+        assert_eq!(fragment.push_constant_size, None);
+        assert!(fragment.bindings.is_empty());
+
+        let interface = PipelineInterface::new(&[&vertex, &fragment]).unwrap();
+        assert!(interface.sets.is_empty());
+        let range = interface.push_constant_range.unwrap();
+        assert_eq!(
+            (range.stage_flags, range.offset, range.size),
+            (vk::ShaderStageFlags::VERTEX, 0, 64)
+        );
+    }
+
+    #[test]
+    fn stage_interface_mismatches_are_errors() {
+        let vertex = vertex();
+        let mut fragment = fragment();
+        check_stage_interface(&vertex, &fragment).unwrap();
+
+        fragment.inputs[0].format = float(4);
+        let err = check_stage_interface(&vertex, &fragment).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("is vec4, but the vertex shader writes vec3"));
+
+        fragment.inputs[0].format = float(2);
+        check_stage_interface(&vertex, &fragment).unwrap();
+
+        fragment.inputs[0].format.numeric = NumericType::Sint;
+        assert!(check_stage_interface(&vertex, &fragment).is_err());
+
+        fragment.inputs[0].format = float(3);
+        fragment.inputs[0].location = 1;
+        let err = check_stage_interface(&vertex, &fragment).unwrap_err();
+        assert!(err.to_string().contains("at location 1 isnt written"));
+    }
+
+    #[test]
+    fn vertex_input_mismatches_are_errors() {
+        let vertex = vertex();
+        let bindings = ColorVertex::bindings();
+        let attributes = ColorVertex::attributes();
+        let used = check_vertex_input(&vertex, &bindings, &attributes).unwrap();
+        assert_eq!(used.len(), 2);
+
+        let with_format = |format: vk::Format| {
+            let mut attributes = attributes.clone();
+            attributes[1].format = format;
+            check_vertex_input(&vertex, &bindings, &attributes)
+        };
+        with_format(vk::Format::R8G8B8A8_UNORM).unwrap();
+        with_format(vk::Format::R16G16B16_SFLOAT).unwrap();
+        with_format(vk::Format::R32G32_SFLOAT).unwrap();
+        let err = with_format(vk::Format::R32G32B32_SINT).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("expected a format like R32G32B32_SFLOAT"));
+        assert!(with_format(vk::Format::R64G64B64_SFLOAT).is_err());
+        assert!(with_format(vk::Format::D32_SFLOAT).is_err());
+
+        let mut double = vertex.clone();
+        double.inputs[1].format.width = 64;
+        let with_double_format = |format: vk::Format| {
+            let mut attributes = attributes.clone();
+            attributes[1].format = format;
+            check_vertex_input(&double, &bindings, &attributes)
+        };
+        with_double_format(vk::Format::R64G64B64_SFLOAT).unwrap();
+        assert!(with_double_format(vk::Format::R64G64_SFLOAT).is_err());
+        assert!(with_double_format(vk::Format::R32G32B32_SFLOAT).is_err());
+        //64 bit inputs arent filled in, the format has to cover every component
+
+        let err = check_vertex_input(&vertex, &bindings, &attributes[..1]).unwrap_err();
+        assert!(err.to_string().contains("colors at location 1"));
+
+        let mut unbound = attributes.clone();
+        unbound[1].binding = 1;
+        assert!(check_vertex_input(&vertex, &bindings, &unbound).is_err());
+
+        let mut unused = attributes.clone();
+        unused.push(vk::VertexInputAttributeDescription {
+            location: 2,
+            binding: 0,
+            format: vk::Format::R32_SFLOAT,
+            offset: 0,
+        });
+        let used = check_vertex_input(&vertex, &bindings, &unused).unwrap();
+        assert!(used.iter().map(|attribute| attribute.location).eq([0, 1]));
+    }
+
+    #[test]
+    fn descriptors_the_renderer_cant_fill_are_errors() {
+        let fragment = fragment();
+        let with_binding = |descriptor_type: vk::DescriptorType, count: u32| {
+            let mut vertex = vertex();
+            vertex.bindings.push(DescriptorBinding {
+                name: "material".to_owned(),
+                set: 0,
+                binding: 1,
+                descriptor_type,
+                count,
+                block: None,
+            });
+            PipelineInterface::new(&[&vertex, &fragment]).map(|interface| interface.sets)
+        };
+
+        let sets = with_binding(vk::DescriptorType::UNIFORM_BUFFER, 1).unwrap();
+        assert_eq!(sets.len(), 1);
+        assert_eq!(sets[0][0].stage_flags, vk::ShaderStageFlags::VERTEX);
+
+        let err = with_binding(vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1).unwrap_err();
+        assert!(err.to_string().contains("can only fill uniform buffers"));
+        assert!(with_binding(vk::DescriptorType::STORAGE_BUFFER, 1).is_err());
+        let err = with_binding(vk::DescriptorType::UNIFORM_BUFFER, 4).unwrap_err();
+        assert!(err.to_string().contains("array of 4 uniform blocks"));
+    }
+}
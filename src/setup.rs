@@ -1,145 +1,520 @@
+use crate::{
+    builder::RendererBuilder,
+    camera::{Camera, CameraPushConstants},
+    debug::DebugUtils,
+    depth::{DepthBuffer, DepthTest},
+    descriptors::DescriptorLayoutCache,
+    device::{DeviceRequirements, DeviceSelection},
+    dynamic_rendering::{Attachments, DynamicRendering, DynamicRenderingSupport, FeatureChain},
+    error::{Context, LyeError, LyeResult},
+    frame::{DrawContext, FrameContext},
+    headless::OffscreenTarget,
+    memory::{Allocator, HeapUsage},
+    mesh::{ColorVertex, Mesh, MeshId, Vertex},
+    pipeline::{GraphicsPipeline, GraphicsPipelineBuilder, PipelineTarget},
+    pipeline_cache::PipelineCache,
+    present::PresentPreference,
+    shaders::PipelineShaders,
+    uniform::{UniformBlock, UniformBuffer, UniformSlot},
+    upload::Uploader,
+    validation::DebugMessenger,
+};
 use ash::{self, prelude::VkResult, vk};
 use std::{marker::PhantomData, ptr};
-use winit::{self, raw_window_handle::HasWindowHandle};
-
-unsafe extern "system" fn vulkan_debug_callback(
-    flag: vk::DebugUtilsMessageSeverityFlagsEXT,
-    typ: vk::DebugUtilsMessageTypeFlagsEXT,
-    p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-    _: *mut std::os::raw::c_void,
-) -> vk::Bool32 {
-    use vk::DebugUtilsMessageSeverityFlagsEXT as Flag;
-
-    let message = std::ffi::CStr::from_ptr((*p_callback_data).p_message);
-    match flag {
-        Flag::VERBOSE => println!("{:?} - {:?}", typ, message),
-        Flag::INFO => println!("{:?} - {:?}", typ, message),
-        Flag::WARNING => println!("{:?} - {:?}", typ, message),
-        _ => println!("{:?} - {:?}", typ, message),
-    }
-    vk::FALSE
-}
-
-#[derive(Default)]
-pub struct App {
-    renderer: Option<Renderer>,
-}
-
-struct Renderer {
-    window: winit::window::Window,
+use winit::{
+    self,
+    raw_window_handle::{HasDisplayHandle, HasWindowHandle, RawDisplayHandle, RawWindowHandle},
+};
+
+/// A Vulkan renderer drawing into a window or an offscreen image.
+///
+/// Created through [`RendererBuilder`], or [`Renderer::new_headless`] for tests. Meshes added with
+/// [`Renderer::add_mesh`] are drawn every frame with the camera set by [`Renderer::set_camera`].
+pub struct Renderer {
+    target: RenderTarget,
     instance: ash::Instance,
-    debug_utils: ash::ext::debug_utils::Instance,
-    debug_utils_messenger: vk::DebugUtilsMessengerEXT,
-    surface_loader: ash::khr::surface::Instance,
-    surface: vk::SurfaceKHR,
+    debug_messenger: Option<DebugMessenger>,
     physical_device: vk::PhysicalDevice,
     queue_family_index: usize,
     device: ash::Device,
+    debug_utils: DebugUtils,
+    features: vk::PhysicalDeviceFeatures,
+    device_extensions: Vec<&'static std::ffi::CStr>,
     present_graphics_queue: vk::Queue,
-    swapchain_loader: ash::khr::swapchain::Device,
-    swapchain: vk::SwapchainKHR,
+    allocator: Allocator,
+    uploader: Uploader,
     format: vk::Format,
     extent: vk::Extent2D,
     images: Vec<vk::Image>,
     image_views: Vec<vk::ImageView>,
+    depth_format: vk::Format,
+    depth_buffers: Vec<DepthBuffer>,
+    dynamic_rendering: Option<DynamicRendering>,
     render_pass: vk::RenderPass,
     framebuffers: Vec<vk::Framebuffer>,
-    pipeline_layout: vk::PipelineLayout,
-    pipeline: vk::Pipeline,
-    command_pool: vk::CommandPool,
-    command_buffers: Vec<vk::CommandBuffer>,
-    image_available: Vec<vk::Semaphore>,
+    pipeline_builder: GraphicsPipelineBuilder,
+    pipeline_cache: PipelineCache,
+    layout_cache: DescriptorLayoutCache,
+    pipeline: GraphicsPipeline,
+    shaders: PipelineShaders,
+    #[cfg(feature = "hot-reload")]
+    shader_watcher: Option<crate::hot_reload::ShaderWatcher>,
+    frames: Vec<FrameContext>,
+    current_frame: usize,
     rendering_finished: Vec<vk::Semaphore>,
-    can_draw: Vec<vk::Fence>,
-    current_image: usize,
+    images_in_flight: Vec<vk::Fence>,
+    meshes: Vec<Mesh>,
+    uniforms: Vec<UniformSlot>,
+    camera: CameraPushConstants,
+    clear_color: [f32; 4],
+}
+
+enum RenderTarget {
+    Window(Box<WindowTarget>),
+    Offscreen(OffscreenTarget),
+}
+
+struct WindowTarget {
+    window: winit::window::Window,
+    surface_loader: ash::khr::surface::Instance,
+    surface: vk::SurfaceKHR,
+    swapchain_loader: ash::khr::swapchain::Device,
+    swapchain: vk::SwapchainKHR,
+    swapchain_outdated: bool,
+    swapchain_retired: bool,
+    options: SwapchainOptions,
+    present_mode: vk::PresentModeKHR,
+}
+
+#[derive(Clone, Copy)]
+struct SwapchainOptions {
+    min_image_count: u32,
+    present_preference: PresentPreference,
+}
+//Kept by the window target so recreated swapchains match the original request
+
+enum TargetInfo {
+    Window(winit::window::Window),
+    Offscreen {
+        extent: vk::Extent2D,
+        format: vk::Format,
+    },
 }
+//What Renderer::create should render into
 
 impl Renderer {
-    fn new(
-        event_loop: &winit::event_loop::ActiveEventLoop,
-    ) -> Result<Self, Box<dyn std::error::Error>> {
-        let window = event_loop.create_window(
-            winit::window::WindowAttributes::default()
-                .with_title("Lye")
-                .with_maximized(true)
-                .with_resizable(false),
-        )?;
+    /// Starts configuring a renderer, see [`RendererBuilder`].
+    pub fn builder() -> RendererBuilder {
+        RendererBuilder::new()
+    }
+
+    /// Headless renderer with default options, shorthand for [`RendererBuilder::build_headless`].
+    pub fn new_headless(extent: vk::Extent2D, format: vk::Format) -> LyeResult<Self> {
+        RendererBuilder::new().build_headless(extent, format)
+    }
+
+    pub(crate) fn create_windowed(
+        window: winit::window::Window,
+        builder: &RendererBuilder,
+    ) -> LyeResult<Self> {
+        Renderer::create(TargetInfo::Window(window), builder)
+    }
+
+    pub(crate) fn create_headless(
+        extent: vk::Extent2D,
+        format: vk::Format,
+        builder: &RendererBuilder,
+    ) -> LyeResult<Self> {
+        Renderer::create(TargetInfo::Offscreen { extent, format }, builder)
+    }
+
+    fn create(target_info: TargetInfo, builder: &RendererBuilder) -> LyeResult<Self> {
+        if builder.frames_in_flight == 0 {
+            return Err(LyeError::InvalidConfig(
+                "At least one frame has to be in flight".to_owned(),
+            ));
+        }
+        if builder.min_image_count == 0 {
+            return Err(LyeError::InvalidConfig(
+                "The swapchain needs at least one image".to_owned(),
+            ));
+        }
+        let device_selection = match &builder.device_selection {
+            Some(device_selection) => device_selection.clone(),
+            None => DeviceSelection::from_env()?,
+        };
+        //LYE_DEVICE is ignored when a device is passed explicitly
 
         let entry = unsafe { ash::Entry::load() }?;
-        let instance = Renderer::create_instance(&entry)?;
-        let (debug_utils, debug_utils_messenger) = Renderer::debug_utils(&entry, &instance)?;
-        let surface_loader = ash::khr::surface::Instance::new(&entry, &instance);
-        let surface = Renderer::create_surface(&window, &entry, &instance)?;
-        let (physical_device, queue_family_index) =
-            Renderer::get_physical_device_and_queue_family(&instance, &surface_loader, surface)?;
-        let (device, queue) =
-            Renderer::create_device_and_queues(queue_family_index, &instance, physical_device)?;
-        let (swapchain, swapchain_loader, format, extent) = Renderer::create_swapchain(
-            &surface_loader,
-            physical_device,
-            surface,
-            &instance,
-            &device,
-            queue_family_index,
-        )?;
-        let (images, image_views) = Renderer::acquire_swapchain_images_and_image_views(
-            &swapchain_loader,
-            swapchain,
-            format,
-            &device,
-        )?;
-        let render_pass = Renderer::create_renderpass(format, &device)?;
-        let framebuffers =
-            Renderer::create_framebuffers(&device, render_pass, &image_views, extent)?;
-        let (pipeline, pipeline_layout) = Renderer::create_pipeline(&device, extent, render_pass)?;
-        let (command_pool, command_buffers) =
-            Renderer::create_command_buffers(queue_family_index, &device, images.len())?;
-        let (image_available, rendering_finished, can_draw) =
-            Renderer::create_semaphores_and_fences(images.len(), &device)?;
-        Renderer::record_command_buffer(
-            &command_buffers,
-            &device,
-            render_pass,
-            &framebuffers,
-            extent,
-            pipeline,
-        )?;
+        let mut instance_extensions = match &target_info {
+            TargetInfo::Window(window) => {
+                Renderer::surface_extensions(window.display_handle()?.as_raw())?.to_vec()
+            }
+            TargetInfo::Offscreen { .. } => Vec::new(),
+        };
+        instance_extensions.extend(&builder.instance_extensions);
+        let available_extensions = unsafe { entry.enumerate_instance_extension_properties(None) }
+            .map_err(LyeError::Instance)?;
+        let is_available = |wanted: &std::ffi::CStr| {
+            available_extensions
+                .iter()
+                .any(|extension| extension.extension_name_as_c_str() == Ok(wanted))
+        };
+        if let Some(missing) = instance_extensions
+            .iter()
+            .find(|&&extension| !is_available(extension))
+        {
+            return Err(LyeError::InvalidConfig(format!(
+                "Instance extension {:?} is not supported",
+                missing
+            )));
+        }
+        let debug_messenger = builder.debug_messenger && is_available(ash::ext::debug_utils::NAME);
+        if builder.debug_messenger && !debug_messenger {
+            log::warn!("VK_EXT_debug_utils is unavailable, running without a debug messenger");
+        }
+        if debug_messenger {
+            instance_extensions.push(ash::ext::debug_utils::NAME);
+        }
+        let mut layers = Vec::new();
+        if builder.validation_layer {
+            if crate::validation::validation_layer_available(&entry) {
+                layers.push(crate::validation::VALIDATION_LAYER);
+            } else {
+                log::warn!(
+                    "{:?} is not installed, running without validation",
+                    crate::validation::VALIDATION_LAYER
+                );
+            }
+        }
+
+        let mut partial = PartialRenderer::default();
+        let instance = &*partial.instance.insert(Renderer::create_instance(
+            &entry,
+            &instance_extensions,
+            &layers,
+            builder,
+        )?);
+        if debug_messenger {
+            partial.debug_messenger = Some(
+                DebugMessenger::new(
+                    &entry,
+                    instance,
+                    builder.min_severity,
+                    builder.strict_validation,
+                )
+                .map_err(LyeError::Instance)?,
+            );
+        }
 
-        Ok(Self {
-            window,
+        if let TargetInfo::Window(window) = &target_info {
+            partial.surface = Some((
+                ash::khr::surface::Instance::new(&entry, instance),
+                Renderer::create_surface(window, &entry, instance)?,
+            ));
+        }
+        let mut required_extensions = match partial.surface {
+            Some(_) => vec![ash::khr::swapchain::NAME],
+            None => vec![],
+        };
+        required_extensions.extend(&builder.device_extensions);
+        let (physical_device, queue_family_index) = crate::device::select_physical_device(
             instance,
-            debug_utils,
-            debug_utils_messenger,
-            surface_loader,
-            surface,
+            &DeviceRequirements {
+                extensions: &required_extensions,
+                features: builder.features,
+                surface: partial
+                    .surface
+                    .as_ref()
+                    .map(|(surface_loader, surface)| (surface_loader, *surface)),
+                extent: match &target_info {
+                    TargetInfo::Offscreen { extent, .. } => Some(*extent),
+                    TargetInfo::Window(_) => None,
+                },
+            },
+            &device_selection,
+        )?;
+        let features = crate::device::enable_optional_features(
+            instance,
+            physical_device,
+            &builder.features,
+            &builder.optional_features,
+        );
+        let mut device_extensions = required_extensions;
+        device_extensions.extend(crate::device::enable_optional_extensions(
+            instance,
+            physical_device,
+            &builder.optional_device_extensions,
+        ));
+        let dynamic_rendering_support = builder
+            .dynamic_rendering
+            .then(|| DynamicRenderingSupport::query(&entry, instance, physical_device))
+            .flatten();
+        if let Some(support) = dynamic_rendering_support {
+            for &extension in support.extensions() {
+                if !device_extensions.contains(&extension) {
+                    device_extensions.push(extension);
+                }
+            }
+        }
+        match dynamic_rendering_support {
+            Some(support) => log::info!("Rendering : dynamic rendering ({:?})", support),
+            None => log::info!("Rendering : render pass"),
+        }
+        let device_extension_names: Vec<_> = device_extensions
+            .iter()
+            .map(|extension| extension.as_ptr())
+            .collect();
+        let transfer_queue_family_index =
+            Renderer::get_transfer_queue_family(instance, physical_device);
+        let mut feature_chain = FeatureChain::new();
+        let (device, queue, transfer_queue) = Renderer::create_device_and_queues(
             queue_family_index,
+            transfer_queue_family_index,
+            instance,
             physical_device,
+            &device_extension_names,
+            &features,
+            feature_chain.p_next(dynamic_rendering_support),
+        )
+        .map_err(LyeError::Device)?;
+        let device = &*partial.device.insert(device);
+        let dynamic_rendering = dynamic_rendering_support
+            .map(|support| DynamicRendering::new(instance, device, support));
+        let debug_utils = DebugUtils::new(instance, device, partial.debug_messenger.is_some());
+        let allocator = partial
+            .allocator
+            .insert(Allocator::new(instance, physical_device));
+        partial.uploader = Some(
+            Uploader::new(
+                device,
+                allocator,
+                (queue_family_index as u32, queue),
+                transfer_queue_family_index
+                    .map(|index| index as u32)
+                    .zip(transfer_queue),
+            )
+            .context("Creating the uploader")?,
+        );
+
+        let (format, extent) = match target_info {
+            TargetInfo::Window(window) => {
+                let (surface_loader, surface) = partial
+                    .surface
+                    .take()
+                    .expect("Windows always get a surface");
+                let swapchain_loader = ash::khr::swapchain::Device::new(instance, device);
+                let options = SwapchainOptions {
+                    min_image_count: builder.min_image_count,
+                    present_preference: builder.present_preference,
+                };
+                let RenderTarget::Window(target) =
+                    partial
+                        .target
+                        .insert(RenderTarget::Window(Box::new(WindowTarget {
+                            window,
+                            surface_loader,
+                            surface,
+                            swapchain_loader,
+                            swapchain: vk::SwapchainKHR::null(),
+                            swapchain_outdated: false,
+                            swapchain_retired: false,
+                            options,
+                            present_mode: vk::PresentModeKHR::FIFO,
+                        })))
+                else {
+                    unreachable!()
+                };
+                let (swapchain, format, extent, present_mode) = Renderer::create_swapchain(
+                    (&target.surface_loader, target.surface),
+                    physical_device,
+                    &target.swapchain_loader,
+                    queue_family_index,
+                    target.window.inner_size(),
+                    vk::SwapchainKHR::null(),
+                    &target.options,
+                )
+                .map_err(LyeError::Swapchain)?;
+                target.swapchain = swapchain;
+                target.present_mode = present_mode;
+                //The target owns the surface before the swapchain exists, so a failure from here on still destroys it
+
+                (partial.images, partial.image_views) =
+                    Renderer::acquire_swapchain_images_and_image_views(
+                        &target.swapchain_loader,
+                        swapchain,
+                        format,
+                        device,
+                    )
+                    .map_err(LyeError::Swapchain)?;
+                log::info!("Swapchain : {:?}, {:?}", format, present_mode);
+                (format, extent)
+            }
+            TargetInfo::Offscreen { extent, format } => {
+                let (offscreen, image) = OffscreenTarget::new(
+                    instance,
+                    physical_device,
+                    device,
+                    allocator,
+                    format,
+                    extent,
+                )?;
+                partial.target = Some(RenderTarget::Offscreen(offscreen));
+                partial.images = vec![image];
+                partial.image_views = vec![Renderer::create_image_view(image, format, device)
+                    .context("Creating the offscreen image view")?];
+                (format, extent)
+            }
+        };
+
+        let final_layout = match partial.target {
+            Some(RenderTarget::Window(_)) => vk::ImageLayout::PRESENT_SRC_KHR,
+            _ => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        };
+        let depth_format = DepthBuffer::find_format(instance, physical_device)
+            .context("Finding a depth format")?;
+        partial.depth_buffers = Renderer::create_depth_buffers(
+            device,
+            allocator,
+            depth_format,
+            extent,
+            partial.images.len(),
+        )
+        .context("Creating depth buffers")?;
+        if dynamic_rendering.is_none() {
+            partial.render_pass =
+                Renderer::create_renderpass(format, depth_format, final_layout, device)
+                    .context("Creating the render pass")?;
+            partial.framebuffers = Renderer::create_framebuffers(
+                device,
+                partial.render_pass,
+                &partial.image_views,
+                &partial.depth_buffers,
+                extent,
+            )
+            .context("Creating framebuffers")?;
+        }
+        //Dynamic rendering begins directly on the image views
+        let pipeline_cache = &*partial.pipeline_cache.insert(
+            PipelineCache::new(
+                &entry,
+                instance,
+                physical_device,
+                device,
+                builder
+                    .pipeline_cache
+                    .then(|| {
+                        builder.pipeline_cache_dir.clone().or_else(|| {
+                            crate::pipeline_cache::default_dir(&builder.application_name)
+                        })
+                    })
+                    .flatten()
+                    .as_deref(),
+            )
+            .context("Creating the pipeline cache")?,
+        );
+        let mut pipeline_builder = builder.pipeline.clone();
+        let shaders = pipeline_builder.shaders.take().unwrap_or_default();
+        //The renderer keeps the shaders itself, hot reloading replaces them
+        partial.pipeline = Some(pipeline_builder.build::<ColorVertex>(
             device,
+            pipeline_cache.handle(),
+            &mut partial.layout_cache,
+            Renderer::pipeline_target(
+                dynamic_rendering.is_some(),
+                partial.render_pass,
+                format,
+                depth_format,
+            ),
+            &shaders,
+        )?);
+        #[cfg(feature = "hot-reload")]
+        let shader_watcher = builder.shader_dir.as_ref().and_then(|shader_dir| {
+            crate::hot_reload::ShaderWatcher::new(shader_dir)
+                .inspect_err(|err| log::error!("Shader hot reloading is disabled : {}", err))
+                .ok()
+        });
+        partial.frames =
+            FrameContext::create_ring(device, queue_family_index, builder.frames_in_flight)
+                .context("Creating frames in flight")?;
+        let rendering_finished = Renderer::create_semaphores(partial.images.len(), device)
+            .context("Creating semaphores")?;
+        let images_in_flight = vec![vk::Fence::null(); partial.images.len()];
+
+        let renderer = Self {
+            target: partial.target.take().expect("The target was created"),
+            instance: partial.instance.take().expect("The instance was created"),
+            debug_messenger: partial.debug_messenger.take(),
+            queue_family_index,
+            physical_device,
+            device: partial.device.take().expect("The device was created"),
+            debug_utils,
+            features,
+            device_extensions,
             present_graphics_queue: queue,
-            swapchain_loader,
-            swapchain,
+            allocator: partial.allocator.take().expect("The allocator was created"),
+            uploader: partial.uploader.take().expect("The uploader was created"),
             format,
             extent,
-            images,
-            image_views,
-            render_pass,
-            framebuffers,
-            pipeline_layout,
-            pipeline,
-            command_pool,
-            command_buffers,
-            image_available,
+            images: std::mem::take(&mut partial.images),
+            image_views: std::mem::take(&mut partial.image_views),
+            depth_format,
+            depth_buffers: std::mem::take(&mut partial.depth_buffers),
+            dynamic_rendering,
+            render_pass: partial.render_pass,
+            framebuffers: std::mem::take(&mut partial.framebuffers),
+            pipeline_builder,
+            pipeline_cache: partial
+                .pipeline_cache
+                .take()
+                .expect("The pipeline cache was created"),
+            layout_cache: std::mem::take(&mut partial.layout_cache),
+            pipeline: partial.pipeline.take().expect("The pipeline was created"),
+            shaders,
+            #[cfg(feature = "hot-reload")]
+            shader_watcher,
+            frames: std::mem::take(&mut partial.frames),
+            current_frame: 0,
             rendering_finished,
-            can_draw,
-            current_image: 0,
-        })
+            images_in_flight,
+            meshes: Vec::new(),
+            uniforms: Vec::new(),
+            camera: CameraPushConstants {
+                view_projection: glam::Mat4::IDENTITY,
+            },
+            clear_color: builder.clear_color,
+        };
+        renderer.name_objects();
+
+        Ok(renderer)
+    }
+    //Everything is owned by partial until the end, so returning early on any error destroys what was created so far
+
+    fn surface_extensions(
+        display_handle: RawDisplayHandle,
+    ) -> LyeResult<[&'static std::ffi::CStr; 2]> {
+        let platform_extension = match display_handle {
+            RawDisplayHandle::Windows(_) => ash::khr::win32_surface::NAME,
+            RawDisplayHandle::Xlib(_) => ash::khr::xlib_surface::NAME,
+            RawDisplayHandle::Xcb(_) => ash::khr::xcb_surface::NAME,
+            RawDisplayHandle::Wayland(_) => ash::khr::wayland_surface::NAME,
+            unsupported => return Err(LyeError::UnsupportedDisplay(format!("{:?}", unsupported))),
+        };
+
+        Ok([ash::khr::surface::NAME, platform_extension])
     }
+    //Instance extensions needed to create a surface for the current window system
 
-    fn create_instance(entry: &ash::Entry) -> Result<ash::Instance, vk::Result> {
+    fn create_instance(
+        entry: &ash::Entry,
+        extensions: &[&std::ffi::CStr],
+        layers: &[&std::ffi::CStr],
+        builder: &RendererBuilder,
+    ) -> LyeResult<ash::Instance> {
         let (major, minor, patch) = match unsafe {
             entry
                 .try_enumerate_instance_version()
-                .unwrap_or_else(|err| panic!("Error : {}", err))
+                .map_err(LyeError::Instance)?
         } {
             Some(version) => (
                 vk::api_version_major(version),
@@ -149,259 +524,306 @@ impl Renderer {
             None => (1, 0, 0),
         };
 
-        println!("Api ver : {}.{}.{}", major, minor, patch);
+        log::info!("Api ver : {}.{}.{}", major, minor, patch);
 
-        let app_name = unsafe { std::ffi::CStr::from_bytes_with_nul_unchecked(b"Lye\0") };
-        let engine_name =
-            unsafe { std::ffi::CStr::from_bytes_with_nul_unchecked(b"Fortnite-Engine\0") };
+        let to_c_string = |name: &str, what: &str| {
+            std::ffi::CString::new(name).map_err(|_| {
+                LyeError::InvalidConfig(format!("The {} name contains a NUL byte", what))
+            })
+        };
+        let application_name = to_c_string(&builder.application_name, "application")?;
+        let engine_name = to_c_string(&builder.engine_name, "engine")?;
+        let version = |(major, minor, patch)| vk::make_api_version(0, major, minor, patch);
         let app_info = vk::ApplicationInfo {
             s_type: vk::StructureType::APPLICATION_INFO,
             p_next: ptr::null(),
-            p_application_name: app_name.as_ptr(),
-            application_version: vk::make_api_version(0, 0, 1, 0),
+            p_application_name: application_name.as_ptr(),
+            application_version: version(builder.application_version),
             p_engine_name: engine_name.as_ptr(),
-            engine_version: vk::make_api_version(0, 0, 1, 0),
+            engine_version: version(builder.engine_version),
             api_version: vk::make_api_version(0, major, minor, 0),
             _marker: PhantomData,
         };
 
-        let extensions = [
-            ash::khr::surface::NAME.as_ptr(),
-            ash::khr::win32_surface::NAME.as_ptr(),
-            ash::ext::debug_utils::NAME.as_ptr(),
-        ];
-
-        #[cfg(target_os = "macos")]
-        {
-            panic!() //LOL
-        }
+        let extensions: Vec<*const std::os::raw::c_char> = extensions
+            .iter()
+            .map(|extension| extension.as_ptr())
+            .collect();
+        let layers: Vec<*const std::os::raw::c_char> =
+            layers.iter().map(|layer| layer.as_ptr()).collect();
 
         let create_info = vk::InstanceCreateInfo {
             s_type: vk::StructureType::INSTANCE_CREATE_INFO,
             p_next: ptr::null(),
             flags: vk::InstanceCreateFlags::empty(),
             p_application_info: &app_info,
-            enabled_layer_count: 0,
-            pp_enabled_layer_names: ptr::null(),
+            enabled_layer_count: layers.len() as u32,
+            pp_enabled_layer_names: layers.as_ptr(),
             enabled_extension_count: extensions.len() as u32,
             pp_enabled_extension_names: extensions.as_ptr(),
             _marker: PhantomData,
         };
 
-        unsafe { entry.create_instance(&create_info, None) }
-    }
-
-    fn debug_utils(
-        entry: &ash::Entry,
-        instance: &ash::Instance,
-    ) -> VkResult<(ash::ext::debug_utils::Instance, vk::DebugUtilsMessengerEXT)> {
-        let message_severity = vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE
-            | vk::DebugUtilsMessageSeverityFlagsEXT::INFO
-            | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
-            | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR;
-        let message_type = vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
-            | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
-            | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION;
-
-        let create_info = vk::DebugUtilsMessengerCreateInfoEXT::default()
-            .message_severity(message_severity)
-            .message_type(message_type)
-            .pfn_user_callback(Some(vulkan_debug_callback));
-
-        let debug_utils = ash::ext::debug_utils::Instance::new(entry, instance);
-        let debug_utils_messenger =
-            unsafe { debug_utils.create_debug_utils_messenger(&create_info, None)? };
-
-        Ok((debug_utils, debug_utils_messenger))
+        unsafe { entry.create_instance(&create_info, None) }.map_err(LyeError::Instance)
     }
 
     fn create_surface(
         window: &winit::window::Window,
         entry: &ash::Entry,
         instance: &ash::Instance,
-    ) -> VkResult<vk::SurfaceKHR> {
-        #[cfg(target_os = "windows")]
-        {
-            let (hwnd, hinstance) = match window.window_handle().unwrap().as_raw() {
-                winit::raw_window_handle::RawWindowHandle::Win32(handle) => {
-                    (handle.hwnd.get(), handle.hinstance.unwrap().get())
-                }
-                _ => panic!("I dont know, surface wrong i guess"),
-            };
-
-            let create_info = vk::Win32SurfaceCreateInfoKHR {
-                s_type: vk::StructureType::WIN32_SURFACE_CREATE_INFO_KHR,
-                p_next: ptr::null(),
-                flags: vk::Win32SurfaceCreateFlagsKHR::empty(),
-                hinstance,
-                hwnd,
-                _marker: PhantomData,
-            };
-
-            let win_surface_loader = ash::khr::win32_surface::Instance::new(entry, instance);
-            unsafe { win_surface_loader.create_win32_surface(&create_info, None) }
-        }
-
-        #[cfg(target_os = "linux")]
-        {
-            todo!()
-        }
+    ) -> LyeResult<vk::SurfaceKHR> {
+        let display_handle = window.display_handle()?.as_raw();
+        let window_handle = window.window_handle()?.as_raw();
+
+        let surface = match (display_handle, window_handle) {
+            (RawDisplayHandle::Windows(_), RawWindowHandle::Win32(handle)) => {
+                let create_info = vk::Win32SurfaceCreateInfoKHR {
+                    s_type: vk::StructureType::WIN32_SURFACE_CREATE_INFO_KHR,
+                    p_next: ptr::null(),
+                    flags: vk::Win32SurfaceCreateFlagsKHR::empty(),
+                    hinstance: handle
+                        .hinstance
+                        .ok_or_else(|| {
+                            LyeError::UnsupportedDisplay(
+                                "Win32 window handle has no hinstance".to_owned(),
+                            )
+                        })?
+                        .get(),
+                    hwnd: handle.hwnd.get(),
+                    _marker: PhantomData,
+                };
+
+                let win_surface_loader = ash::khr::win32_surface::Instance::new(entry, instance);
+                unsafe { win_surface_loader.create_win32_surface(&create_info, None) }
+                    .map_err(LyeError::Surface)?
+            }
+            (RawDisplayHandle::Xlib(display), RawWindowHandle::Xlib(handle)) => {
+                let create_info = vk::XlibSurfaceCreateInfoKHR {
+                    s_type: vk::StructureType::XLIB_SURFACE_CREATE_INFO_KHR,
+                    p_next: ptr::null(),
+                    flags: vk::XlibSurfaceCreateFlagsKHR::empty(),
+                    dpy: display
+                        .display
+                        .ok_or_else(|| {
+                            LyeError::UnsupportedDisplay(
+                                "Xlib display handle has no display connection".to_owned(),
+                            )
+                        })?
+                        .as_ptr(),
+                    window: handle.window,
+                    _marker: PhantomData,
+                };
+
+                let xlib_surface_loader = ash::khr::xlib_surface::Instance::new(entry, instance);
+                unsafe { xlib_surface_loader.create_xlib_surface(&create_info, None) }
+                    .map_err(LyeError::Surface)?
+            }
+            (RawDisplayHandle::Xcb(display), RawWindowHandle::Xcb(handle)) => {
+                let create_info = vk::XcbSurfaceCreateInfoKHR {
+                    s_type: vk::StructureType::XCB_SURFACE_CREATE_INFO_KHR,
+                    p_next: ptr::null(),
+                    flags: vk::XcbSurfaceCreateFlagsKHR::empty(),
+                    connection: display
+                        .connection
+                        .ok_or_else(|| {
+                            LyeError::UnsupportedDisplay(
+                                "XCB display handle has no connection".to_owned(),
+                            )
+                        })?
+                        .as_ptr(),
+                    window: handle.window.get(),
+                    _marker: PhantomData,
+                };
+
+                let xcb_surface_loader = ash::khr::xcb_surface::Instance::new(entry, instance);
+                unsafe { xcb_surface_loader.create_xcb_surface(&create_info, None) }
+                    .map_err(LyeError::Surface)?
+            }
+            (RawDisplayHandle::Wayland(display), RawWindowHandle::Wayland(handle)) => {
+                let create_info = vk::WaylandSurfaceCreateInfoKHR {
+                    s_type: vk::StructureType::WAYLAND_SURFACE_CREATE_INFO_KHR,
+                    p_next: ptr::null(),
+                    flags: vk::WaylandSurfaceCreateFlagsKHR::empty(),
+                    display: display.display.as_ptr(),
+                    surface: handle.surface.as_ptr(),
+                    _marker: PhantomData,
+                };
+
+                let wayland_surface_loader =
+                    ash::khr::wayland_surface::Instance::new(entry, instance);
+                unsafe { wayland_surface_loader.create_wayland_surface(&create_info, None) }
+                    .map_err(LyeError::Surface)?
+            }
+            (display, window) => {
+                return Err(LyeError::UnsupportedDisplay(format!(
+                    "display {:?}, window {:?}",
+                    display, window
+                )))
+            }
+        };
 
-        #[cfg(target_os = "macos")]
-        {
-            todo!()
-        }
+        Ok(surface)
     }
 
-    fn get_physical_device_and_queue_family(
+    fn get_transfer_queue_family(
         instance: &ash::Instance,
-        surface_loader: &ash::khr::surface::Instance,
-        surface: vk::SurfaceKHR,
-    ) -> Result<(vk::PhysicalDevice, usize), vk::Result> {
-        let physical_devices = unsafe { instance.enumerate_physical_devices() }?;
-
-        println!("{} Vulkan device/s", physical_devices.len());
+        physical_device: vk::PhysicalDevice,
+    ) -> Option<usize> {
+        let families =
+            unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+        let transfer_only = |properties: &vk::QueueFamilyProperties, allowed: vk::QueueFlags| {
+            properties.queue_flags.contains(vk::QueueFlags::TRANSFER)
+                && !properties
+                    .queue_flags
+                    .intersects((vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE) & !allowed)
+        };
 
-        let (physical_device, queue_family_index) = unsafe {
-            physical_devices.iter().find_map(|p_device| {
-                instance
-                    .get_physical_device_queue_family_properties(*p_device)
+        let transfer_queue_family = families
+            .iter()
+            .position(|properties| transfer_only(properties, vk::QueueFlags::empty()))
+            .or_else(|| {
+                families
                     .iter()
-                    .enumerate()
-                    .find_map(|(i, properties)| {
-                        let supp_surface_and_graphics = properties
-                            .queue_flags
-                            .contains(vk::QueueFlags::GRAPHICS)
-                            && surface_loader
-                                .get_physical_device_surface_support(*p_device, i as u32, surface)
-                                .unwrap();
-                        if supp_surface_and_graphics {
-                            let prop = instance.get_physical_device_properties(*p_device);
-                            println!(
-                                "Device Type {:?}, Device Name {:?}",
-                                prop.device_type,
-                                prop.device_name_as_c_str().unwrap()
-                            );
-                            Some((*p_device, i))
-                        } else {
-                            None
-                        }
-                    })
-            })
+                    .position(|properties| transfer_only(properties, vk::QueueFlags::COMPUTE))
+            });
+        //Pure transfer families are usually backed by DMA engines, async compute is the fallback
+
+        if let Some(index) = transfer_queue_family {
+            log::info!(
+                "Transfer Q family : {}, Q flags : {:?}",
+                index,
+                families[index].queue_flags
+            );
         }
-        .expect("Couldnt find suitable physical devices"); //Not sure if this selects the best pdevice, looks like it only selects the first one
-
-        let properties = unsafe {
-            instance.get_physical_device_queue_family_properties(physical_device)
-                [queue_family_index]
-        };
-
-        println!(
-            "Q count : {}, Q flags : {:?}",
-            properties.queue_count, properties.queue_flags
-        );
 
-        Ok((physical_device, queue_family_index))
+        transfer_queue_family
     }
 
     fn create_device_and_queues(
         queue_family_index: usize,
+        transfer_queue_family_index: Option<usize>,
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
-    ) -> VkResult<(ash::Device, vk::Queue)> {
-        let queue_create_info = vk::DeviceQueueCreateInfo {
-            s_type: vk::StructureType::DEVICE_QUEUE_CREATE_INFO,
-            p_next: ptr::null(),
-            flags: vk::DeviceQueueCreateFlags::empty(),
-            queue_family_index: queue_family_index as u32,
-            queue_count: 1,
-            p_queue_priorities: [1.0].as_ptr(),
-            _marker: PhantomData,
-        };
-
-        let device_extensions = [ash::khr::swapchain::NAME.as_ptr()];
+        device_extensions: &[*const std::os::raw::c_char],
+        features: &vk::PhysicalDeviceFeatures,
+        p_next: *const std::os::raw::c_void,
+    ) -> VkResult<(ash::Device, vk::Queue, Option<vk::Queue>)> {
+        let queue_create_infos: Vec<vk::DeviceQueueCreateInfo> = [Some(queue_family_index)]
+            .into_iter()
+            .chain([transfer_queue_family_index])
+            .flatten()
+            .map(|family_index| vk::DeviceQueueCreateInfo {
+                s_type: vk::StructureType::DEVICE_QUEUE_CREATE_INFO,
+                p_next: ptr::null(),
+                flags: vk::DeviceQueueCreateFlags::empty(),
+                queue_family_index: family_index as u32,
+                queue_count: 1,
+                p_queue_priorities: [1.0].as_ptr(),
+                _marker: PhantomData,
+            })
+            .collect();
 
         let device_create_info = vk::DeviceCreateInfo {
             s_type: vk::StructureType::DEVICE_CREATE_INFO,
-            p_next: ptr::null(),
+            p_next,
             flags: vk::DeviceCreateFlags::empty(),
-            queue_create_info_count: 1,
-            p_queue_create_infos: &queue_create_info,
+            queue_create_info_count: queue_create_infos.len() as u32,
+            p_queue_create_infos: queue_create_infos.as_ptr(),
             enabled_extension_count: device_extensions.len() as u32,
             pp_enabled_extension_names: device_extensions.as_ptr(),
-            p_enabled_features: ptr::null(),
+            p_enabled_features: features,
             _marker: PhantomData,
             ..Default::default()
         };
 
         let device = unsafe { instance.create_device(physical_device, &device_create_info, None) }?;
         let queue = unsafe { device.get_device_queue(queue_family_index as u32, 0) };
+        let transfer_queue = transfer_queue_family_index
+            .map(|family_index| unsafe { device.get_device_queue(family_index as u32, 0) });
 
-        Ok((device, queue))
+        Ok((device, queue, transfer_queue))
     }
 
     fn create_swapchain(
-        surface_loader: &ash::khr::surface::Instance,
+        (surface_loader, surface): (&ash::khr::surface::Instance, vk::SurfaceKHR),
         physical_device: vk::PhysicalDevice,
-        surface: vk::SurfaceKHR,
-        instance: &ash::Instance,
-        device: &ash::Device,
+        swapchain_loader: &ash::khr::swapchain::Device,
         queue_family_index: usize,
+        window_size: winit::dpi::PhysicalSize<u32>,
+        old_swapchain: vk::SwapchainKHR,
+        options: &SwapchainOptions,
     ) -> VkResult<(
         vk::SwapchainKHR,
-        ash::khr::swapchain::Device,
         vk::Format,
         vk::Extent2D,
+        vk::PresentModeKHR,
     )> {
-        let image_format = unsafe {
-            surface_loader.get_physical_device_surface_formats(physical_device, surface)?[0]
-        };
-
-        let image_resolution = unsafe {
-            match surface_loader
-                .get_physical_device_surface_capabilities(physical_device, surface)?
-                .current_extent
-                .width
-            {
-                u32::MAX => todo!(),
-                _ => {
-                    surface_loader
-                        .get_physical_device_surface_capabilities(physical_device, surface)?
-                        .current_extent
-                }
-            }
+        let image_format = crate::present::choose_surface_format(&unsafe {
+            surface_loader.get_physical_device_surface_formats(physical_device, surface)?
+        });
+
+        let capabilities = unsafe {
+            surface_loader.get_physical_device_surface_capabilities(physical_device, surface)?
+        };
+
+        let image_resolution = match capabilities.current_extent.width {
+            u32::MAX => vk::Extent2D {
+                width: window_size.width.clamp(
+                    capabilities.min_image_extent.width,
+                    capabilities.max_image_extent.width,
+                ),
+                height: window_size.height.clamp(
+                    capabilities.min_image_extent.height,
+                    capabilities.max_image_extent.height,
+                ),
+            },
+            _ => capabilities.current_extent,
         };
-        let queue_family_indeces = vec![queue_family_index as u32];
+        //u32::MAX means the surface size is decided by the swapchain, so the window size is used
+
+        let min_image_count = match capabilities.max_image_count {
+            0 => capabilities.min_image_count.max(options.min_image_count),
+            max => capabilities
+                .min_image_count
+                .max(options.min_image_count)
+                .min(max),
+        };
+
+        let present_modes = unsafe {
+            surface_loader.get_physical_device_surface_present_modes(physical_device, surface)?
+        };
+        let present_mode =
+            crate::present::choose_present_mode(options.present_preference, &present_modes);
+        let queue_family_indeces = [queue_family_index as u32];
 
         let swapchain_create_info = vk::SwapchainCreateInfoKHR {
             s_type: vk::StructureType::SWAPCHAIN_CREATE_INFO_KHR,
             p_next: ptr::null(),
             flags: vk::SwapchainCreateFlagsKHR::empty(),
             surface,
-            min_image_count: 3,
+            min_image_count,
             image_format: image_format.format,
             image_color_space: image_format.color_space,
             image_extent: image_resolution,
             image_array_layers: 1,
             image_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
             image_sharing_mode: vk::SharingMode::EXCLUSIVE,
-            pre_transform: vk::SurfaceTransformFlagsKHR::IDENTITY,
+            pre_transform: capabilities.current_transform,
             composite_alpha: vk::CompositeAlphaFlagsKHR::OPAQUE,
-            present_mode: vk::PresentModeKHR::FIFO,
+            present_mode,
             clipped: vk::FALSE,
             queue_family_index_count: 1,
             p_queue_family_indices: queue_family_indeces.as_ptr(),
-            old_swapchain: vk::SwapchainKHR::null(),
+            old_swapchain,
             _marker: PhantomData,
         };
-        let swapchain_loader = ash::khr::swapchain::Device::new(instance, device);
         let swapchain = unsafe { swapchain_loader.create_swapchain(&swapchain_create_info, None)? };
 
         Ok((
             swapchain,
-            swapchain_loader,
             image_format.format,
             image_resolution,
+            present_mode,
         ))
     }
 
@@ -413,52 +835,85 @@ impl Renderer {
     ) -> VkResult<(Vec<vk::Image>, Vec<vk::ImageView>)> {
         let images = unsafe { swapchain_loader.get_swapchain_images(swapchain)? };
         let mut image_views = Vec::with_capacity(images.len());
-
-        for image in images.iter() {
-            let img_view_create_info = vk::ImageViewCreateInfo {
-                s_type: vk::StructureType::IMAGE_VIEW_CREATE_INFO,
-                p_next: ptr::null(),
-                flags: vk::ImageViewCreateFlags::empty(),
-                image: *image,
-                view_type: vk::ImageViewType::TYPE_2D,
-                format: format,
-                components: vk::ComponentMapping {
-                    r: vk::ComponentSwizzle::IDENTITY,
-                    g: vk::ComponentSwizzle::IDENTITY,
-                    b: vk::ComponentSwizzle::IDENTITY,
-                    a: vk::ComponentSwizzle::IDENTITY,
-                },
-                subresource_range: vk::ImageSubresourceRange {
-                    aspect_mask: vk::ImageAspectFlags::COLOR,
-                    base_mip_level: 0,
-                    level_count: 1,
-                    base_array_layer: 0,
-                    layer_count: 1,
-                },
-                _marker: PhantomData,
-            };
-
-            image_views.push(unsafe { device.create_image_view(&img_view_create_info, None)? });
+        for &image in &images {
+            match Renderer::create_image_view(image, format, device) {
+                Ok(image_view) => image_views.push(image_view),
+                Err(err) => {
+                    image_views.iter().for_each(|&image_view| unsafe {
+                        device.destroy_image_view(image_view, None)
+                    });
+                    return Err(err);
+                }
+            }
         }
 
-        println!("Using {} images/image_views", images.len());
+        log::debug!("Using {} images/image_views", images.len());
 
         Ok((images, image_views))
     }
 
-    fn create_renderpass(format: vk::Format, device: &ash::Device) -> VkResult<vk::RenderPass> {
-        let attachment_description = vk::AttachmentDescription {
-            flags: vk::AttachmentDescriptionFlags::empty(),
+    fn create_image_view(
+        image: vk::Image,
+        format: vk::Format,
+        device: &ash::Device,
+    ) -> VkResult<vk::ImageView> {
+        let img_view_create_info = vk::ImageViewCreateInfo {
+            s_type: vk::StructureType::IMAGE_VIEW_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::ImageViewCreateFlags::empty(),
+            image,
+            view_type: vk::ImageViewType::TYPE_2D,
             format,
-            samples: vk::SampleCountFlags::TYPE_1,
-            load_op: vk::AttachmentLoadOp::CLEAR,
-            store_op: vk::AttachmentStoreOp::STORE,
-            initial_layout: vk::ImageLayout::UNDEFINED,
-            final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
-            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
-            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            components: vk::ComponentMapping {
+                r: vk::ComponentSwizzle::IDENTITY,
+                g: vk::ComponentSwizzle::IDENTITY,
+                b: vk::ComponentSwizzle::IDENTITY,
+                a: vk::ComponentSwizzle::IDENTITY,
+            },
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            _marker: PhantomData,
         };
-        //How attachments are handled before/after renderpass
+
+        unsafe { device.create_image_view(&img_view_create_info, None) }
+    }
+
+    fn create_renderpass(
+        format: vk::Format,
+        depth_format: vk::Format,
+        final_layout: vk::ImageLayout,
+        device: &ash::Device,
+    ) -> VkResult<vk::RenderPass> {
+        let attachment_descriptions = [
+            vk::AttachmentDescription {
+                flags: vk::AttachmentDescriptionFlags::empty(),
+                format,
+                samples: vk::SampleCountFlags::TYPE_1,
+                load_op: vk::AttachmentLoadOp::CLEAR,
+                store_op: vk::AttachmentStoreOp::STORE,
+                initial_layout: vk::ImageLayout::UNDEFINED,
+                final_layout,
+                stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+                stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            },
+            vk::AttachmentDescription {
+                flags: vk::AttachmentDescriptionFlags::empty(),
+                format: depth_format,
+                samples: vk::SampleCountFlags::TYPE_1,
+                load_op: vk::AttachmentLoadOp::CLEAR,
+                store_op: vk::AttachmentStoreOp::DONT_CARE,
+                initial_layout: vk::ImageLayout::UNDEFINED,
+                final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+                stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            },
+        ];
+        //How attachments are handled before/after renderpass, depth is only needed during it
 
         let color_attachment_ref = vk::AttachmentReference {
             attachment: 0,
@@ -466,6 +921,11 @@ impl Renderer {
         };
         //How attachments are used (layout) and which attachmentdescriptions are used for current subpass
 
+        let depth_attachment_ref = vk::AttachmentReference {
+            attachment: 1,
+            layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        };
+
         let subpass_description = vk::SubpassDescription {
             flags: vk::SubpassDescriptionFlags::empty(),
             pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
@@ -474,502 +934,1236 @@ impl Renderer {
             input_attachment_count: 0,
             p_input_attachments: ptr::null(),
             p_resolve_attachments: ptr::null(),
-            p_depth_stencil_attachment: ptr::null(),
+            p_depth_stencil_attachment: &depth_attachment_ref,
             preserve_attachment_count: 0,
             p_preserve_attachments: ptr::null(),
             _marker: PhantomData,
         };
         //Does Render-operations based on attachments
 
-        let subpass_dependency = vk::SubpassDependency {
+        let mut subpass_dependencies = vec![vk::SubpassDependency {
             src_subpass: vk::SUBPASS_EXTERNAL,
             dst_subpass: 0,
-            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-            src_access_mask: vk::AccessFlags::empty(),
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+            src_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
             dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ
-                | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
             dependency_flags: vk::DependencyFlags::empty(),
-        };
-        //Describes relation between subpasses
+        }];
+        //Describes relation between subpasses, the depth clear waits for the previous pass writing the same image
+
+        if final_layout == vk::ImageLayout::TRANSFER_SRC_OPTIMAL {
+            subpass_dependencies.push(vk::SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: vk::SUBPASS_EXTERNAL,
+                src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                dst_stage_mask: vk::PipelineStageFlags::TRANSFER,
+                src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dst_access_mask: vk::AccessFlags::TRANSFER_READ,
+                dependency_flags: vk::DependencyFlags::empty(),
+            });
+        }
+        //Offscreen images get copied out right after the renderpass
 
         let render_pass_create_info = vk::RenderPassCreateInfo {
             s_type: vk::StructureType::RENDER_PASS_CREATE_INFO,
             flags: vk::RenderPassCreateFlags::empty(),
             p_next: ptr::null(),
-            attachment_count: 1,
-            p_attachments: &attachment_description,
+            attachment_count: attachment_descriptions.len() as u32,
+            p_attachments: attachment_descriptions.as_ptr(),
             subpass_count: 1,
             p_subpasses: &subpass_description,
-            dependency_count: 1,
-            p_dependencies: &subpass_dependency,
+            dependency_count: subpass_dependencies.len() as u32,
+            p_dependencies: subpass_dependencies.as_ptr(),
             _marker: PhantomData,
         };
 
         unsafe { device.create_render_pass(&render_pass_create_info, None) }
     }
 
+    fn pipeline_target(
+        dynamic_rendering: bool,
+        render_pass: vk::RenderPass,
+        format: vk::Format,
+        depth_format: vk::Format,
+    ) -> PipelineTarget {
+        match dynamic_rendering {
+            true => PipelineTarget::Dynamic {
+                color_format: format,
+                depth_format,
+            },
+            false => PipelineTarget::RenderPass(render_pass),
+        }
+    }
+
+    fn current_pipeline_target(&self) -> PipelineTarget {
+        Renderer::pipeline_target(
+            self.dynamic_rendering.is_some(),
+            self.render_pass,
+            self.format,
+            self.depth_format,
+        )
+    }
+
     fn create_framebuffers(
         device: &ash::Device,
         render_pass: vk::RenderPass,
         image_views: &[vk::ImageView],
+        depth_buffers: &[DepthBuffer],
         extent: vk::Extent2D,
     ) -> VkResult<Vec<vk::Framebuffer>> {
         let mut framebuffers = Vec::with_capacity(image_views.len());
 
-        for image in image_views {
+        for (&image, depth_buffer) in image_views.iter().zip(depth_buffers) {
+            let attachments = [image, depth_buffer.view()];
             let framebuffer_create_info = vk::FramebufferCreateInfo {
                 s_type: vk::StructureType::FRAMEBUFFER_CREATE_INFO,
                 p_next: ptr::null(),
                 flags: vk::FramebufferCreateFlags::empty(),
                 render_pass,
-                p_attachments: image,
-                attachment_count: 1,
+                p_attachments: attachments.as_ptr(),
+                attachment_count: attachments.len() as u32,
                 width: extent.width,
                 height: extent.height,
                 layers: 1,
                 _marker: Default::default(),
             };
-            framebuffers
-                .push(unsafe { device.create_framebuffer(&framebuffer_create_info, None)? });
+            match unsafe { device.create_framebuffer(&framebuffer_create_info, None) } {
+                Ok(framebuffer) => framebuffers.push(framebuffer),
+                Err(err) => {
+                    framebuffers.iter().for_each(|&framebuffer| unsafe {
+                        device.destroy_framebuffer(framebuffer, None)
+                    });
+                    return Err(err);
+                }
+            }
         }
 
         Ok(framebuffers)
     }
 
-    fn create_shader_module(bytes: &[u8], device: &ash::Device) -> VkResult<vk::ShaderModule> {
-        let mut cursor = std::io::Cursor::new(bytes);
-        let shader_bytes = ash::util::read_spv(&mut cursor).unwrap();
-        let shader_create_info = vk::ShaderModuleCreateInfo::default().code(&shader_bytes);
-        unsafe { device.create_shader_module(&shader_create_info, None) }
-    }
-
-    fn create_pipeline(
+    fn create_depth_buffers(
         device: &ash::Device,
+        allocator: &mut Allocator,
+        format: vk::Format,
         extent: vk::Extent2D,
-        render_pass: vk::RenderPass,
-    ) -> VkResult<(vk::Pipeline, vk::PipelineLayout)> {
-        let vertex_shader_module =
-            Renderer::create_shader_module(&include_bytes!("../shaders/vertex.spv")[..], device)?;
-        let fragment_shader_module =
-            Renderer::create_shader_module(&include_bytes!("../shaders/fragment.spv")[..], device)?;
+        count: usize,
+    ) -> VkResult<Vec<DepthBuffer>> {
+        let mut depth_buffers = Vec::with_capacity(count);
+        for _ in 0..count {
+            match DepthBuffer::new(device, allocator, format, extent) {
+                Ok(depth_buffer) => depth_buffers.push(depth_buffer),
+                Err(err) => {
+                    depth_buffers
+                        .iter()
+                        .for_each(|depth_buffer| depth_buffer.destroy(device, allocator));
+                    return Err(err);
+                }
+            }
+        }
+        Ok(depth_buffers)
+    }
 
-        let entry = unsafe { std::ffi::CStr::from_bytes_with_nul_unchecked(b"main\0") }; //might be a problem
+    fn record_frame<F: FnOnce(&mut DrawContext)>(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        descriptor_sets: &[vk::DescriptorSet],
+        hook: F,
+    ) -> VkResult<()> {
+        let device = &self.device;
+        let begin_info = vk::CommandBufferBeginInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
+            p_next: ptr::null(),
+            flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+            p_inheritance_info: ptr::null(),
+            _marker: PhantomData,
+        };
 
-        let shader_states_create_infos = [
-            vk::PipelineShaderStageCreateInfo {
-                s_type: vk::StructureType::PIPELINE_SHADER_STAGE_CREATE_INFO,
-                p_next: ptr::null(),
-                flags: vk::PipelineShaderStageCreateFlags::empty(),
-                stage: vk::ShaderStageFlags::VERTEX,
-                module: vertex_shader_module,
-                p_name: entry.as_ptr(),
-                p_specialization_info: ptr::null(),
-                _marker: PhantomData,
+        let clear_depth = self
+            .pipeline_builder
+            .depth_test
+            .map_or(1.0, |depth_test| depth_test.clear_depth);
+        unsafe {
+            device.begin_command_buffer(command_buffer, &begin_info)?;
+            self.debug_utils
+                .begin_label(command_buffer, "Render pass", [0.0; 4]);
+            match &self.dynamic_rendering {
+                Some(dynamic_rendering) => dynamic_rendering.begin(
+                    device,
+                    command_buffer,
+                    self.extent,
+                    &Attachments {
+                        color_image: self.images[image_index],
+                        color_view: self.image_views[image_index],
+                        depth_buffer: &self.depth_buffers[image_index],
+                        depth_format: self.depth_format,
+                        clear_color: self.clear_color,
+                        clear_depth,
+                    },
+                ),
+                None => self.begin_render_pass(command_buffer, image_index, clear_depth),
+            }
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline.pipeline,
+            );
+            self.pipeline
+                .set_dynamic_state(device, command_buffer, self.extent);
+            if !descriptor_sets.is_empty() {
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.pipeline.layout,
+                    0,
+                    descriptor_sets,
+                    &[],
+                );
+            }
+            if !self.pipeline.push_constant_stages.is_empty() {
+                device.cmd_push_constants(
+                    command_buffer,
+                    self.pipeline.layout,
+                    self.pipeline.push_constant_stages,
+                    0,
+                    self.camera.as_bytes(),
+                );
+            }
+            self.debug_utils
+                .begin_label(command_buffer, "Meshes", [0.0; 4]);
+            for mesh in &self.meshes {
+                mesh.draw(device, command_buffer);
+            }
+            self.debug_utils.end_label(command_buffer);
+            self.debug_utils
+                .begin_label(command_buffer, "Draw hook", [0.0; 4]);
+            hook(&mut DrawContext {
+                device,
+                command_buffer,
+                pipeline: &self.pipeline,
+                meshes: &self.meshes,
+                extent: self.extent,
+                debug_utils: &self.debug_utils,
+            });
+            self.debug_utils.end_label(command_buffer);
+            match &self.dynamic_rendering {
+                Some(dynamic_rendering) => dynamic_rendering.end(
+                    device,
+                    command_buffer,
+                    self.images[image_index],
+                    self.final_layout(),
+                ),
+                None => device.cmd_end_render_pass(command_buffer),
+            }
+            self.debug_utils.end_label(command_buffer);
+            if let RenderTarget::Offscreen(offscreen) = &self.target {
+                self.debug_utils
+                    .begin_label(command_buffer, "Readback", [0.0; 4]);
+                offscreen.record_readback(device, command_buffer, self.images[image_index]);
+                self.debug_utils.end_label(command_buffer);
+            }
+            device.end_command_buffer(command_buffer)
+        }
+    }
+    //Recorded from scratch every frame, the hook runs after the renderers own meshes
+
+    unsafe fn begin_render_pass(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        clear_depth: f32,
+    ) {
+        let render_area = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: self.extent,
+        };
+
+        let clear_values = [
+            vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: self.clear_color,
+                },
             },
-            vk::PipelineShaderStageCreateInfo {
-                s_type: vk::StructureType::PIPELINE_SHADER_STAGE_CREATE_INFO,
-                p_next: ptr::null(),
-                flags: vk::PipelineShaderStageCreateFlags::empty(),
-                stage: vk::ShaderStageFlags::FRAGMENT,
-                module: fragment_shader_module,
-                p_name: entry.as_ptr(),
-                p_specialization_info: ptr::null(),
-                _marker: PhantomData,
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: clear_depth,
+                    stencil: 0,
+                },
             },
         ];
 
-        let vertex_input_state = vk::PipelineVertexInputStateCreateInfo {
-            s_type: vk::StructureType::PIPELINE_VERTEX_INPUT_STATE_CREATE_INFO,
+        let render_pass_begin = vk::RenderPassBeginInfo {
+            s_type: vk::StructureType::RENDER_PASS_BEGIN_INFO,
             p_next: ptr::null(),
-            flags: vk::PipelineVertexInputStateCreateFlags::empty(),
-            vertex_attribute_description_count: 0,
-            p_vertex_attribute_descriptions: ptr::null(),
-            vertex_binding_description_count: 0,
-            p_vertex_binding_descriptions: ptr::null(),
+            render_area,
+            framebuffer: self.framebuffers[image_index],
+            render_pass: self.render_pass,
+            clear_value_count: clear_values.len() as u32,
+            p_clear_values: clear_values.as_ptr(),
             _marker: PhantomData,
         };
+        self.device.cmd_begin_render_pass(
+            command_buffer,
+            &render_pass_begin,
+            vk::SubpassContents::INLINE,
+        );
+    }
+
+    fn final_layout(&self) -> vk::ImageLayout {
+        match self.target {
+            RenderTarget::Window(_) => vk::ImageLayout::PRESENT_SRC_KHR,
+            RenderTarget::Offscreen(_) => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        }
+    }
 
-        let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo {
-            s_type: vk::StructureType::PIPELINE_INPUT_ASSEMBLY_STATE_CREATE_INFO,
+    fn create_semaphores(count: usize, device: &ash::Device) -> VkResult<Vec<vk::Semaphore>> {
+        let semaphore_create_info = vk::SemaphoreCreateInfo {
+            s_type: vk::StructureType::SEMAPHORE_CREATE_INFO,
             p_next: ptr::null(),
-            flags: vk::PipelineInputAssemblyStateCreateFlags::empty(),
-            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
-            primitive_restart_enable: vk::FALSE,
+            flags: vk::SemaphoreCreateFlags::empty(),
             _marker: PhantomData,
         };
-        //How vertices form a shape
 
-        let viewports = [vk::Viewport {
-            x: 0.0,
-            y: 0.0,
-            width: extent.width as f32,
-            height: extent.height as f32,
-            min_depth: 0.0,
-            max_depth: 1.0,
-        }];
-        //Area rendered
+        let mut semaphores = Vec::with_capacity(count);
+        for _ in 0..count {
+            match unsafe { device.create_semaphore(&semaphore_create_info, None) } {
+                Ok(semaphore) => semaphores.push(semaphore),
+                Err(err) => {
+                    semaphores.iter().for_each(|&semaphore| unsafe {
+                        device.destroy_semaphore(semaphore, None)
+                    });
+                    return Err(err);
+                }
+            }
+        }
+        Ok(semaphores)
+    }
+    //One "rendering finished" semaphore per swapchain image, presentation holds on to it until the image is reacquired
 
-        let scissors = [vk::Rect2D {
-            offset: vk::Offset2D { x: 0, y: 0 },
-            extent,
-        }];
-        //Which part of viewport is rendered
+    /// Records, submits and presents one frame.
+    #[inline]
+    pub fn draw(&mut self) -> LyeResult<()> {
+        self.draw_with(|_| ())
+    }
 
-        let viewport_state = vk::PipelineViewportStateCreateInfo {
-            s_type: vk::StructureType::PIPELINE_VIEWPORT_STATE_CREATE_INFO,
-            p_next: ptr::null(),
-            flags: vk::PipelineViewportStateCreateFlags::empty(),
-            viewport_count: 1,
-            p_viewports: viewports.as_ptr(),
-            scissor_count: 1,
-            p_scissors: scissors.as_ptr(),
-            _marker: PhantomData,
-        };
+    /// Like [`Renderer::draw`], `hook` can record extra draws after the renderers meshes.
+    pub fn draw_with<F: FnOnce(&mut DrawContext)>(&mut self, hook: F) -> LyeResult<()> {
+        #[cfg(feature = "hot-reload")]
+        self.reload_shaders();
+        let result = self.draw_frame(hook);
+        self.check_validation()?;
 
-        let rasterization_state = vk::PipelineRasterizationStateCreateInfo {
-            s_type: vk::StructureType::PIPELINE_RASTERIZATION_STATE_CREATE_INFO,
-            p_next: ptr::null(),
-            flags: vk::PipelineRasterizationStateCreateFlags::empty(),
-            depth_clamp_enable: vk::FALSE,
-            rasterizer_discard_enable: vk::FALSE,
-            polygon_mode: vk::PolygonMode::FILL,
-            cull_mode: vk::CullModeFlags::BACK,
-            front_face: vk::FrontFace::CLOCKWISE,
-            depth_bias_enable: vk::FALSE,
-            depth_bias_constant_factor: 0.0,
-            depth_bias_clamp: 0.0,
-            depth_bias_slope_factor: 0.0,
-            line_width: 1.0,
-            _marker: PhantomData,
-        };
-        //Coordinates to Pixels, face-culling (cut out none visible parts)
+        result
+    }
+    //In strict mode validation errors take precedence, they usually explain whatever else failed
+
+    fn draw_frame<F: FnOnce(&mut DrawContext)>(&mut self, hook: F) -> LyeResult<()> {
+        match &self.target {
+            RenderTarget::Window(target) => {
+                let size = target.window.inner_size();
+                if size.width == 0 || size.height == 0 {
+                    return Ok(());
+                }
+                //Minimized, nothing to draw into
+                if target.swapchain_outdated {
+                    return self.recreate_swapchain();
+                }
+            }
+            RenderTarget::Offscreen(_) => return self.draw_offscreen(hook),
+        }
 
-        let multisample_state = vk::PipelineMultisampleStateCreateInfo {
-            s_type: vk::StructureType::PIPELINE_MULTISAMPLE_STATE_CREATE_INFO,
-            p_next: ptr::null(),
-            flags: vk::PipelineMultisampleStateCreateFlags::empty(),
-            rasterization_samples: vk::SampleCountFlags::TYPE_1,
-            sample_shading_enable: vk::FALSE,
-            min_sample_shading: 1.0,
-            p_sample_mask: ptr::null(),
-            alpha_to_coverage_enable: 0,
-            alpha_to_one_enable: 0,
-            _marker: PhantomData,
+        let RenderTarget::Window(target) = &self.target else {
+            unreachable!()
         };
-        //Multisampling
-
-        let color_blend_attachments = [vk::PipelineColorBlendAttachmentState {
-            blend_enable: vk::FALSE,
-            src_color_blend_factor: vk::BlendFactor::ONE,
-            dst_color_blend_factor: vk::BlendFactor::ZERO,
-            color_blend_op: vk::BlendOp::ADD,
-            src_alpha_blend_factor: vk::BlendFactor::ONE,
-            dst_alpha_blend_factor: vk::BlendFactor::ZERO,
-            alpha_blend_op: vk::BlendOp::ADD,
-            color_write_mask: vk::ColorComponentFlags::RGBA,
-        }];
+        let (swapchain_loader, swapchain) = (&target.swapchain_loader, target.swapchain);
+        let frame = &self.frames[self.current_frame];
 
-        let color_blend_state = vk::PipelineColorBlendStateCreateInfo {
-            s_type: vk::StructureType::PIPELINE_COLOR_BLEND_STATE_CREATE_INFO,
-            p_next: ptr::null(),
-            flags: vk::PipelineColorBlendStateCreateFlags::empty(),
-            logic_op: vk::LogicOp::COPY,
-            logic_op_enable: vk::FALSE,
-            attachment_count: 1,
-            p_attachments: color_blend_attachments.as_ptr(),
-            blend_constants: [0.0, 0.0, 0.0, 0.0],
-            _marker: PhantomData,
-        };
-        //Combines color from framebuffer and newly rendered color
+        unsafe {
+            self.device
+                .wait_for_fences(&[frame.in_flight], true, u64::MAX)
+                .context("Waiting for the frame fence")?;
+            self.device
+                .reset_command_pool(frame.command_pool, vk::CommandPoolResetFlags::empty())
+                .context("Resetting the frame command pool")?;
+            //The frames semaphore is only free again once its last submit completed
+
+            let descriptor_sets = self.frames[self.current_frame].descriptors.prepare(
+                &self.device,
+                &mut self.allocator,
+                &self.pipeline,
+                &self.uniforms,
+            )?;
+            let frame = &self.frames[self.current_frame];
+            //Prepared before acquiring, so a missing uniform buffer doesnt leave an image acquired
 
-        let pipeline_layout_info = vk::PipelineLayoutCreateInfo {
-            s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
-            p_next: ptr::null(),
-            flags: vk::PipelineLayoutCreateFlags::empty(),
-            set_layout_count: 0,
-            p_set_layouts: ptr::null(),
-            push_constant_range_count: 0,
-            p_push_constant_ranges: ptr::null(),
-            _marker: PhantomData,
-        };
+            let img_index = match swapchain_loader.acquire_next_image(
+                swapchain,
+                u64::MAX,
+                frame.image_available,
+                vk::Fence::null(),
+            ) {
+                Ok((img_index, _)) => img_index as usize,
+                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                    self.mark_swapchain_outdated();
+                    return self.recreate_swapchain();
+                }
+                Err(err) => return Err(LyeError::Swapchain(err)),
+            };
+            //A suboptimal swapchain can still be presented to, it gets recreated after presenting
 
-        let pipeline_layout =
-            unsafe { device.create_pipeline_layout(&pipeline_layout_info, None)? };
+            let image_fence = self.images_in_flight[img_index];
+            if image_fence != vk::Fence::null() && image_fence != frame.in_flight {
+                self.device
+                    .wait_for_fences(&[image_fence], true, u64::MAX)
+                    .context("Waiting for the image fence")?;
+            }
+            self.images_in_flight[img_index] = frame.in_flight;
+            //The images command buffer may still run as part of another frame
 
-        let pipeline_create_info = [vk::GraphicsPipelineCreateInfo {
-            s_type: vk::StructureType::GRAPHICS_PIPELINE_CREATE_INFO,
-            p_next: ptr::null(),
-            flags: vk::PipelineCreateFlags::empty(),
-            stage_count: shader_states_create_infos.len() as u32,
-            p_stages: shader_states_create_infos.as_ptr(),
-            p_vertex_input_state: &vertex_input_state,
-            p_input_assembly_state: &input_assembly_state,
-            p_tessellation_state: ptr::null(),
-            p_viewport_state: &viewport_state,
-            p_rasterization_state: &rasterization_state,
-            p_multisample_state: &multisample_state,
-            p_depth_stencil_state: ptr::null(),
-            p_color_blend_state: &color_blend_state,
-            p_dynamic_state: ptr::null(),
-            layout: pipeline_layout,
-            render_pass,
-            subpass: 0,
-            base_pipeline_handle: Default::default(),
-            base_pipeline_index: 0,
-            _marker: PhantomData,
-        }];
+            self.record_frame(frame.command_buffer, img_index, &descriptor_sets, hook)
+                .context("Recording the frame")?;
 
-        let pipeline = unsafe {
-            device
-                .create_graphics_pipelines(vk::PipelineCache::null(), &pipeline_create_info, None)
-                .unwrap()[0]
-        };
+            self.device
+                .reset_fences(&[frame.in_flight])
+                .context("Resetting the frame fence")?;
+            //Only reset once a submit is guaranteed, an early return would leave the fence unsignaled forever
+
+            let wait_semaphores = [frame.image_available];
+            let wait_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
+            let command_buffers = [frame.command_buffer];
+            let signal_semaphores = [self.rendering_finished[img_index]];
+            let submit_info = [vk::SubmitInfo {
+                s_type: vk::StructureType::SUBMIT_INFO,
+                p_next: ptr::null(),
+                wait_semaphore_count: wait_semaphores.len() as u32,
+                p_wait_semaphores: wait_semaphores.as_ptr(),
+                p_wait_dst_stage_mask: wait_stages.as_ptr(),
+                command_buffer_count: command_buffers.len() as u32,
+                p_command_buffers: command_buffers.as_ptr(),
+                signal_semaphore_count: signal_semaphores.len() as u32,
+                p_signal_semaphores: signal_semaphores.as_ptr(),
+                _marker: PhantomData,
+            }];
+
+            self.device
+                .queue_submit(self.present_graphics_queue, &submit_info, frame.in_flight)
+                .context("Submitting the frame")?;
+
+            let swapchains = [swapchain];
+            let image_indices = [img_index as u32];
+            let present_info = vk::PresentInfoKHR {
+                s_type: vk::StructureType::PRESENT_INFO_KHR,
+                p_next: ptr::null(),
+                wait_semaphore_count: signal_semaphores.len() as u32,
+                p_wait_semaphores: signal_semaphores.as_ptr(),
+                swapchain_count: swapchains.len() as u32,
+                p_swapchains: swapchains.as_ptr(),
+                p_image_indices: image_indices.as_ptr(),
+                p_results: ptr::null_mut(),
+                _marker: PhantomData,
+            };
+
+            self.current_frame = (self.current_frame + 1) % self.frames.len();
+
+            match swapchain_loader.queue_present(self.present_graphics_queue, &present_info) {
+                Ok(false) => Ok(()),
+                Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                    self.mark_swapchain_outdated();
+                    self.recreate_swapchain()
+                }
+                Err(err) => Err(LyeError::Swapchain(err)),
+            }
+        }
+    }
+    //Frames in flight rotate independently of the swapchain images, acquire decides which image a frame renders to
+
+    fn draw_offscreen<F: FnOnce(&mut DrawContext)>(&mut self, hook: F) -> LyeResult<()> {
+        let frame = &self.frames[self.current_frame];
 
         unsafe {
-            device.destroy_shader_module(vertex_shader_module, None);
-            device.destroy_shader_module(fragment_shader_module, None);
-        };
+            let mut fences = vec![frame.in_flight];
+            fences
+                .extend(Some(self.images_in_flight[0]).filter(|&fence| fence != vk::Fence::null()));
+            self.device
+                .wait_for_fences(&fences, true, u64::MAX)
+                .context("Waiting for the frame fence")?;
+            self.images_in_flight[0] = frame.in_flight;
+
+            self.device
+                .reset_command_pool(frame.command_pool, vk::CommandPoolResetFlags::empty())
+                .context("Resetting the frame command pool")?;
+            let descriptor_sets = self.frames[self.current_frame].descriptors.prepare(
+                &self.device,
+                &mut self.allocator,
+                &self.pipeline,
+                &self.uniforms,
+            )?;
+            let frame = &self.frames[self.current_frame];
+            self.record_frame(frame.command_buffer, 0, &descriptor_sets, hook)
+                .context("Recording the frame")?;
+
+            self.device
+                .reset_fences(&[frame.in_flight])
+                .context("Resetting the frame fence")?;
+
+            let command_buffers = [frame.command_buffer];
+            let submit_info = [vk::SubmitInfo {
+                s_type: vk::StructureType::SUBMIT_INFO,
+                p_next: ptr::null(),
+                wait_semaphore_count: 0,
+                p_wait_semaphores: ptr::null(),
+                p_wait_dst_stage_mask: ptr::null(),
+                command_buffer_count: command_buffers.len() as u32,
+                p_command_buffers: command_buffers.as_ptr(),
+                signal_semaphore_count: 0,
+                p_signal_semaphores: ptr::null(),
+                _marker: PhantomData,
+            }];
+
+            self.device
+                .queue_submit(self.present_graphics_queue, &submit_info, frame.in_flight)
+                .context("Submitting the frame")?;
+        }
+        self.current_frame = (self.current_frame + 1) % self.frames.len();
 
-        Ok((pipeline, pipeline_layout))
+        Ok(())
     }
+    //No swapchain to acquire from or present to, the single offscreen image is reused every frame
 
-    fn create_command_buffers(
-        queue_family_index: usize,
-        device: &ash::Device,
-        count: usize,
-    ) -> VkResult<(vk::CommandPool, Vec<vk::CommandBuffer>)> {
-        let commandpool_create_info = vk::CommandPoolCreateInfo {
-            s_type: vk::StructureType::COMMAND_POOL_CREATE_INFO,
-            p_next: ptr::null(),
-            flags: vk::CommandPoolCreateFlags::TRANSIENT,
-            queue_family_index: queue_family_index as u32,
-            _marker: PhantomData,
+    /// Reads back the last drawn frame, only for headless renderers.
+    pub fn read_frame(&self) -> LyeResult<Vec<u8>> {
+        let RenderTarget::Offscreen(offscreen) = &self.target else {
+            return Err(LyeError::NotHeadless);
         };
 
-        let command_pool = unsafe { device.create_command_pool(&commandpool_create_info, None)? };
+        if self.images_in_flight[0] != vk::Fence::null() {
+            unsafe {
+                self.device
+                    .wait_for_fences(&[self.images_in_flight[0]], true, u64::MAX)
+                    .context("Waiting for the last frame")?
+            };
+        }
 
-        let allocate_info = vk::CommandBufferAllocateInfo {
-            s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
-            p_next: ptr::null(),
-            command_pool,
-            level: vk::CommandBufferLevel::PRIMARY,
-            command_buffer_count: count as u32,
-            _marker: PhantomData,
-        };
+        Ok(offscreen.read())
+    }
+    //Tightly packed rows of the last drawn frame in the renderers format
+
+    /// Uploads a mesh that is drawn every frame from now on.
+    ///
+    /// The vertex layout has to match the pipeline, which is built for [`ColorVertex`],
+    /// otherwise this returns [`LyeError::ShaderInterface`].
+    pub fn add_mesh<V: Vertex>(&mut self, vertices: &[V], indices: &[u32]) -> LyeResult<MeshId> {
+        self.pipeline.check_vertex::<V>()?;
+        let mesh = Mesh::new(
+            &self.device,
+            &mut self.allocator,
+            &mut self.uploader,
+            vertices,
+            indices,
+        )?;
+        self.uploader
+            .flush(&self.device)
+            .context("Uploading the mesh")?;
+        self.meshes.push(mesh);
+        self.name_objects();
 
-        let command_buffers = unsafe { device.allocate_command_buffers(&allocate_info)? };
+        Ok(MeshId(self.meshes.len() - 1))
+    }
+    //The flush waits for the upload, the mesh is drawn from the next frame on
 
-        Ok((command_pool, command_buffers))
+    /// Camera used for every following frame.
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.camera = CameraPushConstants::from(camera);
     }
+    //Pushed at the start of every frame, DrawContext::set_camera overrides it for single draws
 
-    fn record_command_buffer(
-        command_buffers: &[vk::CommandBuffer],
-        device: &ash::Device,
-        render_pass: vk::RenderPass,
-        framebuffers: &[vk::Framebuffer],
-        extent: vk::Extent2D,
-        pipeline: vk::Pipeline,
-    ) -> VkResult<()> {
-        let begin_info = vk::CommandBufferBeginInfo {
-            s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
-            p_next: ptr::null(),
-            flags: vk::CommandBufferUsageFlags::empty(),
-            p_inheritance_info: ptr::null(),
-            _marker: PhantomData,
-        };
+    /// Creates the buffer for the uniform block at `set`/`binding` of the shaders, starting out as `value`.
+    ///
+    /// Fails if `T` doesnt follow std140 or doesnt match the block the shaders declare there.
+    pub fn create_uniform_buffer<T: UniformBlock>(
+        &mut self,
+        set: u32,
+        binding: u32,
+        value: &T,
+    ) -> LyeResult<UniformBuffer<T>> {
+        if self
+            .uniforms
+            .iter()
+            .any(|slot| (slot.set, slot.binding) == (set, binding))
+        {
+            return Err(LyeError::InvalidConfig(format!(
+                "Set {} binding {} already has a uniform buffer",
+                set, binding
+            )));
+        }
+        let slot = UniformSlot::new(set, binding, value)?;
+        slot.check_pipeline(&self.pipeline.uniform_blocks)?;
+        self.uniforms.push(slot);
 
-        let render_area = vk::Rect2D {
-            offset: vk::Offset2D { x: 0, y: 0 },
-            extent,
-        };
+        Ok(UniformSlot::handle(self.uniforms.len() - 1))
+    }
 
-        let clear_values = [vk::ClearValue {
-            color: vk::ClearColorValue {
-                float32: [1.0, 1.0, 1.0, 1.0],
-            },
-        }];
+    /// Replaces the value of a uniform block, used from the next [`Renderer::draw`] on.
+    ///
+    /// Fails if `buffer` doesnt point at a uniform buffer of `T`, which can happen with a handle
+    /// from another renderer.
+    pub fn update_uniform<T: UniformBlock>(
+        &mut self,
+        buffer: UniformBuffer<T>,
+        value: &T,
+    ) -> LyeResult<()> {
+        self.uniforms
+            .get_mut(buffer.index)
+            .ok_or_else(|| {
+                LyeError::InvalidConfig(format!("{:?} wasnt created by this renderer", buffer))
+            })?
+            .write(value)
+    }
+    //Frames in flight keep the copy they were recorded with
 
-        for (i, &command_buffer) in command_buffers.iter().enumerate() {
-            let render_pass_begin = vk::RenderPassBeginInfo {
-                s_type: vk::StructureType::RENDER_PASS_BEGIN_INFO,
-                p_next: ptr::null(),
-                render_area,
-                framebuffer: framebuffers[i],
-                render_pass,
-                clear_value_count: 1,
-                p_clear_values: clear_values.as_ptr(),
-                _marker: PhantomData,
-            };
-            unsafe {
-                device.begin_command_buffer(command_buffer, &begin_info)?;
-                device.cmd_begin_render_pass(
-                    command_buffer,
-                    &render_pass_begin,
-                    vk::SubpassContents::INLINE,
-                );
-                device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
-                device.cmd_draw(command_buffer, 3, 1, 0, 0);
-                device.cmd_end_render_pass(command_buffer);
-                device.end_command_buffer(command_buffer)?;
-            };
+    fn check_uniforms(&self, pipeline: &GraphicsPipeline) -> LyeResult<()> {
+        self.uniforms
+            .iter()
+            .try_for_each(|slot| slot.check_pipeline(&pipeline.uniform_blocks))
+    }
+    //A new pipeline has to keep accepting the uniform buffers already handed out
+
+    /// Replaces the fixed function state of the pipeline, and its shaders if the builder sets
+    /// [`GraphicsPipelineBuilder::shaders`], waits for the GPU and rebuilds it.
+    ///
+    /// The current pipeline is kept if the new one cant be built. Uniform blocks the new shaders
+    /// add need a buffer from [`Renderer::create_uniform_buffer`] before the next draw.
+    pub fn set_pipeline(&mut self, mut pipeline_builder: GraphicsPipelineBuilder) -> LyeResult<()> {
+        let shaders = pipeline_builder
+            .shaders
+            .take()
+            .unwrap_or_else(|| self.shaders.clone());
+        let target = self.current_pipeline_target();
+        let pipeline = pipeline_builder.build::<ColorVertex>(
+            &self.device,
+            self.pipeline_cache.handle(),
+            &mut self.layout_cache,
+            target,
+            &shaders,
+        )?;
+        if let Err(err) = self.check_uniforms(&pipeline) {
+            pipeline.destroy(&self.device);
+            return Err(err);
+        }
+        if let Err(result) = unsafe { self.device.device_wait_idle() } {
+            pipeline.destroy(&self.device);
+            return Err(LyeError::Vulkan {
+                context: "Waiting for the device",
+                result,
+            });
         }
+        self.pipeline.destroy(&self.device);
+        self.pipeline = pipeline;
+        self.pipeline_builder = pipeline_builder;
+        self.shaders = shaders;
+        self.name_objects();
 
         Ok(())
     }
 
-    fn create_semaphores_and_fences(
-        images_len: usize,
-        device: &ash::Device,
-    ) -> VkResult<(Vec<vk::Semaphore>, Vec<vk::Semaphore>, Vec<vk::Fence>)> {
-        let (mut available, mut finished, mut can_draw) = (
-            Vec::with_capacity(images_len),
-            Vec::with_capacity(images_len),
-            Vec::with_capacity(images_len),
-        );
-        let semaphore_create_info = vk::SemaphoreCreateInfo {
-            s_type: vk::StructureType::SEMAPHORE_CREATE_INFO,
-            p_next: ptr::null(),
-            flags: vk::SemaphoreCreateFlags::empty(),
-            _marker: PhantomData,
-        };
-        let fence_create_info = vk::FenceCreateInfo {
-            s_type: vk::StructureType::FENCE_CREATE_INFO,
-            p_next: ptr::null(),
-            flags: vk::FenceCreateFlags::SIGNALED,
-            _marker: PhantomData,
+    /// The state the current pipeline was built with, clone it to change a part of it.
+    pub fn pipeline_builder(&self) -> &GraphicsPipelineBuilder {
+        &self.pipeline_builder
+    }
+
+    /// Replaces the depth test, see [`Renderer::set_pipeline`].
+    pub fn set_depth_test(&mut self, depth_test: DepthTest) -> LyeResult<()> {
+        self.set_pipeline(self.pipeline_builder.clone().depth_test(Some(depth_test)))
+    }
+    //The compare op is baked into the pipeline, so it has to be rebuilt
+
+    /// Whether frames are recorded with dynamic rendering instead of a render pass.
+    pub fn uses_dynamic_rendering(&self) -> bool {
+        self.dynamic_rendering.is_some()
+    }
+
+    /// Changes how many frames the CPU may record ahead of the GPU, at least 1.
+    pub fn set_frames_in_flight(&mut self, count: usize) -> LyeResult<()> {
+        if count == 0 {
+            return Err(LyeError::InvalidConfig(
+                "At least one frame has to be in flight".to_owned(),
+            ));
+        }
+        let frames = FrameContext::create_ring(&self.device, self.queue_family_index, count)
+            .context("Creating frames in flight")?;
+
+        unsafe {
+            self.device
+                .device_wait_idle()
+                .context("Waiting for the device")?
         };
+        for frame in &mut self.frames {
+            frame.descriptors.destroy(&self.device, &mut self.allocator);
+            frame.destroy(&self.device);
+        }
+        self.frames = frames;
+        self.current_frame = 0;
+        self.images_in_flight.fill(vk::Fence::null());
+        self.name_objects();
 
-        for _ in 0..images_len {
-            unsafe {
-                available.push(device.create_semaphore(&semaphore_create_info, None)?);
-                finished.push(device.create_semaphore(&semaphore_create_info, None)?);
-                can_draw.push(device.create_fence(&fence_create_info, None)?);
-            };
+        Ok(())
+    }
+    //More frames in flight let the CPU run further ahead of the GPU at the cost of latency
+
+    /// Color the target is cleared to from the next frame on.
+    pub fn set_clear_color(&mut self, clear_color: [f32; 4]) {
+        self.clear_color = clear_color;
+    }
+
+    /// Required and supported optional features the device was created with.
+    pub fn features(&self) -> &vk::PhysicalDeviceFeatures {
+        &self.features
+    }
+
+    /// Whether `extension` was enabled, either required or optional and supported.
+    pub fn has_device_extension(&self, extension: &std::ffi::CStr) -> bool {
+        self.device_extensions.contains(&extension)
+    }
+
+    /// Number of frames that may be in flight at once.
+    pub fn frames_in_flight(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Format of the depth buffers, picked from what the device supports.
+    pub fn depth_format(&self) -> vk::Format {
+        self.depth_format
+    }
+
+    /// A mesh previously returned by [`Renderer::add_mesh`].
+    ///
+    /// # Panics
+    ///
+    /// If `id` came from a different renderer and is out of range for this one.
+    pub fn mesh(&self, id: MeshId) -> &Mesh {
+        &self.meshes[id.0]
+    }
+
+    /// Per heap statistics of the renderers allocator.
+    pub fn memory_usage(&self) -> Vec<HeapUsage> {
+        self.allocator.heap_usage()
+    }
+
+    /// Current size of the render target in pixels.
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    /// Color format of the render target.
+    pub fn format(&self) -> vk::Format {
+        self.format
+    }
+
+    /// The window being presented to, None for headless renderers.
+    pub fn window(&self) -> Option<&winit::window::Window> {
+        match &self.target {
+            RenderTarget::Window(target) => Some(&target.window),
+            RenderTarget::Offscreen(_) => None,
         }
+    }
 
-        Ok((available, finished, can_draw))
+    /// Changes the present mode preference, the swapchain is rebuilt before the next frame.
+    ///
+    /// Does nothing for headless renderers.
+    pub fn set_present_preference(&mut self, present_preference: PresentPreference) {
+        if let RenderTarget::Window(target) = &mut self.target {
+            if target.options.present_preference != present_preference {
+                target.options.present_preference = present_preference;
+                target.swapchain_outdated = true;
+            }
+        }
     }
 
-    #[inline]
-    fn draw(&mut self) -> VkResult<()> {
-        let current_img = (self.current_image + 1) % self.images.len();
-        self.current_image = current_img;
+    /// Switches between [`PresentPreference::VSync`] and [`PresentPreference::Immediate`].
+    pub fn set_vsync(&mut self, vsync: bool) {
+        if vsync != self.vsync() {
+            self.set_present_preference(match vsync {
+                true => PresentPreference::VSync,
+                false => PresentPreference::Immediate,
+            });
+        }
+    }
 
-        unsafe {
-            let (img_index, _) = self.swapchain_loader.acquire_next_image(
-                self.swapchain,
-                u64::MAX,
-                self.image_available[current_img],
-                vk::Fence::null(),
-            )?;
+    /// Whether the current preference waits for vblank, always true for headless renderers.
+    pub fn vsync(&self) -> bool {
+        match &self.target {
+            RenderTarget::Window(target) => target.options.present_preference.is_vsync(),
+            RenderTarget::Offscreen(_) => true,
+        }
+    }
 
-            self.device
-                .wait_for_fences(&[self.can_draw[current_img]], true, u64::MAX)?;
+    /// Present mode the swapchain was created with, None for headless renderers.
+    pub fn present_mode(&self) -> Option<vk::PresentModeKHR> {
+        match &self.target {
+            RenderTarget::Window(target) => Some(target.present_mode),
+            RenderTarget::Offscreen(_) => None,
+        }
+    }
 
-            self.device.reset_fences(&[self.can_draw[current_img]])?;
+    /// Validation errors recorded in strict mode since the last call, see
+    /// [`RendererBuilder::strict_validation`].
+    pub fn take_validation_errors(&mut self) -> Vec<String> {
+        self.debug_messenger
+            .as_ref()
+            .map(DebugMessenger::take_errors)
+            .unwrap_or_default()
+    }
 
-            let submit_info = [vk::SubmitInfo {
-                s_type: vk::StructureType::SUBMIT_INFO,
-                p_next: ptr::null(),
-                wait_semaphore_count: 1,
-                p_wait_semaphores: [self.image_available[current_img]].as_ptr(),
-                p_wait_dst_stage_mask: [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT].as_ptr(),
-                command_buffer_count: 1,
-                p_command_buffers: [self.command_buffers[current_img]].as_ptr(),
-                signal_semaphore_count: 1,
-                p_signal_semaphores: [self.rendering_finished[current_img]].as_ptr(),
-                _marker: PhantomData,
-            }];
+    fn check_validation(&mut self) -> LyeResult<()> {
+        if !self
+            .debug_messenger
+            .as_ref()
+            .is_some_and(DebugMessenger::is_strict)
+        {
+            return Ok(());
+        }
+        match self.take_validation_errors() {
+            errors if errors.is_empty() => Ok(()),
+            errors => Err(LyeError::Validation(errors)),
+        }
+    }
 
-            self.device.queue_submit(
-                self.present_graphics_queue,
-                &submit_info,
-                self.can_draw[current_img],
-            )?;
+    /// Blocks until the GPU finished all submitted work.
+    pub fn wait_idle(&self) -> LyeResult<()> {
+        unsafe { self.device.device_wait_idle() }.context("Waiting for the device")
+    }
 
-            let present_info = vk::PresentInfoKHR {
-                s_type: vk::StructureType::PRESENT_INFO_KHR,
-                p_next: ptr::null(),
-                wait_semaphore_count: 1,
-                p_wait_semaphores: [self.rendering_finished[current_img]].as_ptr(),
-                swapchain_count: 1,
-                p_swapchains: [self.swapchain].as_ptr(),
-                p_image_indices: [img_index].as_ptr(),
-                p_results: ptr::null_mut(),
-                _marker: PhantomData,
-            };
+    #[cfg(feature = "hot-reload")]
+    fn reload_shaders(&mut self) {
+        let Some(shader_watcher) = self.shader_watcher.as_mut() else {
+            return;
+        };
+        if !shader_watcher.changed() {
+            return;
+        }
 
-            self.swapchain_loader
-                .queue_present(self.present_graphics_queue, &present_info)?;
+        let shaders = match shader_watcher.compile() {
+            Ok(shaders) => shaders,
+            Err(err) => {
+                log::error!("Keeping the last good pipeline : {}", err);
+                return;
+            }
         };
-        Ok(())
+        let target = self.current_pipeline_target();
+        let pipeline = match self.pipeline_builder.build::<ColorVertex>(
+            &self.device,
+            self.pipeline_cache.handle(),
+            &mut self.layout_cache,
+            target,
+            &shaders,
+        ) {
+            Ok(pipeline) => pipeline,
+            Err(err) => {
+                log::error!("Keeping the last good pipeline : {}", err);
+                return;
+            }
+        };
+        if let Err(err) = self.check_uniforms(&pipeline) {
+            log::error!("Keeping the last good pipeline : {}", err);
+            pipeline.destroy(&self.device);
+            return;
+        }
+
+        if let Err(err) = unsafe { self.device.device_wait_idle() } {
+            log::error!(
+                "Couldnt wait for the device, keeping the old pipeline : {}",
+                err
+            );
+            pipeline.destroy(&self.device);
+            return;
+        }
+        self.pipeline.destroy(&self.device);
+        self.pipeline = pipeline;
+        self.shaders = shaders;
+        self.name_objects();
+        log::info!("Shaders reloaded");
+    }
+    //The new pipeline is built before the old one is destroyed, so a broken shader never leaves a gap
+
+    fn name_objects(&self) {
+        let debug_utils = &self.debug_utils;
+        match &self.target {
+            RenderTarget::Window(target) => {
+                debug_utils.name(target.surface, "Surface");
+                debug_utils.name(target.swapchain, "Swapchain");
+                for (i, &image) in self.images.iter().enumerate() {
+                    debug_utils.name(image, &format!("Swapchain image {}", i));
+                }
+            }
+            RenderTarget::Offscreen(offscreen) => {
+                debug_utils.name(self.images[0], "Offscreen image");
+                debug_utils.name(offscreen.readback_buffer(), "Readback buffer");
+            }
+        }
+        for (i, &image_view) in self.image_views.iter().enumerate() {
+            debug_utils.name(image_view, &format!("Color view {}", i));
+        }
+        for (i, depth_buffer) in self.depth_buffers.iter().enumerate() {
+            debug_utils.name(depth_buffer.image(), &format!("Depth image {}", i));
+            debug_utils.name(depth_buffer.view(), &format!("Depth view {}", i));
+        }
+        for (i, &framebuffer) in self.framebuffers.iter().enumerate() {
+            debug_utils.name(framebuffer, &format!("Framebuffer {}", i));
+        }
+        for (i, &semaphore) in self.rendering_finished.iter().enumerate() {
+            debug_utils.name(semaphore, &format!("Rendering finished {}", i));
+        }
+        for (i, frame) in self.frames.iter().enumerate() {
+            debug_utils.name(
+                frame.image_available,
+                &format!("Frame {} image available", i),
+            );
+            debug_utils.name(frame.in_flight, &format!("Frame {} in flight", i));
+            debug_utils.name(frame.command_pool, &format!("Frame {} command pool", i));
+            debug_utils.name(frame.command_buffer, &format!("Frame {} command buffer", i));
+        }
+        for (i, mesh) in self.meshes.iter().enumerate() {
+            let (vertex_buffer, index_buffer) = mesh.buffers();
+            debug_utils.name(vertex_buffer, &format!("Mesh {} vertices", i));
+            debug_utils.name(index_buffer, &format!("Mesh {} indices", i));
+        }
+        debug_utils.name(self.present_graphics_queue, "Graphics queue");
+        debug_utils.name(self.uploader.staging_buffer(), "Staging buffer");
+        if self.dynamic_rendering.is_none() {
+            debug_utils.name(self.render_pass, "Render pass");
+        }
+        debug_utils.name(self.pipeline_cache.handle(), "Pipeline cache");
+        debug_utils.name(self.pipeline.layout, "Pipeline layout");
+        debug_utils.name(self.pipeline.pipeline, "Pipeline");
+        for (set, &set_layout) in self.pipeline.set_layouts.iter().enumerate() {
+            debug_utils.name(set_layout, &format!("Descriptor set layout {}", set));
+        }
     }
+    //Renames everything, so it is simply called again whenever objects are replaced
 
-    fn recreate_swapchain(&mut self) -> VkResult<()> {
-        unsafe { self.device.device_wait_idle()? };
-        Ok(())
+    pub(crate) fn mark_swapchain_outdated(&mut self) {
+        if let RenderTarget::Window(target) = &mut self.target {
+            target.swapchain_outdated = true;
+        }
     }
-}
 
-impl winit::application::ApplicationHandler for App {
-    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
-        if self.renderer.is_none() {
-            self.renderer =
-                Some(Renderer::new(event_loop).unwrap_or_else(|err| {
-                    panic!("Error occured while creating Renderer : {}", err)
-                }))
+    fn recreate_swapchain(&mut self) -> LyeResult<()> {
+        let RenderTarget::Window(target) = &mut self.target else {
+            return Ok(());
+        };
+
+        let window_size = target.window.inner_size();
+        if window_size.width == 0 || window_size.height == 0 {
+            return Ok(());
+        }
+        //Stays outdated until the window is restored
+
+        unsafe {
+            self.device
+                .device_wait_idle()
+                .context("Waiting for the device")?
+        };
+
+        let old_swapchain = match target.swapchain_retired {
+            true => vk::SwapchainKHR::null(),
+            false => target.swapchain,
+        };
+        let (swapchain, format, extent, present_mode) = Renderer::create_swapchain(
+            (&target.surface_loader, target.surface),
+            self.physical_device,
+            &target.swapchain_loader,
+            self.queue_family_index,
+            window_size,
+            old_swapchain,
+            &target.options,
+        )
+        .map_err(LyeError::Swapchain)?;
+        target.swapchain_retired = true;
+        //A retired swapchain cant be passed as the old one again if the steps below fail
+        let swapchain_loader = target.swapchain_loader.clone();
+
+        let mut resources = SwapchainResources {
+            swapchain,
+            ..SwapchainResources::default()
+        };
+        if let Err(err) = self.create_swapchain_resources(&mut resources, format, extent) {
+            resources.destroy(&self.device, &mut self.allocator, &swapchain_loader);
+            return Err(err);
         }
+        //Everything is built before anything is replaced, so a failure leaves the old objects in place
+        //and the swapchain outdated, the next draw tries again instead of indexing empty vectors
+
+        unsafe {
+            for &framebuffer in &self.framebuffers {
+                self.device.destroy_framebuffer(framebuffer, None);
+            }
+            for &image_view in &self.image_views {
+                self.device.destroy_image_view(image_view, None);
+            }
+            for depth_buffer in &self.depth_buffers {
+                depth_buffer.destroy(&self.device, &mut self.allocator);
+            }
+            for &semaphore in &self.rendering_finished {
+                self.device.destroy_semaphore(semaphore, None);
+            }
+            if let Some(render_pass) = resources.render_pass {
+                self.device.destroy_render_pass(self.render_pass, None);
+                self.render_pass = render_pass;
+            }
+        };
+        if let Some(pipeline) = resources.pipeline {
+            self.pipeline.destroy(&self.device);
+            self.pipeline = pipeline;
+        }
+        self.images = resources.images;
+        self.image_views = resources.image_views;
+        self.depth_buffers = resources.depth_buffers;
+        self.framebuffers = resources.framebuffers;
+        self.rendering_finished = resources.rendering_finished;
+        self.images_in_flight = vec![vk::Fence::null(); self.images.len()];
+        self.format = format;
+        self.extent = extent;
+
+        let RenderTarget::Window(target) = &mut self.target else {
+            unreachable!()
+        };
+        unsafe { swapchain_loader.destroy_swapchain(target.swapchain, None) };
+        target.swapchain = swapchain;
+        target.swapchain_retired = false;
+        if present_mode != target.present_mode {
+            log::info!("Present mode : {:?}", present_mode);
+        }
+        target.present_mode = present_mode;
+        target.swapchain_outdated = false;
+        self.name_objects();
+
+        Ok(())
     }
+    //The image count can change with the swapchain, so everything sized by it is rebuilt, frames in flight are kept
 
-    #[inline]
-    fn window_event(
+    fn create_swapchain_resources(
         &mut self,
-        event_loop: &winit::event_loop::ActiveEventLoop,
-        _window_id: winit::window::WindowId,
-        event: winit::event::WindowEvent,
+        resources: &mut SwapchainResources,
+        format: vk::Format,
+        extent: vk::Extent2D,
+    ) -> LyeResult<()> {
+        let RenderTarget::Window(target) = &self.target else {
+            unreachable!()
+        };
+        (resources.images, resources.image_views) =
+            Renderer::acquire_swapchain_images_and_image_views(
+                &target.swapchain_loader,
+                resources.swapchain,
+                format,
+                &self.device,
+            )
+            .map_err(LyeError::Swapchain)?;
+
+        let format_changed = format != self.format;
+        if format_changed && self.dynamic_rendering.is_none() {
+            resources.render_pass = Some(
+                Renderer::create_renderpass(
+                    format,
+                    self.depth_format,
+                    vk::ImageLayout::PRESENT_SRC_KHR,
+                    &self.device,
+                )
+                .context("Creating the render pass")?,
+            );
+        }
+        let render_pass = resources.render_pass.unwrap_or(self.render_pass);
+
+        resources.depth_buffers = Renderer::create_depth_buffers(
+            &self.device,
+            &mut self.allocator,
+            self.depth_format,
+            extent,
+            resources.images.len(),
+        )
+        .context("Creating depth buffers")?;
+        if self.dynamic_rendering.is_none() {
+            resources.framebuffers = Renderer::create_framebuffers(
+                &self.device,
+                render_pass,
+                &resources.image_views,
+                &resources.depth_buffers,
+                extent,
+            )
+            .context("Creating framebuffers")?;
+        }
+        if format_changed {
+            let target = Renderer::pipeline_target(
+                self.dynamic_rendering.is_some(),
+                render_pass,
+                format,
+                self.depth_format,
+            );
+            resources.pipeline = Some(self.pipeline_builder.build::<ColorVertex>(
+                &self.device,
+                self.pipeline_cache.handle(),
+                &mut self.layout_cache,
+                target,
+                &self.shaders,
+            )?);
+        }
+        //Viewport and scissor are dynamic, only a new color format needs a new render pass and pipeline
+        resources.rendering_finished =
+            Renderer::create_semaphores(resources.images.len(), &self.device)
+                .context("Creating semaphores")?;
+
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct SwapchainResources {
+    swapchain: vk::SwapchainKHR,
+    images: Vec<vk::Image>,
+    image_views: Vec<vk::ImageView>,
+    render_pass: Option<vk::RenderPass>,
+    depth_buffers: Vec<DepthBuffer>,
+    framebuffers: Vec<vk::Framebuffer>,
+    pipeline: Option<GraphicsPipeline>,
+    rendering_finished: Vec<vk::Semaphore>,
+}
+//What recreate_swapchain builds before swapping it in, render_pass and pipeline are only set when the format changed
+
+impl SwapchainResources {
+    fn destroy(
+        &self,
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        swapchain_loader: &ash::khr::swapchain::Device,
     ) {
-        match event {
-            winit::event::WindowEvent::CloseRequested => {
-                unsafe {
-                    self.renderer
-                        .as_ref()
-                        .unwrap()
-                        .device
-                        .device_wait_idle()
-                        .unwrap();
-                }
-                event_loop.exit();
+        unsafe {
+            for &semaphore in &self.rendering_finished {
+                device.destroy_semaphore(semaphore, None);
             }
-            winit::event::WindowEvent::RedrawRequested => {
-                self.renderer
-                    .as_mut()
-                    .unwrap()
-                    .draw()
-                    .unwrap_or_else(|err| println!("Error while drawing : {}", err));
+            if let Some(pipeline) = &self.pipeline {
+                pipeline.destroy(device);
             }
-            _ => (),
-        }
+            for &framebuffer in &self.framebuffers {
+                device.destroy_framebuffer(framebuffer, None);
+            }
+            for depth_buffer in &self.depth_buffers {
+                depth_buffer.destroy(device, allocator);
+            }
+            if let Some(render_pass) = self.render_pass {
+                device.destroy_render_pass(render_pass, None);
+            }
+            for &image_view in &self.image_views {
+                device.destroy_image_view(image_view, None);
+            }
+            swapchain_loader.destroy_swapchain(self.swapchain, None);
+        };
     }
+}
+
+#[derive(Default)]
+struct PartialRenderer {
+    instance: Option<ash::Instance>,
+    debug_messenger: Option<DebugMessenger>,
+    surface: Option<(ash::khr::surface::Instance, vk::SurfaceKHR)>,
+    device: Option<ash::Device>,
+    allocator: Option<Allocator>,
+    uploader: Option<Uploader>,
+    target: Option<RenderTarget>,
+    images: Vec<vk::Image>,
+    image_views: Vec<vk::ImageView>,
+    depth_buffers: Vec<DepthBuffer>,
+    render_pass: vk::RenderPass,
+    framebuffers: Vec<vk::Framebuffer>,
+    pipeline_cache: Option<PipelineCache>,
+    layout_cache: DescriptorLayoutCache,
+    pipeline: Option<GraphicsPipeline>,
+    frames: Vec<FrameContext>,
+}
+//What Renderer::create has built so far, the renderer takes everything out of it once it is complete
 
-    fn about_to_wait(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
-        self.renderer.as_ref().unwrap().window.request_redraw();
+impl Drop for PartialRenderer {
+    fn drop(&mut self) {
+        let Some(instance) = &self.instance else {
+            return;
+        };
+        unsafe {
+            if let Some(device) = &self.device {
+                for frame in &self.frames {
+                    frame.destroy(device);
+                }
+                if let Some(pipeline) = &self.pipeline {
+                    pipeline.destroy(device);
+                }
+                self.layout_cache.destroy(device);
+                if let Some(pipeline_cache) = &self.pipeline_cache {
+                    pipeline_cache.destroy(device);
+                }
+                for &framebuffer in &self.framebuffers {
+                    device.destroy_framebuffer(framebuffer, None);
+                }
+                device.destroy_render_pass(self.render_pass, None);
+                for &image_view in &self.image_views {
+                    device.destroy_image_view(image_view, None);
+                }
+                if let Some(allocator) = &mut self.allocator {
+                    for depth_buffer in &self.depth_buffers {
+                        depth_buffer.destroy(device, allocator);
+                    }
+                    if let (Some(RenderTarget::Offscreen(offscreen)), Some(&image)) =
+                        (&self.target, self.images.first())
+                    {
+                        offscreen.destroy(device, allocator, image);
+                    }
+                    if let Some(uploader) = &self.uploader {
+                        uploader.destroy(device, allocator);
+                    }
+                    allocator.destroy(device);
+                }
+                if let Some(RenderTarget::Window(target)) = &self.target {
+                    target
+                        .swapchain_loader
+                        .destroy_swapchain(target.swapchain, None);
+                }
+                device.destroy_device(None);
+            }
+            if let Some(RenderTarget::Window(target)) = &self.target {
+                target.surface_loader.destroy_surface(target.surface, None);
+            }
+            if let Some((surface_loader, surface)) = &self.surface {
+                surface_loader.destroy_surface(*surface, None);
+            }
+            if let Some(debug_messenger) = &self.debug_messenger {
+                debug_messenger.destroy();
+            }
+            instance.destroy_instance(None);
+        };
     }
 }
+//Same order as Renderer::drop, nothing was submitted yet so there is nothing to wait for
 
 impl Drop for Renderer {
     fn drop(&mut self) {
         unsafe {
-            for i in 0..self.can_draw.len() {
-                self.device.destroy_semaphore(self.image_available[i], None);
-                self.device
-                    .destroy_semaphore(self.rendering_finished[i], None);
-                self.device.destroy_fence(self.can_draw[i], None);
+            let _ = self.device.device_wait_idle();
+            for frame in &mut self.frames {
+                frame.descriptors.destroy(&self.device, &mut self.allocator);
+                frame.destroy(&self.device);
             }
-            self.device.destroy_command_pool(self.command_pool, None);
-            self.device.destroy_pipeline(self.pipeline, None);
-            self.device
-                .destroy_pipeline_layout(self.pipeline_layout, None);
+            for &semaphore in &self.rendering_finished {
+                self.device.destroy_semaphore(semaphore, None);
+            }
+            for mesh in &self.meshes {
+                mesh.destroy(&self.device, &mut self.allocator);
+            }
+            self.pipeline.destroy(&self.device);
+            self.layout_cache.destroy(&self.device);
+            self.pipeline_cache.save(&self.device);
+            self.pipeline_cache.destroy(&self.device);
             for i in 0..self.framebuffers.len() {
                 self.device.destroy_framebuffer(self.framebuffers[i], None);
             }
@@ -977,12 +2171,29 @@ impl Drop for Renderer {
             for i in 0..self.image_views.len() {
                 self.device.destroy_image_view(self.image_views[i], None);
             }
-            self.swapchain_loader
-                .destroy_swapchain(self.swapchain, None);
-            self.device.destroy_device(None);
-            self.surface_loader.destroy_surface(self.surface, None);
-            self.debug_utils
-                .destroy_debug_utils_messenger(self.debug_utils_messenger, None);
+            for depth_buffer in &self.depth_buffers {
+                depth_buffer.destroy(&self.device, &mut self.allocator);
+            }
+            match &self.target {
+                RenderTarget::Window(target) => {
+                    target
+                        .swapchain_loader
+                        .destroy_swapchain(target.swapchain, None);
+                    self.uploader.destroy(&self.device, &mut self.allocator);
+                    self.allocator.destroy(&self.device);
+                    self.device.destroy_device(None);
+                    target.surface_loader.destroy_surface(target.surface, None);
+                }
+                RenderTarget::Offscreen(offscreen) => {
+                    offscreen.destroy(&self.device, &mut self.allocator, self.images[0]);
+                    self.uploader.destroy(&self.device, &mut self.allocator);
+                    self.allocator.destroy(&self.device);
+                    self.device.destroy_device(None);
+                }
+            }
+            if let Some(debug_messenger) = &self.debug_messenger {
+                debug_messenger.destroy();
+            }
             self.instance.destroy_instance(None);
         };
     }
@@ -0,0 +1,607 @@
+//! GLSL to SPIR-V compilation with naga, shared by build.rs and runtime code through #[path]
+
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+pub const DEFINES_VAR: &str = "LYE_SHADER_DEFINES";
+//Extra defines, LYE_VERTEX/LYE_FRAGMENT/LYE_COMPUTE are always set
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Define {
+    pub file: Option<String>,
+    pub name: String,
+    pub value: String,
+}
+//file limits the define to the shader with that file name
+
+pub fn parse_defines(spec: &str) -> Result<Vec<Define>, String> {
+    spec.split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (file, define) = match entry.split_once(':') {
+                Some((file, define)) => (Some(file.trim().to_owned()), define),
+                None => (None, entry),
+            };
+            let (name, value) = define.split_once('=').unwrap_or((define, "1"));
+            let name = name.trim();
+            let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid || file.as_deref() == Some("") {
+                return Err(format!(
+                    "Invalid shader define {:?} in {}",
+                    entry, DEFINES_VAR
+                ));
+            }
+            Ok(Define {
+                file,
+                name: name.to_owned(),
+                value: value.trim().to_owned(),
+            })
+        })
+        .collect()
+}
+//"NAME=VALUE;fragment.glsl:OTHER" sets NAME for every shader and OTHER=1 for fragment.glsl only
+
+pub fn defines_for<'a>(defines: &'a [Define], file_name: &str) -> Vec<(&'a str, &'a str)> {
+    defines
+        .iter()
+        .filter(|define| define.file.as_deref().is_none_or(|file| file == file_name))
+        .map(|define| (define.name.as_str(), define.value.as_str()))
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct CompileError {
+    pub file: PathBuf,
+    pub line: u32,
+    pub column: u32,
+    pub message: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.file.display(),
+            self.line,
+            self.column,
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+pub struct CompiledShader {
+    pub words: Vec<u32>,
+    pub dependencies: Vec<PathBuf>,
+}
+//dependencies lists the shader itself and everything it includes
+
+#[derive(Default)]
+struct Preprocessed {
+    source: String,
+    lines: Vec<(PathBuf, u32)>,
+    dependencies: Vec<PathBuf>,
+    defined: HashSet<String>,
+    uncertain: HashSet<String>,
+}
+//lines maps every line of source back to the file and line it came from, defined holds the macros
+//known to be set so far and uncertain the ones (un)defined inside an #if that cant be evaluated
+
+struct Conditional {
+    parent_active: bool,
+    active: bool,
+    known: bool,
+    taken: bool,
+}
+//One #if/#ifdef/#ifndef block, known is false when its condition or one around it cant be evaluated
+
+fn ends_in_comment(line: &str, mut in_comment: bool) -> bool {
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (in_comment, c, chars.peek()) {
+            (true, '*', Some('/')) => {
+                chars.next();
+                in_comment = false;
+            }
+            (false, '/', Some('/')) => return false,
+            (false, '/', Some('*')) => {
+                chars.next();
+                in_comment = true;
+            }
+            _ => (),
+        }
+    }
+    in_comment
+}
+//Whether the next line starts inside a block comment
+
+pub fn stage_of(path: &Path) -> Option<naga::ShaderStage> {
+    let stem = path.file_stem()?.to_str()?;
+    match stem.rsplit(['.', '_']).next()? {
+        "vert" | "vertex" => Some(naga::ShaderStage::Vertex),
+        "frag" | "fragment" => Some(naga::ShaderStage::Fragment),
+        "comp" | "compute" => Some(naga::ShaderStage::Compute),
+        _ => None,
+    }
+}
+//vertex.glsl, sky_vertex.glsl and sky.vert.glsl are all vertex shaders
+
+fn stage_define(stage: naga::ShaderStage) -> &'static str {
+    match stage {
+        naga::ShaderStage::Vertex => "LYE_VERTEX",
+        naga::ShaderStage::Fragment => "LYE_FRAGMENT",
+        _ => "LYE_COMPUTE",
+    }
+}
+//Lets shared includes tell which stage they are compiled into
+
+fn preprocess(
+    path: &Path,
+    included_from: Option<(&Path, u32)>,
+    stack: &mut Vec<PathBuf>,
+    output: &mut Preprocessed,
+) -> Result<(), CompileError> {
+    let error_at = |message: String| {
+        let (file, line) = included_from.unwrap_or((path, 0));
+        CompileError {
+            file: file.to_owned(),
+            line,
+            column: 1,
+            message,
+        }
+    };
+
+    let text = fs::read_to_string(path)
+        .map_err(|err| error_at(format!("Couldnt read {} : {}", path.display(), err)))?;
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    if stack.contains(&canonical) {
+        return Err(error_at(format!("{} includes itself", path.display())));
+    }
+    if output.dependencies.contains(&canonical) {
+        return Ok(());
+    }
+    //Every file is included once, like with #pragma once
+    stack.push(canonical.clone());
+    output.dependencies.push(canonical);
+
+    let mut conditionals: Vec<Conditional> = Vec::new();
+    let mut in_comment = false;
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index as u32 + 1;
+        let directive = match in_comment {
+            true => None,
+            false => line.trim_start().strip_prefix('#').map(|directive| {
+                let directive = directive.trim_start();
+                let end = directive
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(directive.len());
+                (&directive[..end], directive[end..].trim())
+            }),
+        };
+        in_comment = ends_in_comment(line, in_comment);
+
+        let active = conditionals.last().is_none_or(|block| block.active);
+        let known = conditionals.last().is_none_or(|block| block.known);
+        let macro_name = |argument: &str| {
+            argument
+                .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .next()
+                .unwrap_or_default()
+                .to_owned()
+        };
+        match directive {
+            Some((keyword @ ("ifdef" | "ifndef"), argument)) => {
+                let name = macro_name(argument);
+                let condition = output.defined.contains(&name) == (keyword == "ifdef");
+                conditionals.push(Conditional {
+                    parent_active: active,
+                    active: active && condition,
+                    known: known && !output.uncertain.contains(&name),
+                    taken: condition,
+                });
+            }
+            Some(("if", _)) => conditionals.push(Conditional {
+                parent_active: active,
+                active,
+                known: false,
+                taken: false,
+            }),
+            Some(("elif", _)) => {
+                if let Some(block) = conditionals.last_mut() {
+                    match block.known && block.taken {
+                        true => block.active = false,
+                        false => {
+                            block.active = block.parent_active;
+                            block.known = false;
+                        }
+                    }
+                }
+            }
+            Some(("else", _)) => {
+                if let Some(block) = conditionals.last_mut() {
+                    if block.known {
+                        block.active = block.parent_active && !block.taken;
+                        block.taken = true;
+                    }
+                }
+            }
+            Some(("endif", _)) => {
+                conditionals.pop();
+            }
+            Some(("define", argument)) if active && known => {
+                output.defined.insert(macro_name(argument));
+            }
+            Some(("undef", argument)) if active && known => {
+                output.defined.remove(&macro_name(argument));
+            }
+            Some(("define" | "undef", argument)) if active => {
+                output.uncertain.insert(macro_name(argument));
+            }
+            _ => (),
+        }
+        //Only tracked to decide which includes to expand, naga evaluates the conditionals itself
+
+        let Some(("include", argument)) = directive else {
+            output.source.push_str(line);
+            output.source.push('\n');
+            output.lines.push((path.to_owned(), line_number));
+            continue;
+        };
+        if !known {
+            return Err(CompileError {
+                file: path.to_owned(),
+                line: line_number,
+                column: 1,
+                message: "#include inside #if or #elif isnt supported, only #ifdef, #ifndef and #else are evaluated".to_owned(),
+            });
+        }
+        if !active {
+            continue;
+        }
+
+        let include = argument
+            .strip_prefix('"')
+            .and_then(|rest| rest.strip_suffix('"'))
+            .or_else(|| {
+                argument
+                    .strip_prefix('<')
+                    .and_then(|rest| rest.strip_suffix('>'))
+            })
+            .ok_or_else(|| CompileError {
+                file: path.to_owned(),
+                line: line_number,
+                column: 1,
+                message: format!("Malformed #include {}", argument),
+            })?;
+        let include_path = path.parent().unwrap_or(Path::new("")).join(include);
+        preprocess(&include_path, Some((path, line_number)), stack, output)?;
+    }
+
+    stack.pop();
+    Ok(())
+}
+//Includes are resolved relative to the including file, the ones in skipped #ifdef/#ifndef branches or
+//comments are left out
+
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        message.push_str(" : ");
+        message.push_str(&error.to_string());
+        source = error.source();
+    }
+    message
+}
+
+pub fn compile(
+    path: &Path,
+    stage: naga::ShaderStage,
+    defines: &[(&str, &str)],
+) -> Result<CompiledShader, CompileError> {
+    let mut preprocessed = Preprocessed::default();
+    preprocessed.defined.insert(stage_define(stage).to_owned());
+    preprocessed
+        .defined
+        .extend(defines.iter().map(|&(name, _)| name.to_owned()));
+    preprocess(path, None, &mut Vec::new(), &mut preprocessed)?;
+    let Preprocessed {
+        source,
+        lines,
+        dependencies,
+        ..
+    } = preprocessed;
+
+    let error_at = |location: Option<naga::SourceLocation>, message: String| {
+        let (file, line, column) = match location {
+            Some(location) => {
+                let (file, line) = lines
+                    .get(location.line_number as usize - 1)
+                    .or(lines.last())
+                    .cloned()
+                    .unwrap_or_else(|| (path.to_owned(), 0));
+                (file, line, location.line_position)
+            }
+            None => (path.to_owned(), 0, 0),
+        };
+        CompileError {
+            file,
+            line,
+            column,
+            message,
+        }
+    };
+
+    let mut options = naga::front::glsl::Options::from(stage);
+    options
+        .defines
+        .insert(stage_define(stage).to_owned(), "1".to_owned());
+    for &(name, value) in defines {
+        options.defines.insert(name.to_owned(), value.to_owned());
+    }
+    let module = naga::front::glsl::Frontend::default()
+        .parse(&options, &source)
+        .map_err(|errors| {
+            let error = &errors.errors[0];
+            let mut message = error.kind.to_string();
+            if errors.errors.len() > 1 {
+                message.push_str(&format!(" (and {} more)", errors.errors.len() - 1));
+            }
+            error_at(error.location(&source), message)
+        })?;
+
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|error| error_at(error.location(&source), error_chain(&error)))?;
+
+    let mut spv_options = naga::back::spv::Options::default();
+    spv_options
+        .flags
+        .remove(naga::back::spv::WriterFlags::ADJUST_COORDINATE_SPACE);
+    //The projection already flips Y, naga would flip it a second time
+    let words = naga::back::spv::write_vec(&module, &info, &spv_options, None)
+        .map_err(|error| error_at(None, error_chain(&error)))?;
+
+    Ok(CompiledShader {
+        words,
+        dependencies,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shader_dir(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("lye-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        for (name, source) in files {
+            let path = directory.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        directory
+    }
+    //Every test gets its own directory, tests run in parallel
+
+    #[test]
+    fn stage_is_taken_from_the_file_name() {
+        let stage = |name: &str| stage_of(Path::new(name));
+        assert_eq!(stage("vertex.glsl"), Some(naga::ShaderStage::Vertex));
+        assert_eq!(
+            stage("shaders/sky_vertex.glsl"),
+            Some(naga::ShaderStage::Vertex)
+        );
+        assert_eq!(stage("sky.vert.glsl"), Some(naga::ShaderStage::Vertex));
+        assert_eq!(stage("fragment.glsl"), Some(naga::ShaderStage::Fragment));
+        assert_eq!(stage("post_frag.glsl"), Some(naga::ShaderStage::Fragment));
+        assert_eq!(stage("cull.comp.glsl"), Some(naga::ShaderStage::Compute));
+        assert_eq!(stage("common.glsl"), None);
+        assert_eq!(stage("vertex_common.glsl"), None);
+    }
+
+    #[test]
+    fn defines_are_parsed_per_file() {
+        let defines = parse_defines("A=2; fragment.glsl:B ;;C = x + 1").unwrap();
+        assert_eq!(
+            defines_for(&defines, "fragment.glsl"),
+            [("A", "2"), ("B", "1"), ("C", "x + 1")]
+        );
+        assert_eq!(
+            defines_for(&defines, "vertex.glsl"),
+            [("A", "2"), ("C", "x + 1")]
+        );
+        assert_eq!(parse_defines("").unwrap(), []);
+        assert!(parse_defines("1A").is_err());
+        assert!(parse_defines(":A").is_err());
+        assert!(parse_defines("A B=1").is_err());
+    }
+
+    #[test]
+    fn includes_resolve_relative_to_the_including_file() {
+        let directory = shader_dir(
+            "includes",
+            &[
+                (
+                    "fragment.glsl",
+                    "#version 450\n#include \"lib/color.glsl\"\n#include <lib/color.glsl>\nlayout(location = 0) out vec4 color;\nvoid main() {\n    color = tint();\n}\n",
+                ),
+                ("lib/color.glsl", "#include \"constants.glsl\"\nvec4 tint() { return vec4(RED, 0.0, 0.0, 1.0); }\n"),
+                ("lib/constants.glsl", "const float RED = 1.0;\n"),
+            ],
+        );
+        let shader = directory.join("fragment.glsl");
+        let compiled = compile(&shader, naga::ShaderStage::Fragment, &[]).unwrap();
+        let dependencies: Vec<_> = compiled
+            .dependencies
+            .iter()
+            .map(|path| {
+                path.strip_prefix(directory.canonicalize().unwrap())
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(
+            dependencies,
+            [
+                Path::new("fragment.glsl"),
+                Path::new("lib/color.glsl"),
+                Path::new("lib/constants.glsl")
+            ]
+        );
+        assert!(!compiled.words.is_empty());
+        fs::remove_dir_all(directory).unwrap();
+    }
+    //The second include of color.glsl is skipped, otherwise tint would be defined twice
+
+    #[test]
+    fn include_errors_point_at_the_include() {
+        let directory = shader_dir(
+            "include-errors",
+            &[
+                (
+                    "fragment.glsl",
+                    "#version 450\n\n#include \"missing.glsl\"\n",
+                ),
+                (
+                    "self_frag.glsl",
+                    "#version 450\n#include \"self_frag.glsl\"\n",
+                ),
+            ],
+        );
+        let missing = compile(
+            &directory.join("fragment.glsl"),
+            naga::ShaderStage::Fragment,
+            &[],
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            (missing.file, missing.line),
+            (directory.join("fragment.glsl"), 3)
+        );
+
+        let recursive = compile(
+            &directory.join("self_frag.glsl"),
+            naga::ShaderStage::Fragment,
+            &[],
+        )
+        .err()
+        .unwrap();
+        assert_eq!(recursive.line, 2);
+        assert!(recursive.message.contains("includes itself"));
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn errors_map_to_the_included_file_and_line() {
+        let directory = shader_dir(
+            "error-lines",
+            &[
+                (
+                    "fragment.glsl",
+                    "#version 450\n#include \"common.glsl\"\nlayout(location = 0) out vec4 color;\nvoid main() {\n    color = vec4(1.0);\n}\n",
+                ),
+                ("common.glsl", "float a() { return 1.0; }\n\nfloat b() { return undefined_name; }\n"),
+            ],
+        );
+        let error = compile(
+            &directory.join("fragment.glsl"),
+            naga::ShaderStage::Fragment,
+            &[],
+        )
+        .err()
+        .unwrap();
+        assert_eq!(error.file, directory.join("common.glsl"));
+        assert_eq!(error.line, 3);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn defines_reach_the_shader() {
+        let directory = shader_dir(
+            "defines",
+            &[(
+                "fragment.glsl",
+                "#version 450\nlayout(location = 0) out vec4 color;\nvoid main() {\n    color = vec4(BRIGHTNESS);\n}\n",
+            )],
+        );
+        let shader = directory.join("fragment.glsl");
+        assert!(compile(&shader, naga::ShaderStage::Fragment, &[]).is_err());
+        compile(
+            &shader,
+            naga::ShaderStage::Fragment,
+            &[("BRIGHTNESS", "0.5")],
+        )
+        .unwrap();
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn includes_follow_ifdef_branches_and_skip_comments() {
+        let directory = shader_dir(
+            "conditional-includes",
+            &[
+                (
+                    "fragment.glsl",
+                    "#version 450\n#ifdef LYE_VERTEX\n#include \"missing.glsl\"\n#else\n#include \"fragment_only.glsl\"\n#endif\n#define LOCAL\n#ifndef LOCAL\n#include \"missing.glsl\"\n#endif\n#ifdef SHADOWS\n#include \"shadows.glsl\"\n#endif\n/*\n#include \"missing.glsl\"\n*/\nlayout(location = 0) out vec4 color;\nvoid main() {\n    color = tint();\n}\n",
+                ),
+                ("fragment_only.glsl", "vec4 tint() { return vec4(1.0); }\n"),
+                ("shadows.glsl", "const float SHADOW = 0.5;\n"),
+            ],
+        );
+        let shader = directory.join("fragment.glsl");
+        let file_names = |compiled: CompiledShader| -> Vec<String> {
+            compiled
+                .dependencies
+                .iter()
+                .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+
+        let compiled = compile(&shader, naga::ShaderStage::Fragment, &[]).unwrap();
+        assert_eq!(
+            file_names(compiled),
+            ["fragment.glsl", "fragment_only.glsl"]
+        );
+        let compiled = compile(&shader, naga::ShaderStage::Fragment, &[("SHADOWS", "1")]).unwrap();
+        assert_eq!(
+            file_names(compiled),
+            ["fragment.glsl", "fragment_only.glsl", "shadows.glsl"]
+        );
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn includes_inside_if_are_errors() {
+        let directory = shader_dir(
+            "if-includes",
+            &[
+                (
+                    "fragment.glsl",
+                    "#version 450\n#if SAMPLES > 1\n#include \"samples.glsl\"\n#endif\n",
+                ),
+                ("samples.glsl", "const int COUNT = SAMPLES;\n"),
+            ],
+        );
+        let error = compile(
+            &directory.join("fragment.glsl"),
+            naga::ShaderStage::Fragment,
+            &[("SAMPLES", "4")],
+        )
+        .err()
+        .unwrap();
+        assert_eq!(error.line, 3);
+        assert!(error.message.contains("#include inside #if"));
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,28 @@
+use std::borrow::Cow;
+
+#[derive(Clone, PartialEq)]
+pub(crate) struct PipelineShaders {
+    pub vertex: Cow<'static, [u8]>,
+    pub fragment: Cow<'static, [u8]>,
+}
+//SPIR-V the pipeline is built from, replaced at runtime by hot reloading
+
+impl Default for PipelineShaders {
+    fn default() -> Self {
+        Self {
+            vertex: Cow::Borrowed(include_bytes!(concat!(env!("OUT_DIR"), "/vertex.spv"))),
+            fragment: Cow::Borrowed(include_bytes!(concat!(env!("OUT_DIR"), "/fragment.spv"))),
+        }
+    }
+}
+//Compiled by build.rs
+
+impl std::fmt::Debug for PipelineShaders {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PipelineShaders")
+            .field("vertex", &format_args!("{} bytes", self.vertex.len()))
+            .field("fragment", &format_args!("{} bytes", self.fragment.len()))
+            .finish()
+    }
+}
+//The SPIR-V itself would flood the builders Debug output
@@ -0,0 +1,529 @@
+use crate::{
+    error::{LyeError, LyeResult},
+    reflect::DescriptorBinding,
+};
+use ash::vk;
+use std::{
+    any::{type_name, TypeId},
+    marker::PhantomData,
+    mem,
+};
+
+/// A type with a known std140 layout, usable as a [`UniformBlock`] field.
+///
+/// # Safety
+///
+/// Fields are copied into the uniform buffer by reading their first `SIZE` bytes (if the type
+/// is that large), so those bytes have to be initialized plain data without any padding.
+pub unsafe trait Std140: Copy + 'static {
+    /// Base alignment of the type in a std140 block.
+    const ALIGN: usize;
+    /// Bytes the shader reads, a `vec3` reads 12 even though it is aligned to 16.
+    const SIZE: usize;
+}
+
+macro_rules! impl_std140 {
+    ($($ty:ty => $align:expr, $size:expr;)*) => {
+        $(unsafe impl Std140 for $ty {
+            const ALIGN: usize = $align;
+            const SIZE: usize = $size;
+        })*
+    };
+}
+
+impl_std140! {
+    f32 => 4, 4;
+    i32 => 4, 4;
+    u32 => 4, 4;
+    glam::Vec2 => 8, 8;
+    glam::Vec3 => 16, 12;
+    glam::Vec4 => 16, 16;
+    glam::IVec2 => 8, 8;
+    glam::IVec3 => 16, 12;
+    glam::IVec4 => 16, 16;
+    glam::UVec2 => 8, 8;
+    glam::UVec3 => 16, 12;
+    glam::UVec4 => 16, 16;
+    glam::Mat4 => 16, 64;
+}
+//No bool (4 bytes in GLSL) and no Mat3 (columns padded to vec4), use u32 and Mat4 instead
+
+unsafe impl<T: Std140, const N: usize> Std140 for [T; N] {
+    const ALIGN: usize = 16;
+    const SIZE: usize = N * T::SIZE.next_multiple_of(16);
+}
+//Every array element is padded to 16 bytes, so only arrays of 16 byte types match their Rust layout
+
+/// Where a field of a [`UniformBlock`] lives, filled in by [`uniform_block!`](crate::uniform_block!).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UniformField {
+    /// Name of the Rust field.
+    pub name: &'static str,
+    /// Offset of the field in the Rust struct.
+    pub offset: usize,
+    /// std140 base alignment.
+    pub align: usize,
+    /// Bytes the shader reads.
+    pub size: usize,
+    /// Size of the Rust field.
+    pub rust_size: usize,
+}
+
+impl UniformField {
+    #[doc(hidden)]
+    pub fn of<B, T: Std140>(name: &'static str, offset: usize, _field: fn(&B) -> &T) -> Self {
+        Self {
+            name,
+            offset,
+            align: T::ALIGN,
+            size: T::SIZE,
+            rust_size: std::mem::size_of::<T>(),
+        }
+    }
+    //The accessor only exists to infer T from the field
+}
+
+/// A `#[repr(C)]` struct uploaded as a GLSL uniform block, implemented with [`uniform_block!`](crate::uniform_block!).
+///
+/// # Safety
+///
+/// Every [`UniformField`] has to describe a field of the struct, with its real offset and the
+/// [`Std140`] type it has, as [`uniform_block!`](crate::uniform_block!) does. The fields are read
+/// one by one from those offsets, padding between them is never read.
+pub unsafe trait UniformBlock: Copy + 'static {
+    /// The fields in declaration order.
+    fn fields() -> Vec<UniformField>;
+}
+
+/// Implements [`UniformBlock`] for a `#[repr(C)]` struct, listing all of its fields in order.
+///
+/// ```
+/// #[derive(Clone, Copy)]
+/// #[repr(C)]
+/// struct Globals {
+///     view_projection: glam::Mat4,
+///     tint: glam::Vec3,
+///     time: f32,
+/// }
+/// lye::uniform_block!(Globals { view_projection, tint, time });
+/// ```
+#[macro_export]
+macro_rules! uniform_block {
+    ($ty:ty { $($field:ident),+ $(,)? }) => {
+        unsafe impl $crate::UniformBlock for $ty {
+            fn fields() -> ::std::vec::Vec<$crate::UniformField> {
+                ::std::vec![$($crate::UniformField::of(
+                    ::std::stringify!($field),
+                    ::std::mem::offset_of!($ty, $field),
+                    |block: &$ty| &block.$field,
+                )),+]
+            }
+        }
+    };
+}
+
+/// Handle to a uniform block created with [`crate::Renderer::create_uniform_buffer`].
+pub struct UniformBuffer<T> {
+    pub(crate) index: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for UniformBuffer<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for UniformBuffer<T> {}
+
+impl<T> std::fmt::Debug for UniformBuffer<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("UniformBuffer").field(&self.index).finish()
+    }
+}
+//Written by hand, deriving would require T to implement them too
+
+pub(crate) struct UniformSlot {
+    pub(crate) set: u32,
+    pub(crate) binding: u32,
+    type_id: TypeId,
+    type_name: &'static str,
+    fields: Vec<UniformField>,
+    pub(crate) data: Vec<u8>,
+}
+//The latest value of one uniform block, copied into the frames uniform buffer when it is recorded
+
+impl UniformSlot {
+    pub(crate) fn new<T: UniformBlock>(set: u32, binding: u32, value: &T) -> LyeResult<Self> {
+        let mut fields = T::fields();
+        fields.sort_by_key(|field| field.offset);
+        check_std140(type_name::<T>(), mem::size_of::<T>(), &fields)?;
+        let mut slot = Self {
+            set,
+            binding,
+            type_id: TypeId::of::<T>(),
+            type_name: type_name::<T>(),
+            fields,
+            data: vec![0; mem::size_of::<T>()],
+        };
+        slot.copy_fields(value);
+
+        Ok(slot)
+    }
+
+    pub(crate) fn handle<T>(index: usize) -> UniformBuffer<T> {
+        UniformBuffer {
+            index,
+            _marker: PhantomData,
+        }
+    }
+
+    pub(crate) fn write<T: UniformBlock>(&mut self, value: &T) -> LyeResult<()> {
+        if TypeId::of::<T>() != self.type_id {
+            return Err(LyeError::InvalidConfig(format!(
+                "The uniform buffer at set {} binding {} holds {}, not {}",
+                self.set,
+                self.binding,
+                self.type_name,
+                type_name::<T>()
+            )));
+        }
+        self.copy_fields(value);
+
+        Ok(())
+    }
+    //A handle from another renderer can point at a slot of a different type
+
+    fn copy_fields<T: UniformBlock>(&mut self, value: &T) {
+        let base = value as *const T as *const u8;
+        for field in &self.fields {
+            let bytes = unsafe { std::slice::from_raw_parts(base.add(field.offset), field.size) };
+            self.data[field.offset..field.offset + field.size].copy_from_slice(bytes);
+        }
+    }
+    //Only the bytes of the fields are read, padding is uninitialized and stays zeroed in data.
+    //check_std140 made sure every field fits in T, the UniformBlock and Std140 contracts that they are initialized
+
+    pub(crate) fn check_pipeline(&self, uniform_blocks: &[DescriptorBinding]) -> LyeResult<()> {
+        match uniform_blocks
+            .iter()
+            .find(|block| (block.set, block.binding) == (self.set, self.binding))
+        {
+            Some(block) => self.check(block),
+            None => Err(LyeError::ShaderInterface(format!(
+                "The shaders have no uniform block at set {} binding {} for {}",
+                self.set, self.binding, self.type_name
+            ))),
+        }
+    }
+
+    fn check(&self, binding: &DescriptorBinding) -> LyeResult<()> {
+        let mismatch = |reason: String| {
+            Err(LyeError::ShaderInterface(format!(
+                "{} doesnt match uniform block {} at set {} binding {} : {}",
+                self.type_name, binding.name, binding.set, binding.binding, reason
+            )))
+        };
+        if binding.descriptor_type != vk::DescriptorType::UNIFORM_BUFFER || binding.count != 1 {
+            return mismatch(format!(
+                "the shader declares {:?}[{}], not a single uniform block",
+                binding.descriptor_type, binding.count
+            ));
+        }
+        let Some(block) = &binding.block else {
+            return mismatch("the block layout couldnt be reflected".to_owned());
+        };
+
+        for (index, member) in block.members.iter().enumerate() {
+            let Some(field) = self.fields.get(index) else {
+                return mismatch(format!(
+                    "{} at offset {} has no matching field",
+                    member.name, member.offset
+                ));
+            };
+            if (field.offset, field.size) != (member.offset as usize, member.size as usize) {
+                return mismatch(format!(
+                    "{} is {} bytes at offset {} in the shader, but {} is {} bytes at offset {}",
+                    member.name, member.size, member.offset, field.name, field.size, field.offset
+                ));
+            }
+        }
+        if let Some(field) = self.fields.get(block.members.len()) {
+            return mismatch(format!(
+                "{} at offset {} isnt in the shader block",
+                field.name, field.offset
+            ));
+        }
+
+        Ok(())
+    }
+    //Members are compared by position, naga doesnt always keep member names
+}
+
+fn check_std140(type_name: &str, type_size: usize, fields: &[UniformField]) -> LyeResult<()> {
+    let mut end: usize = 0;
+    for field in fields {
+        let expected = end.next_multiple_of(field.align);
+        if field.offset != expected {
+            return Err(LyeError::ShaderInterface(format!(
+                "{}.{} is at offset {}, but std140 puts it at {}, add padding or reorder the fields",
+                type_name, field.name, field.offset, expected
+            )));
+        }
+        if field.rust_size < field.size {
+            return Err(LyeError::ShaderInterface(format!(
+                "{}.{} is {} bytes, but std140 pads it to {} (array elements take 16 bytes each)",
+                type_name, field.name, field.rust_size, field.size
+            )));
+        }
+        if field.offset + field.rust_size > type_size {
+            return Err(LyeError::ShaderInterface(format!(
+                "{}.{} ends at {}, past the {} bytes of {}",
+                type_name,
+                field.name,
+                field.offset + field.rust_size,
+                type_size,
+                type_name
+            )));
+        }
+        end = field.offset + field.size;
+    }
+
+    Ok(())
+}
+//A field may start inside the padding of a vec3, so the end is tracked with the std140 size, not the Rust one
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reflect::{BlockLayout, BlockMember, ShaderReflection};
+    use glam::{Mat4, Vec3, Vec4};
+
+    #[derive(Clone, Copy)]
+    #[repr(C)]
+    struct Globals {
+        view_projection: Mat4,
+        tint: Vec3,
+        time: f32,
+        lights: [Vec4; 2],
+    }
+    crate::uniform_block!(Globals {
+        view_projection,
+        tint,
+        time,
+        lights
+    });
+
+    fn globals() -> Globals {
+        Globals {
+            view_projection: Mat4::IDENTITY,
+            tint: Vec3::new(1.0, 0.5, 0.25),
+            time: 2.0,
+            lights: [Vec4::ONE, Vec4::splat(3.0)],
+        }
+    }
+
+    fn uniform_block(members: &[(&str, u32, u32)]) -> DescriptorBinding {
+        let members: Vec<BlockMember> = members
+            .iter()
+            .map(|&(name, offset, size)| BlockMember {
+                name: name.to_owned(),
+                offset,
+                size,
+            })
+            .collect();
+        DescriptorBinding {
+            name: "globals".to_owned(),
+            set: 0,
+            binding: 1,
+            descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+            count: 1,
+            block: Some(BlockLayout {
+                size: members
+                    .last()
+                    .map_or(0, |member| member.offset + member.size),
+                members,
+            }),
+        }
+    }
+
+    fn globals_block() -> DescriptorBinding {
+        uniform_block(&[
+            ("view_projection", 0, 64),
+            ("tint", 64, 12),
+            ("time", 76, 4),
+            ("lights", 80, 32),
+        ])
+    }
+
+    fn std140_error<T: UniformBlock>(value: T) -> String {
+        match UniformSlot::new(0, 0, &value) {
+            Ok(_) => panic!("{} passed the std140 check", type_name::<T>()),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn std140_packs_scalars_after_vec3_and_pads_arrays() {
+        let slot = UniformSlot::new(0, 1, &globals()).unwrap();
+        let layout: Vec<_> = slot
+            .fields
+            .iter()
+            .map(|field| (field.name, field.offset, field.size))
+            .collect();
+        assert_eq!(
+            layout,
+            [
+                ("view_projection", 0, 64),
+                ("tint", 64, 12),
+                ("time", 76, 4),
+                ("lights", 80, 32)
+            ]
+        );
+    }
+
+    #[test]
+    fn std140_violations_name_the_field() {
+        #[derive(Clone, Copy)]
+        #[repr(C)]
+        struct TwoVec3 {
+            a: Vec3,
+            b: Vec3,
+        }
+        crate::uniform_block!(TwoVec3 { a, b });
+        let err = std140_error(TwoVec3 {
+            a: Vec3::ZERO,
+            b: Vec3::ZERO,
+        });
+        assert!(err.contains("TwoVec3.b is at offset 12, but std140 puts it at 16"));
+
+        #[derive(Clone, Copy)]
+        #[repr(C)]
+        struct ScalarArray {
+            weights: [f32; 4],
+        }
+        crate::uniform_block!(ScalarArray { weights });
+        let err = std140_error(ScalarArray { weights: [0.0; 4] });
+        assert!(err.contains("ScalarArray.weights is 16 bytes, but std140 pads it to 64"));
+    }
+
+    #[test]
+    fn matching_blocks_pass_the_pipeline_check() {
+        let slot = UniformSlot::new(0, 1, &globals()).unwrap();
+        slot.check_pipeline(&[globals_block()]).unwrap();
+    }
+
+    #[test]
+    fn pipeline_mismatches_are_errors() {
+        let slot = UniformSlot::new(0, 1, &globals()).unwrap();
+        let check =
+            |block: DescriptorBinding| slot.check_pipeline(&[block]).unwrap_err().to_string();
+
+        let mut elsewhere = globals_block();
+        elsewhere.binding = 2;
+        assert!(check(elsewhere).contains("no uniform block at set 0 binding 1"));
+
+        let mut storage = globals_block();
+        storage.descriptor_type = vk::DescriptorType::STORAGE_BUFFER;
+        assert!(check(storage).contains("not a single uniform block"));
+
+        let shifted = uniform_block(&[
+            ("view_projection", 0, 64),
+            ("tint", 64, 12),
+            ("time", 80, 4),
+            ("lights", 96, 32),
+        ]);
+        assert!(check(shifted).contains("time is 4 bytes at offset 80 in the shader"));
+
+        let shorter = uniform_block(&[("view_projection", 0, 64), ("tint", 64, 12)]);
+        assert!(check(shorter).contains("time at offset 76 isnt in the shader block"));
+
+        let longer = uniform_block(&[
+            ("view_projection", 0, 64),
+            ("tint", 64, 12),
+            ("time", 76, 4),
+            ("lights", 80, 32),
+            ("exposure", 112, 4),
+        ]);
+        assert!(check(longer).contains("exposure at offset 112 has no matching field"));
+    }
+
+    #[test]
+    fn nested_structs_are_compared_as_one_member() {
+        #[derive(Clone, Copy)]
+        #[repr(C)]
+        struct Flat {
+            color: Vec4,
+            intensity: f32,
+        }
+        crate::uniform_block!(Flat { color, intensity });
+        let slot = UniformSlot::new(
+            0,
+            1,
+            &Flat {
+                color: Vec4::ONE,
+                intensity: 1.0,
+            },
+        )
+        .unwrap();
+
+        let nested = uniform_block(&[("light", 0, 20)]);
+        let err = slot.check_pipeline(&[nested]).unwrap_err().to_string();
+        assert!(err.contains("light is 20 bytes at offset 0 in the shader, but color is 16 bytes"));
+    }
+    //struct Light { vec4 color; float intensity; } light; is a single 20 byte member to the reflection
+
+    #[test]
+    fn shipped_material_block_matches() {
+        #[derive(Clone, Copy)]
+        #[repr(C)]
+        struct Material {
+            tint: Vec3,
+            brightness: f32,
+        }
+        crate::uniform_block!(Material { tint, brightness });
+
+        let bytes = include_bytes!(concat!(env!("OUT_DIR"), "/tinted_fragment.spv"));
+        let words = ash::util::read_spv(&mut std::io::Cursor::new(&bytes[..])).unwrap();
+        let fragment = ShaderReflection::new("tinted_fragment.spv", &words).unwrap();
+        let slot = UniformSlot::new(
+            0,
+            0,
+            &Material {
+                tint: Vec3::ONE,
+                brightness: 1.0,
+            },
+        )
+        .unwrap();
+        slot.check_pipeline(&fragment.bindings).unwrap();
+    }
+    //The block of shaders/tinted_fragment.glsl, as used by the uniform_tint golden test
+
+    #[test]
+    fn writes_copy_fields_and_zero_padding() {
+        let mut slot = UniformSlot::new(0, 1, &globals()).unwrap();
+        let mut value = globals();
+        value.time = 5.0;
+        slot.write(&value).unwrap();
+
+        assert_eq!(slot.data.len(), mem::size_of::<Globals>());
+        assert_eq!(slot.data[76..80], 5f32.to_ne_bytes());
+        assert_eq!(slot.data[64..68], 1f32.to_ne_bytes());
+        assert_eq!(slot.data[96..100], 3f32.to_ne_bytes());
+        assert!(slot.data[112..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn writes_of_another_type_are_errors() {
+        #[derive(Clone, Copy)]
+        #[repr(C)]
+        struct Other {
+            time: f32,
+        }
+        crate::uniform_block!(Other { time });
+
+        let mut slot = UniformSlot::new(0, 1, &globals()).unwrap();
+        let err = slot.write(&Other { time: 1.0 }).unwrap_err().to_string();
+        assert!(err.contains("holds lye::uniform::tests::Globals, not lye::uniform::tests"));
+    }
+}
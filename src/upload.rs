@@ -0,0 +1,373 @@
+use crate::memory::{Allocation, Allocator, MemoryLocation};
+use ash::{self, prelude::VkResult, vk};
+use std::{marker::PhantomData, mem, ptr};
+
+const STAGING_SIZE: vk::DeviceSize = 16 * 1024 * 1024;
+const STAGING_ALIGNMENT: vk::DeviceSize = 16;
+
+const CONSUMER_STAGES: vk::PipelineStageFlags = vk::PipelineStageFlags::from_raw(
+    vk::PipelineStageFlags::VERTEX_INPUT.as_raw()
+        | vk::PipelineStageFlags::VERTEX_SHADER.as_raw()
+        | vk::PipelineStageFlags::FRAGMENT_SHADER.as_raw(),
+);
+const CONSUMER_ACCESS: vk::AccessFlags = vk::AccessFlags::from_raw(
+    vk::AccessFlags::VERTEX_ATTRIBUTE_READ.as_raw()
+        | vk::AccessFlags::INDEX_READ.as_raw()
+        | vk::AccessFlags::UNIFORM_READ.as_raw()
+        | vk::AccessFlags::SHADER_READ.as_raw(),
+);
+//Everything uploaded buffers can be read as on the graphics queue
+
+struct QueueCommands {
+    family_index: u32,
+    queue: vk::Queue,
+    command_pool: vk::CommandPool,
+    command_buffer: vk::CommandBuffer,
+}
+
+impl QueueCommands {
+    fn new(device: &ash::Device, family_index: u32, queue: vk::Queue) -> VkResult<Self> {
+        let commandpool_create_info = vk::CommandPoolCreateInfo {
+            s_type: vk::StructureType::COMMAND_POOL_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::CommandPoolCreateFlags::TRANSIENT,
+            queue_family_index: family_index,
+            _marker: PhantomData,
+        };
+        let command_pool = unsafe { device.create_command_pool(&commandpool_create_info, None)? };
+
+        let allocate_info = vk::CommandBufferAllocateInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
+            p_next: ptr::null(),
+            command_pool,
+            level: vk::CommandBufferLevel::PRIMARY,
+            command_buffer_count: 1,
+            _marker: PhantomData,
+        };
+        let command_buffer = unsafe { device.allocate_command_buffers(&allocate_info)?[0] };
+
+        Ok(Self {
+            family_index,
+            queue,
+            command_pool,
+            command_buffer,
+        })
+    }
+
+    fn begin(&self, device: &ash::Device) -> VkResult<()> {
+        let begin_info = vk::CommandBufferBeginInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
+            p_next: ptr::null(),
+            flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+            p_inheritance_info: ptr::null(),
+            _marker: PhantomData,
+        };
+
+        unsafe {
+            device.reset_command_pool(self.command_pool, vk::CommandPoolResetFlags::empty())?;
+            device.begin_command_buffer(self.command_buffer, &begin_info)
+        }
+    }
+
+    unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_command_pool(self.command_pool, None);
+    }
+}
+
+pub struct Uploader {
+    staging_buffer: vk::Buffer,
+    staging_allocation: Allocation,
+    head: vk::DeviceSize,
+    transfer: QueueCommands,
+    graphics: Option<QueueCommands>,
+    ownership_semaphore: vk::Semaphore,
+    fence: vk::Fence,
+    recording: bool,
+    pending: Vec<(vk::Buffer, vk::DeviceSize, vk::DeviceSize)>,
+}
+//graphics is only set when transfers run on their own queue family and ownership has to be handed over
+
+impl Uploader {
+    pub fn new(
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        graphics: (u32, vk::Queue),
+        transfer: Option<(u32, vk::Queue)>,
+    ) -> VkResult<Self> {
+        let buffer_create_info = vk::BufferCreateInfo {
+            s_type: vk::StructureType::BUFFER_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::BufferCreateFlags::empty(),
+            size: STAGING_SIZE,
+            usage: vk::BufferUsageFlags::TRANSFER_SRC,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+            _marker: PhantomData,
+        };
+        let (staging_buffer, staging_allocation) =
+            allocator.create_buffer(device, &buffer_create_info, MemoryLocation::CpuToGpu)?;
+
+        let (transfer, graphics) = match transfer {
+            Some((family_index, queue)) if family_index != graphics.0 => (
+                QueueCommands::new(device, family_index, queue)?,
+                Some(QueueCommands::new(device, graphics.0, graphics.1)?),
+            ),
+            _ => (QueueCommands::new(device, graphics.0, graphics.1)?, None),
+        };
+
+        let semaphore_create_info = vk::SemaphoreCreateInfo {
+            s_type: vk::StructureType::SEMAPHORE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::SemaphoreCreateFlags::empty(),
+            _marker: PhantomData,
+        };
+        let fence_create_info = vk::FenceCreateInfo {
+            s_type: vk::StructureType::FENCE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::FenceCreateFlags::empty(),
+            _marker: PhantomData,
+        };
+
+        let (ownership_semaphore, fence) = unsafe {
+            (
+                device.create_semaphore(&semaphore_create_info, None)?,
+                device.create_fence(&fence_create_info, None)?,
+            )
+        };
+
+        Ok(Self {
+            staging_buffer,
+            staging_allocation,
+            head: 0,
+            transfer,
+            graphics,
+            ownership_semaphore,
+            fence,
+            recording: false,
+            pending: Vec::new(),
+        })
+    }
+
+    pub fn staging_buffer(&self) -> vk::Buffer {
+        self.staging_buffer
+    }
+
+    pub fn upload_buffer<T: Copy>(
+        &mut self,
+        device: &ash::Device,
+        buffer: vk::Buffer,
+        data: &[T],
+    ) -> VkResult<()> {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(data.as_ptr() as *const u8, mem::size_of_val(data))
+        };
+        //Sound as long as T has no padding, callers pass u32 indices or a Vertex, whose unsafe impl promises it
+
+        let mut dst_offset = 0;
+        for chunk in bytes.chunks(STAGING_SIZE as usize) {
+            let size = chunk.len() as vk::DeviceSize;
+            if self.head + size > STAGING_SIZE {
+                self.flush(device)?;
+            }
+            //The ring is full, wait for the copies using it and start over at the front
+            if !self.recording {
+                self.transfer.begin(device)?;
+                self.recording = true;
+            }
+
+            self.staging_allocation.write(self.head as usize, chunk);
+            let region = vk::BufferCopy {
+                src_offset: self.head,
+                dst_offset,
+                size,
+            };
+            unsafe {
+                device.cmd_copy_buffer(
+                    self.transfer.command_buffer,
+                    self.staging_buffer,
+                    buffer,
+                    &[region],
+                )
+            };
+            self.pending.push((buffer, dst_offset, size));
+
+            self.head = (self.head + size).next_multiple_of(STAGING_ALIGNMENT);
+            dst_offset += size;
+        }
+
+        Ok(())
+    }
+    //Buffers have to be created with TRANSFER_DST and become usable after the next flush
+
+    fn buffer_barriers(
+        &self,
+        src_access_mask: vk::AccessFlags,
+        dst_access_mask: vk::AccessFlags,
+        families: (u32, u32),
+    ) -> Vec<vk::BufferMemoryBarrier<'static>> {
+        self.pending
+            .iter()
+            .map(|&(buffer, offset, size)| vk::BufferMemoryBarrier {
+                s_type: vk::StructureType::BUFFER_MEMORY_BARRIER,
+                p_next: ptr::null(),
+                src_access_mask,
+                dst_access_mask,
+                src_queue_family_index: families.0,
+                dst_queue_family_index: families.1,
+                buffer,
+                offset,
+                size,
+                _marker: PhantomData,
+            })
+            .collect()
+    }
+
+    pub fn flush(&mut self, device: &ash::Device) -> VkResult<()> {
+        if !self.recording {
+            return Ok(());
+        }
+
+        unsafe {
+            match &self.graphics {
+                Some(graphics) => {
+                    let families = (self.transfer.family_index, graphics.family_index);
+                    let release = self.buffer_barriers(
+                        vk::AccessFlags::TRANSFER_WRITE,
+                        vk::AccessFlags::empty(),
+                        families,
+                    );
+                    let acquire =
+                        self.buffer_barriers(vk::AccessFlags::empty(), CONSUMER_ACCESS, families);
+                    //Ownership transfer, released on the transfer queue and acquired on the graphics queue
+
+                    device.cmd_pipeline_barrier(
+                        self.transfer.command_buffer,
+                        vk::PipelineStageFlags::TRANSFER,
+                        vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &release,
+                        &[],
+                    );
+                    device.end_command_buffer(self.transfer.command_buffer)?;
+
+                    graphics.begin(device)?;
+                    device.cmd_pipeline_barrier(
+                        graphics.command_buffer,
+                        CONSUMER_STAGES,
+                        CONSUMER_STAGES,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &acquire,
+                        &[],
+                    );
+                    device.end_command_buffer(graphics.command_buffer)?;
+
+                    let transfer_submit = [vk::SubmitInfo {
+                        s_type: vk::StructureType::SUBMIT_INFO,
+                        p_next: ptr::null(),
+                        wait_semaphore_count: 0,
+                        p_wait_semaphores: ptr::null(),
+                        p_wait_dst_stage_mask: ptr::null(),
+                        command_buffer_count: 1,
+                        p_command_buffers: &self.transfer.command_buffer,
+                        signal_semaphore_count: 1,
+                        p_signal_semaphores: &self.ownership_semaphore,
+                        _marker: PhantomData,
+                    }];
+                    device.queue_submit(
+                        self.transfer.queue,
+                        &transfer_submit,
+                        vk::Fence::null(),
+                    )?;
+
+                    let acquire_submit = [vk::SubmitInfo {
+                        s_type: vk::StructureType::SUBMIT_INFO,
+                        p_next: ptr::null(),
+                        wait_semaphore_count: 1,
+                        p_wait_semaphores: &self.ownership_semaphore,
+                        p_wait_dst_stage_mask: &CONSUMER_STAGES,
+                        command_buffer_count: 1,
+                        p_command_buffers: &graphics.command_buffer,
+                        signal_semaphore_count: 0,
+                        p_signal_semaphores: ptr::null(),
+                        _marker: PhantomData,
+                    }];
+                    device.queue_submit(graphics.queue, &acquire_submit, self.fence)?;
+                    //The semaphore is waited on at the stages the acquire barrier starts from, so the barrier
+                    //chains to the copy, TOP_OF_PIPE there would order nothing after it
+                }
+                None => {
+                    let visible = self.buffer_barriers(
+                        vk::AccessFlags::TRANSFER_WRITE,
+                        CONSUMER_ACCESS,
+                        (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED),
+                    );
+                    device.cmd_pipeline_barrier(
+                        self.transfer.command_buffer,
+                        vk::PipelineStageFlags::TRANSFER,
+                        CONSUMER_STAGES,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &visible,
+                        &[],
+                    );
+                    device.end_command_buffer(self.transfer.command_buffer)?;
+
+                    let submit = [vk::SubmitInfo {
+                        s_type: vk::StructureType::SUBMIT_INFO,
+                        p_next: ptr::null(),
+                        wait_semaphore_count: 0,
+                        p_wait_semaphores: ptr::null(),
+                        p_wait_dst_stage_mask: ptr::null(),
+                        command_buffer_count: 1,
+                        p_command_buffers: &self.transfer.command_buffer,
+                        signal_semaphore_count: 0,
+                        p_signal_semaphores: ptr::null(),
+                        _marker: PhantomData,
+                    }];
+                    device.queue_submit(self.transfer.queue, &submit, self.fence)?;
+                }
+            }
+
+            device.wait_for_fences(&[self.fence], true, u64::MAX)?;
+            device.reset_fences(&[self.fence])?;
+        };
+
+        self.recording = false;
+        self.head = 0;
+        self.pending.clear();
+
+        Ok(())
+    }
+    //Blocks until every pending copy is done and visible to the graphics queue
+
+    pub fn abort(&mut self, device: &ash::Device) -> VkResult<()> {
+        if !self.recording {
+            return Ok(());
+        }
+
+        unsafe {
+            device.device_wait_idle()?;
+            device.reset_fences(&[self.fence])?;
+        };
+        self.recording = false;
+        self.head = 0;
+        self.pending.clear();
+
+        Ok(())
+    }
+    //Drops every copy recorded since the last flush, so their destination buffers can be destroyed,
+    //waits first in case a failed flush already submitted them, the next begin resets the command pool
+
+    pub unsafe fn destroy(&self, device: &ash::Device, allocator: &mut Allocator) {
+        device.destroy_fence(self.fence, None);
+        device.destroy_semaphore(self.ownership_semaphore, None);
+        if let Some(graphics) = &self.graphics {
+            graphics.destroy(device);
+        }
+        self.transfer.destroy(device);
+        allocator.destroy_buffer(device, self.staging_buffer, &self.staging_allocation);
+    }
+}
@@ -0,0 +1,157 @@
+use ash::{self, prelude::VkResult, vk};
+use std::{
+    collections::HashSet,
+    ffi::CStr,
+    sync::{Mutex, PoisonError},
+};
+
+pub(crate) const VALIDATION_LAYER: &CStr = c"VK_LAYER_KHRONOS_validation";
+
+#[derive(Default)]
+struct MessengerState {
+    strict: bool,
+    seen: Mutex<HashSet<(i32, String)>>,
+    errors: Mutex<Vec<String>>,
+}
+//Lives in a Box so the pointer handed to the driver as user data stays valid
+
+pub(crate) struct DebugMessenger {
+    debug_utils: ash::ext::debug_utils::Instance,
+    messenger: vk::DebugUtilsMessengerEXT,
+    state: Box<MessengerState>,
+}
+
+fn level(severity: vk::DebugUtilsMessageSeverityFlagsEXT) -> log::Level {
+    match severity {
+        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => log::Level::Error,
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => log::Level::Warn,
+        vk::DebugUtilsMessageSeverityFlagsEXT::INFO => log::Level::Info,
+        _ => log::Level::Trace,
+    }
+}
+
+fn severities(min_severity: log::Level) -> vk::DebugUtilsMessageSeverityFlagsEXT {
+    [
+        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING,
+        vk::DebugUtilsMessageSeverityFlagsEXT::INFO,
+        vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE,
+    ]
+    .into_iter()
+    .filter(|&severity| level(severity) <= min_severity)
+    .fold(
+        vk::DebugUtilsMessageSeverityFlagsEXT::empty(),
+        |mask, severity| mask | severity,
+    )
+}
+//log::Level orders Error lowest, so everything at or below the minimum is at least as severe
+
+unsafe extern "system" fn vulkan_debug_callback(
+    severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    typ: vk::DebugUtilsMessageTypeFlagsEXT,
+    p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+    p_user_data: *mut std::os::raw::c_void,
+) -> vk::Bool32 {
+    let state = &*(p_user_data as *const MessengerState);
+    let callback_data = &*p_callback_data;
+    let id_name = callback_data
+        .message_id_name_as_c_str()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let message = callback_data
+        .message_as_c_str()
+        .map(|message| message.to_string_lossy())
+        .unwrap_or_default();
+
+    if state.strict && severity == vk::DebugUtilsMessageSeverityFlagsEXT::ERROR {
+        state
+            .errors
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(format!("{} : {}", id_name, message));
+    }
+    //Recorded every time, a repeated error still has to fail the frame it happens in
+
+    let key = match (callback_data.message_id_number, id_name.is_empty()) {
+        (0, true) => (0, message.to_string()),
+        (number, _) => (number, id_name.to_string()),
+    };
+    //Messages without an ID are deduplicated by their text instead
+    if state
+        .seen
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(key)
+    {
+        log::log!(target: "vulkan", level(severity), "{:?} [{}] {}", typ, id_name, message);
+    }
+
+    vk::FALSE
+}
+//Panicking here would unwind into the driver, so poisoned locks are simply taken over
+
+impl DebugMessenger {
+    pub(crate) fn new(
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+        min_severity: log::Level,
+        strict: bool,
+    ) -> VkResult<Self> {
+        let state = Box::new(MessengerState {
+            strict,
+            ..Default::default()
+        });
+        let message_severity = severities(min_severity)
+            | match strict {
+                true => vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+                false => vk::DebugUtilsMessageSeverityFlagsEXT::empty(),
+            };
+        //Strict mode has to see errors even if they arent logged
+        let message_type = vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+            | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
+            | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION;
+
+        let create_info = vk::DebugUtilsMessengerCreateInfoEXT::default()
+            .message_severity(message_severity)
+            .message_type(message_type)
+            .pfn_user_callback(Some(vulkan_debug_callback))
+            .user_data(&*state as *const MessengerState as *mut std::os::raw::c_void);
+
+        let debug_utils = ash::ext::debug_utils::Instance::new(entry, instance);
+        let messenger = unsafe { debug_utils.create_debug_utils_messenger(&create_info, None)? };
+
+        Ok(Self {
+            debug_utils,
+            messenger,
+            state,
+        })
+    }
+
+    pub(crate) fn take_errors(&self) -> Vec<String> {
+        std::mem::take(
+            &mut *self
+                .state
+                .errors
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        )
+    }
+
+    pub(crate) fn is_strict(&self) -> bool {
+        self.state.strict
+    }
+
+    pub(crate) fn destroy(&self) {
+        unsafe {
+            self.debug_utils
+                .destroy_debug_utils_messenger(self.messenger, None)
+        };
+    }
+}
+
+pub(crate) fn validation_layer_available(entry: &ash::Entry) -> bool {
+    unsafe { entry.enumerate_instance_layer_properties() }
+        .unwrap_or_default()
+        .iter()
+        .any(|layer| layer.layer_name_as_c_str() == Ok(VALIDATION_LAYER))
+}
@@ -0,0 +1,311 @@
+use lye::{
+    vk, Camera, ColorVertex, DepthTest, GraphicsPipelineBuilder, LyeError, Projection, Renderer,
+    RendererBuilder,
+};
+use std::path::{Path, PathBuf};
+
+const EXTENT: vk::Extent2D = vk::Extent2D {
+    width: 64,
+    height: 64,
+};
+
+struct Tolerance {
+    channel: u8,
+    pixels: usize,
+}
+//channel : max difference per color channel, pixels : how many pixels may exceed it (edge rasterization differs between drivers)
+
+const DEFAULT_TOLERANCE: Tolerance = Tolerance {
+    channel: 2,
+    pixels: 0,
+};
+
+const EDGE_TOLERANCE: Tolerance = Tolerance {
+    channel: 2,
+    pixels: 8,
+};
+//Solid shapes with edges crossing pixel centers
+
+fn front_camera() -> Camera {
+    Camera::new(
+        glam::Vec3::new(0.0, 0.0, 1.0),
+        Projection::orthographic(2.0, 2.0, 0.1, 10.0),
+    )
+}
+//Maps world x/y in -1..1 straight onto the frame, Y up
+
+fn headless_renderer() -> Option<Renderer> {
+    let renderer = RendererBuilder::new()
+        .validation_layer(true)
+        .strict_validation(true)
+        .pipeline_cache(false)
+        .build_headless(EXTENT, vk::Format::R8G8B8A8_UNORM);
+    match renderer {
+        Ok(renderer) => Some(renderer),
+        Err(err @ LyeError::Loader(_)) => {
+            if std::env::var_os("LYE_REQUIRE_VULKAN").is_some() {
+                panic!(
+                    "LYE_REQUIRE_VULKAN is set, but there is no Vulkan loader : {}",
+                    err
+                );
+            }
+            eprintln!("Skipping golden test, no Vulkan loader : {}", err);
+            None
+        }
+        Err(err) => panic!("Couldnt create headless renderer : {}", err),
+    }
+}
+//Strict validation makes draw fail on any validation error, the layer is used when installed,
+//.github/workflows/ci.yml sets LYE_REQUIRE_VULKAN so a missing loader fails instead of passing without rendering
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn output_dir() -> PathBuf {
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden")
+}
+
+fn read_png(path: &Path) -> (u32, u32, Vec<u8>) {
+    let decoder = png::Decoder::new(std::fs::File::open(path).unwrap_or_else(|err| {
+        panic!(
+            "Missing reference {} ({}), rerun with LYE_BLESS=1 to create it",
+            path.display(),
+            err
+        )
+    }));
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!(
+        (info.color_type, info.bit_depth),
+        (png::ColorType::Rgba, png::BitDepth::Eight),
+        "{} has to be 8 bit RGBA",
+        path.display()
+    );
+    pixels.truncate(info.buffer_size());
+    (info.width, info.height, pixels)
+}
+
+fn write_png(path: &Path, width: u32, height: u32, pixels: &[u8]) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let mut encoder = png::Encoder::new(std::fs::File::create(path).unwrap(), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(pixels)
+        .unwrap();
+}
+
+fn diff_image(actual: &[u8], expected: &[u8], tolerance: &Tolerance) -> (usize, Vec<u8>) {
+    let mut mismatched = 0;
+    let diff = actual
+        .chunks_exact(4)
+        .zip(expected.chunks_exact(4))
+        .flat_map(|(a, e)| {
+            let worst = a.iter().zip(e).map(|(a, e)| a.abs_diff(*e)).max().unwrap();
+            if worst > tolerance.channel {
+                mismatched += 1;
+                [255, 0, 255, 255]
+            } else {
+                let grey = (e[..3].iter().map(|&c| c as u32).sum::<u32>() / 12) as u8;
+                [grey, grey, grey, 255]
+            }
+        })
+        .collect();
+    (mismatched, diff)
+}
+//Mismatched pixels are magenta, everything else is a darkened version of the reference
+
+fn assert_golden(name: &str, renderer: &Renderer, tolerance: Tolerance) {
+    let extent = renderer.extent();
+    let actual = renderer.read_frame().unwrap();
+    let reference = golden_dir().join(format!("{}.png", name));
+
+    if std::env::var_os("LYE_BLESS").is_some() {
+        write_png(&reference, extent.width, extent.height, &actual);
+        eprintln!("Blessed {}", reference.display());
+        return;
+    }
+
+    let (width, height, expected) = read_png(&reference);
+    assert_eq!(
+        (width, height),
+        (extent.width, extent.height),
+        "{} has a different size than the rendered frame",
+        reference.display()
+    );
+
+    let (mismatched, diff) = diff_image(&actual, &expected, &tolerance);
+    if mismatched > tolerance.pixels {
+        let actual_path = output_dir().join(format!("{}.actual.png", name));
+        let diff_path = output_dir().join(format!("{}.diff.png", name));
+        write_png(&actual_path, width, height, &actual);
+        write_png(&diff_path, width, height, &diff);
+        panic!(
+            "{} : {} pixels differ by more than {} (allowed {}), see {} and {}",
+            name,
+            mismatched,
+            tolerance.channel,
+            tolerance.pixels,
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+const TRIANGLE: [ColorVertex; 3] = [
+    ColorVertex {
+        position: [0.0, 0.5, 0.0],
+        color: [1.0, 0.0, 0.0],
+    },
+    ColorVertex {
+        position: [0.5, -0.5, 0.0],
+        color: [0.0, 1.0, 0.0],
+    },
+    ColorVertex {
+        position: [-0.5, -0.5, 0.0],
+        color: [0.0, 0.0, 1.0],
+    },
+];
+
+#[test]
+fn triangle() {
+    let Some(mut renderer) = headless_renderer() else {
+        return;
+    };
+    renderer.add_mesh(&TRIANGLE, &[0, 1, 2]).unwrap();
+    renderer.set_camera(&front_camera());
+    renderer.draw().unwrap();
+    assert_golden("triangle", &renderer, DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn draw_hook() {
+    let Some(mut renderer) = headless_renderer() else {
+        return;
+    };
+    let triangle = renderer.add_mesh(&TRIANGLE, &[0, 1, 2]).unwrap();
+    let mut camera = front_camera();
+    camera.position.x = 0.5;
+    renderer.set_camera(&camera);
+    camera.position.x = -0.5;
+    renderer
+        .draw_with(|context| {
+            context.set_camera(&camera);
+            context.draw_mesh(triangle);
+        })
+        .unwrap();
+    assert_golden("draw_hook", &renderer, EDGE_TOLERANCE);
+}
+//The renderer draws the triangle on the left, the hook draws it again on the right
+
+fn solid_triangle(
+    top: [f32; 2],
+    width: f32,
+    height: f32,
+    z: f32,
+    color: [f32; 3],
+) -> [ColorVertex; 3] {
+    let [x, y] = top;
+    [
+        ColorVertex {
+            position: [x, y, z],
+            color,
+        },
+        ColorVertex {
+            position: [x + width / 2.0, y - height, z],
+            color,
+        },
+        ColorVertex {
+            position: [x - width / 2.0, y - height, z],
+            color,
+        },
+    ]
+}
+
+#[test]
+fn depth_occlusion() {
+    let Some(mut renderer) = headless_renderer() else {
+        return;
+    };
+    let near = solid_triangle([-0.2, 0.6], 1.2, 1.0, 0.25, [1.0, 0.0, 0.0]);
+    let far = solid_triangle([0.2, 0.4], 1.2, 1.0, -0.25, [0.0, 0.0, 1.0]);
+    renderer.add_mesh(&near, &[0, 1, 2]).unwrap();
+    renderer.add_mesh(&far, &[0, 1, 2]).unwrap();
+    renderer.set_camera(&front_camera());
+    renderer.draw().unwrap();
+    assert_golden("depth_occlusion", &renderer, EDGE_TOLERANCE);
+}
+//The far triangle is drawn last and still has to end up behind the near one
+
+#[test]
+fn depth_occlusion_reverse_z() {
+    let Some(mut renderer) = headless_renderer() else {
+        return;
+    };
+    let near = solid_triangle([-0.2, 0.6], 1.2, 1.0, 0.25, [1.0, 0.0, 0.0]);
+    let far = solid_triangle([0.2, 0.4], 1.2, 1.0, -0.25, [0.0, 0.0, 1.0]);
+    renderer.add_mesh(&near, &[0, 1, 2]).unwrap();
+    renderer.add_mesh(&far, &[0, 1, 2]).unwrap();
+    let camera = Camera::new(
+        glam::Vec3::new(0.0, 0.0, 2.0),
+        Projection::perspective_reverse_z(60f32.to_radians(), 1.0, 0.1, f32::INFINITY),
+    );
+    renderer
+        .set_depth_test(DepthTest::for_projection(&camera.projection))
+        .unwrap();
+    renderer.set_camera(&camera);
+    renderer.draw().unwrap();
+    assert_golden("depth_occlusion_reverse_z", &renderer, EDGE_TOLERANCE);
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Material {
+    tint: glam::Vec3,
+    brightness: f32,
+}
+lye::uniform_block!(Material { tint, brightness });
+
+#[test]
+fn uniform_tint() {
+    let Some(mut renderer) = headless_renderer() else {
+        return;
+    };
+    renderer
+        .set_pipeline(GraphicsPipelineBuilder::new().shaders(
+            &include_bytes!(concat!(env!("OUT_DIR"), "/vertex.spv"))[..],
+            &include_bytes!(concat!(env!("OUT_DIR"), "/tinted_fragment.spv"))[..],
+        ))
+        .unwrap();
+    renderer.add_mesh(&TRIANGLE, &[0, 1, 2]).unwrap();
+    renderer.set_camera(&front_camera());
+    assert!(matches!(renderer.draw(), Err(LyeError::InvalidConfig(_))));
+    //The block has no buffer yet
+
+    let material = renderer
+        .create_uniform_buffer(
+            0,
+            0,
+            &Material {
+                tint: glam::Vec3::ONE,
+                brightness: 1.0,
+            },
+        )
+        .unwrap();
+    renderer
+        .update_uniform(
+            material,
+            &Material {
+                tint: glam::Vec3::new(1.0, 1.0, 0.0),
+                brightness: 0.5,
+            },
+        )
+        .unwrap();
+    renderer.draw().unwrap();
+    assert_golden("uniform_tint", &renderer, DEFAULT_TOLERANCE);
+}
+//shaders/tinted_fragment.glsl multiplies the vertex colors with the material, blue drops out and the rest is halved